@@ -3,13 +3,35 @@ use axum::{
     response::{IntoResponse, Response},
 };
 
-// Make our own error that wraps `anyhow::Error`.
-pub struct AppError(anyhow::Error);
+// Make our own error that wraps `anyhow::Error` with the status code of the response.
+pub struct AppError(StatusCode, anyhow::Error);
+
+impl AppError {
+    // An invalid request, like an option that does not parse, answered with 400 instead of 500.
+    pub fn bad_request<E>(err: E) -> Self
+    where
+        E: Into<anyhow::Error>,
+    {
+        Self(StatusCode::BAD_REQUEST, err.into())
+    }
+
+    // A failed conversion, answered with 415 when the input is not an image of a supported format.
+    #[must_use]
+    pub fn conversion(err: crate::Error) -> Self {
+        let status = match err {
+            crate::Error::UnsupportedInputFormat | crate::Error::InputFileHasNoExtension => {
+                StatusCode::UNSUPPORTED_MEDIA_TYPE
+            }
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        Self(status, err.into())
+    }
+}
 
 // Tell axum how to convert `AppError` into a response.
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        (StatusCode::INTERNAL_SERVER_ERROR, self.0.to_string()).into_response()
+        (self.0, self.1.to_string()).into_response()
     }
 }
 
@@ -20,6 +42,6 @@ where
     E: Into<anyhow::Error>,
 {
     fn from(err: E) -> Self {
-        Self(err.into())
+        Self(StatusCode::INTERNAL_SERVER_ERROR, err.into())
     }
 }
@@ -0,0 +1,223 @@
+//! Probes the external tools and the native libraries the built-in converters depend on.
+
+use std::{
+    fmt::{self, Display},
+    sync::OnceLock,
+};
+
+use crate::Error;
+
+/// The `gifsicle` command, used to convert GIF to GIF.
+pub const GIFSICLE: &str = "gifsicle";
+/// The library of the `magick` module, used to write JPEG and GIF and to resize PNG.
+pub const IMAGEMAGICK: &str = "imagemagick";
+/// The libwebp library, used to write WebP.
+pub const LIBWEBP: &str = "libwebp";
+/// The libheif delegate of `imagemagick`, used to decode AVIF.
+pub const LIBHEIF: &str = "libheif";
+
+/// A tool or a library and whether it can be used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capability {
+    /// One of [`GIFSICLE`], [`IMAGEMAGICK`], [`LIBWEBP`] or [`LIBHEIF`].
+    pub name: &'static str,
+    /// The version, or why the capability is unavailable.
+    pub version: Result<String, String>,
+    /// The optional features, e.g. the delegate libraries of `imagemagick`.
+    pub features: Vec<String>,
+}
+
+impl Capability {
+    #[must_use]
+    pub fn is_available(&self) -> bool {
+        self.version.is_ok()
+    }
+}
+
+impl Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.version {
+            Ok(version) => write!(f, "{}: {version}", self.name)?,
+            Err(reason) => write!(f, "{}: unavailable ({reason})", self.name)?,
+        }
+        if !self.features.is_empty() {
+            write!(f, " [{}]", self.features.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+/// The probed capabilities, see [`capabilities`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    /// `native` with the `native` feature, `pure-rust` without it.
+    pub backend: &'static str,
+    /// Empty for the pure-Rust backend, which has no external dependencies.
+    pub capabilities: Vec<Capability>,
+}
+
+impl Capabilities {
+    /// Runs the external tools and queries the linked libraries for their versions.
+    #[must_use]
+    pub fn probe() -> Self {
+        #[cfg(feature = "native")]
+        {
+            use crate::utils::{gifsicle, libwebp, magick};
+
+            let delegates = magick::delegates();
+            let heif = delegates.iter().any(|delegate| delegate == "heic");
+            Capabilities {
+                backend: "native",
+                capabilities: vec![
+                    Capability {
+                        name: IMAGEMAGICK,
+                        version: magick::version()
+                            .ok_or_else(|| "the version cannot be read".to_string()),
+                        features: delegates,
+                    },
+                    Capability {
+                        name: LIBHEIF,
+                        version: if heif {
+                            Ok("imagemagick delegate".to_string())
+                        } else {
+                            Err("not a delegate of imagemagick".to_string())
+                        },
+                        features: Vec::new(),
+                    },
+                    Capability {
+                        name: LIBWEBP,
+                        version: Ok(libwebp::version()),
+                        features: Vec::new(),
+                    },
+                    Capability {
+                        name: GIFSICLE,
+                        version: gifsicle::version().map_err(|error| error.to_string()),
+                        features: Vec::new(),
+                    },
+                ],
+            }
+        }
+        #[cfg(not(feature = "native"))]
+        {
+            Capabilities {
+                backend: "pure-rust",
+                capabilities: Vec::new(),
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&Capability> {
+        self.capabilities
+            .iter()
+            .find(|capability| capability.name == name)
+    }
+
+    /// Whether every capability is available.
+    #[must_use]
+    pub fn is_complete(&self) -> bool {
+        self.capabilities.iter().all(Capability::is_available)
+    }
+
+    /// # Errors
+    ///
+    /// Returns [`Error::BackendUnavailable`] for the first of the `names` that is not available.
+    ///
+    pub fn require(&self, names: &[&str]) -> Result<(), Error> {
+        for name in names {
+            match self.get(name).map(|capability| &capability.version) {
+                Some(Ok(_)) => {}
+                Some(Err(reason)) => {
+                    return Err(Error::BackendUnavailable(
+                        (*name).to_string(),
+                        reason.clone(),
+                    ))
+                }
+                None => {
+                    return Err(Error::BackendUnavailable(
+                        (*name).to_string(),
+                        format!("not part of the {} backend", self.backend),
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "backend: {}", self.backend)?;
+        for capability in &self.capabilities {
+            write!(f, "\n{capability}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The capabilities probed on the first call, used to refuse conversions whose backend is unavailable.
+pub fn capabilities() -> &'static Capabilities {
+    static CAPABILITIES: OnceLock<Capabilities> = OnceLock::new();
+    CAPABILITIES.get_or_init(Capabilities::probe)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn require_unavailable() {
+        use super::*;
+
+        let capabilities = Capabilities {
+            backend: "native",
+            capabilities: vec![
+                Capability {
+                    name: LIBWEBP,
+                    version: Ok("1.4.0".to_string()),
+                    features: Vec::new(),
+                },
+                Capability {
+                    name: GIFSICLE,
+                    version: Err("Io(No such file or directory (os error 2))".to_string()),
+                    features: Vec::new(),
+                },
+            ],
+        };
+
+        assert!(capabilities.require(&[LIBWEBP]).is_ok());
+        assert!(matches!(
+            capabilities.require(&[LIBWEBP, GIFSICLE]),
+            Err(Error::BackendUnavailable(name, _)) if name == GIFSICLE
+        ));
+        assert!(matches!(
+            capabilities.require(&[IMAGEMAGICK]),
+            Err(Error::BackendUnavailable(name, _)) if name == IMAGEMAGICK
+        ));
+        assert!(!capabilities.is_complete());
+    }
+
+    #[test]
+    fn display() {
+        use super::*;
+
+        let capabilities = Capabilities {
+            backend: "native",
+            capabilities: vec![
+                Capability {
+                    name: IMAGEMAGICK,
+                    version: Ok("7.1.1-38".to_string()),
+                    features: vec!["jpeg".to_string(), "png".to_string()],
+                },
+                Capability {
+                    name: GIFSICLE,
+                    version: Err("Exit(1)".to_string()),
+                    features: Vec::new(),
+                },
+            ],
+        };
+
+        assert_eq!(
+            capabilities.to_string(),
+            "backend: native\nimagemagick: 7.1.1-38 [jpeg png]\ngifsicle: unavailable (Exit(1))"
+        );
+    }
+}
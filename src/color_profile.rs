@@ -0,0 +1,129 @@
+//! The color profile the pixels are converted to, see [`ColorProfile`].
+//!
+//! The pixels of inputs with an embedded ICC profile are converted from it, inputs without one are sRGB.
+//! The conversion itself is done by [`utils::icc`](crate::utils::icc) and by the `magick` backend.
+
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ParseError {
+    #[error("Unknown color profile: {0}, expected one of srgb, display_p3, adobe_rgb")]
+    ColorProfile(String),
+}
+
+/// 2024-01-01 00:00:00, as stored in the header of an ICC profile.
+const CREATION_DATE: [u8; 12] = [0x07, 0xE8, 0, 1, 0, 1, 0, 0, 0, 0, 0, 0];
+
+/// The color profile of the output pixels, also the profile embedded in the output when one is written.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(try_from = "String")
+)]
+pub enum ColorProfile {
+    /// The profile assumed by browsers for images without one.
+    #[default]
+    Srgb,
+    /// The wide gamut of Apple displays and iPhone photos.
+    DisplayP3,
+    /// The wide gamut of print workflows.
+    AdobeRgb,
+}
+
+impl FromStr for ColorProfile {
+    type Err = ParseError;
+
+    /// Parses the snake case name, dashes are accepted instead of underscores.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('-', "_").as_str() {
+            "srgb" => Ok(ColorProfile::Srgb),
+            "display_p3" => Ok(ColorProfile::DisplayP3),
+            "adobe_rgb" => Ok(ColorProfile::AdobeRgb),
+            _ => Err(ParseError::ColorProfile(s.to_string())),
+        }
+    }
+}
+
+try_from_string!(ColorProfile);
+
+impl Display for ColorProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ColorProfile::Srgb => "srgb",
+            ColorProfile::DisplayP3 => "display_p3",
+            ColorProfile::AdobeRgb => "adobe_rgb",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl ColorProfile {
+    pub(crate) fn profile(self) -> moxcms::ColorProfile {
+        match self {
+            ColorProfile::Srgb => moxcms::ColorProfile::new_srgb(),
+            ColorProfile::DisplayP3 => moxcms::ColorProfile::new_display_p3(),
+            ColorProfile::AdobeRgb => moxcms::ColorProfile::new_adobe_rgb(),
+        }
+    }
+
+    /// The ICC profile, as embedded in the output.
+    #[must_use]
+    pub fn icc(self) -> Vec<u8> {
+        let mut icc = self
+            .profile()
+            .encode()
+            .unwrap_or_else(|error| unreachable!("the built-in profiles are encodable: {error}"));
+        // The encoder writes the current date, a fixed one gives the same output for the same conversion.
+        icc[24..36].copy_from_slice(&CREATION_DATE);
+        icc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn color_profile_from_str() {
+        use super::*;
+
+        assert_eq!("srgb".parse(), Ok(ColorProfile::Srgb));
+        assert_eq!("Display-P3".parse(), Ok(ColorProfile::DisplayP3));
+        assert_eq!("adobe_rgb".parse(), Ok(ColorProfile::AdobeRgb));
+        assert_eq!(
+            "p3".parse::<ColorProfile>(),
+            Err(ParseError::ColorProfile("p3".to_string()))
+        );
+        for profile in [
+            ColorProfile::Srgb,
+            ColorProfile::DisplayP3,
+            ColorProfile::AdobeRgb,
+        ] {
+            assert_eq!(profile.to_string().parse(), Ok(profile));
+        }
+    }
+
+    #[test]
+    fn icc() {
+        use super::*;
+
+        for profile in [
+            ColorProfile::Srgb,
+            ColorProfile::DisplayP3,
+            ColorProfile::AdobeRgb,
+        ] {
+            let icc = profile.icc();
+            assert_eq!(&icc[36..40], b"acsp");
+            assert_eq!(&icc[24..36], CREATION_DATE);
+            assert_eq!(
+                moxcms::ColorProfile::new_from_slice(&icc)
+                    .unwrap()
+                    .color_space,
+                moxcms::DataColorSpace::Rgb
+            );
+        }
+    }
+}
@@ -1,36 +1,59 @@
 use crate::app_error::AppError;
-use crate::{convert, ConfigBuilder};
-use axum::{body::Body, http::StatusCode, response::Response, routing::post, Json, Router};
+use crate::{Config, EncoderOptions, EncoderParams, Options, Registry};
+use anyhow::anyhow;
+use axum::{
+    body::Body,
+    extract::{rejection::JsonRejection, State},
+    http::StatusCode,
+    response::Response,
+    routing::post,
+    Json, Router,
+};
 use serde::Deserialize;
+use std::sync::Arc;
 
 #[derive(Deserialize, Debug)]
 struct Command {
     pub input_path: String,
     pub output_path: String,
-    pub width: Option<u32>,
-    pub height: Option<u32>,
-    pub quality: Option<u32>,
+    #[serde(flatten)]
+    pub options: Options,
+    #[serde(flatten)]
+    pub encoder: EncoderParams,
 }
 
-async fn convert_method(Json(payload): Json<Command>) -> Result<Response, AppError> {
-    convert(
-        &ConfigBuilder::default()
-            .input_path(&payload.input_path)
-            .output_path(&payload.output_path)
-            .width(payload.width)
-            .height(payload.height)
-            .quality(payload.quality)
-            .build()
-            .unwrap(),
-    )?;
-    let response = Response::builder()
-        .status(StatusCode::OK)
-        .body(Body::empty())?;
-    Ok(response)
+async fn convert_method(
+    State(registry): State<Arc<Registry>>,
+    payload: Result<Json<Command>, JsonRejection>,
+) -> Result<Response, AppError> {
+    let Json(payload) =
+        payload.map_err(|rejection| AppError::bad_request(anyhow!(rejection.body_text())))?;
+    let report = registry
+        .convert(&Config {
+            input_path: payload.input_path.into(),
+            output_path: payload.output_path.into(),
+            options: Options {
+                encoder: EncoderOptions::from(&payload.encoder),
+                ..payload.options
+            },
+        })
+        .map_err(AppError::conversion)?;
+    let mut response = Response::builder().status(StatusCode::OK);
+    for (name, value) in report.headers() {
+        response = response.header(name, value);
+    }
+    Ok(response.body(Body::empty())?)
 }
 
 pub fn app() -> Router {
-    Router::new().route("/", post(convert_method))
+    app_with_registry(Arc::new(Registry::default()))
+}
+
+/// Creates the service that converts images with the converters of the given registry.
+pub fn app_with_registry(registry: Arc<Registry>) -> Router {
+    Router::new()
+        .route("/", post(convert_method))
+        .with_state(registry)
 }
 
 mod tests {
@@ -52,7 +75,9 @@ mod tests {
         use super::*;
         use axum_test::TestServer;
 
-        let app = Router::new().route("/", post(convert_method));
+        let app = Router::new()
+            .route("/", post(convert_method))
+            .with_state(Arc::new(Registry::default()));
         let server = TestServer::new(app).unwrap();
 
         let response = server
@@ -68,12 +93,149 @@ mod tests {
         assert_eq!(response.status_code(), StatusCode::OK);
     }
 
+    #[tokio::test]
+    async fn test_convert_encoder_params() {
+        use super::*;
+        use axum_test::TestServer;
+
+        let server = TestServer::new(app()).unwrap();
+
+        let response = server
+            .post("/")
+            .json(&serde_json::json!({
+                "input_path": "tests/files/issue-159.png",
+                "output_path": "target/command_server_interlaced.png",
+                "png_level": 1,
+                "png_interlace": true,
+            }))
+            .await;
+
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let contents = std::fs::read("target/command_server_interlaced.png").unwrap();
+        // The interlace method is the last byte of the IHDR chunk.
+        assert_eq!(contents[28], 1);
+    }
+
+    #[tokio::test]
+    async fn test_convert_max_bytes() {
+        use super::*;
+        use axum_test::TestServer;
+
+        let server = TestServer::new(app()).unwrap();
+
+        let response = server
+            .post("/")
+            .json(&serde_json::json!({
+                "input_path": "tests/files/issue-159.png",
+                "output_path": "target/command_server_max_bytes.avif",
+                "width": 100,
+                "avif_speed": 10,
+                "max_bytes": 6000,
+            }))
+            .await;
+
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let metadata = std::fs::metadata("target/command_server_max_bytes.avif").unwrap();
+        assert!(metadata.len() <= 6000);
+    }
+
+    #[tokio::test]
+    async fn test_convert_auto() {
+        use super::*;
+        use crate::report::FORMAT_HEADER;
+        use axum_test::TestServer;
+
+        let server = TestServer::new(app()).unwrap();
+
+        let response = server
+            .post("/")
+            .json(&serde_json::json!({
+                "input_path": "tests/files/issue-159.png",
+                "output_path": "target/command_server_auto.auto",
+                "width": 100,
+                "avif_speed": 10,
+            }))
+            .await;
+
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let format = response.header(FORMAT_HEADER);
+        let output_path = format!("target/command_server_auto.{}", format.to_str().unwrap());
+        assert!(std::path::Path::new(&output_path).exists());
+    }
+
+    #[tokio::test]
+    async fn test_convert_metadata() {
+        use super::*;
+        use crate::utils::{
+            container::{jpeg, png, Chunks},
+            exif::tests::{sample, LATITUDE},
+        };
+        use crate::ColorProfile;
+        use axum_test::TestServer;
+
+        let server = TestServer::new(app()).unwrap();
+        let input = jpeg::write(
+            include_bytes!("../tests/files/orientation_test.jpg"),
+            &Chunks {
+                icc: Some(ColorProfile::DisplayP3.icc()),
+                exif: Some(sample()),
+                ..Chunks::default()
+            },
+        )
+        .unwrap();
+        std::fs::create_dir_all("target").unwrap();
+        std::fs::write("target/command_server_metadata.jpg", input).unwrap();
+
+        let response = server
+            .post("/")
+            .json(&serde_json::json!({
+                "input_path": "target/command_server_metadata.jpg",
+                "output_path": "target/command_server_metadata.png",
+                "width": 100,
+                "metadata": "strip_gps",
+            }))
+            .await;
+
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let output = std::fs::read("target/command_server_metadata.png").unwrap();
+        let chunks = png::read(&output).unwrap();
+        assert_eq!(chunks.icc, Some(ColorProfile::Srgb.icc()));
+        assert!(chunks.exif.is_some());
+        assert!(!output
+            .windows(LATITUDE.len())
+            .any(|bytes| bytes == LATITUDE));
+    }
+
+    #[tokio::test]
+    async fn test_convert_unknown_resize_mode() {
+        use super::*;
+        use axum_test::TestServer;
+
+        let server = TestServer::new(app()).unwrap();
+
+        let response = server
+            .post("/")
+            .json(&serde_json::json!({
+                "input_path": "tests/files/command_server_test1.jpg",
+                "output_path": "target/command_server_unknown_resize_mode.webp",
+                "resize_mode": "stretch",
+            }))
+            .await;
+
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        assert!(response.text().contains(
+            "Unknown resize mode: stretch, expected one of fit, inside, cover, exact, pad"
+        ));
+    }
+
     #[tokio::test]
     async fn test_convert_nested_dir() {
         use super::*;
         use axum_test::TestServer;
 
-        let app = Router::new().route("/", post(convert_method));
+        let app = Router::new()
+            .route("/", post(convert_method))
+            .with_state(Arc::new(Registry::default()));
         let server = TestServer::new(app).unwrap();
 
         let response = server
@@ -94,7 +256,9 @@ mod tests {
         use super::*;
         use axum_test::TestServer;
 
-        let app = Router::new().route("/", post(convert_method));
+        let app = Router::new()
+            .route("/", post(convert_method))
+            .with_state(Arc::new(Registry::default()));
         let server = TestServer::new(app).unwrap();
 
         let response = server
@@ -108,4 +272,67 @@ mod tests {
 
         assert_eq!(response.status_code(), StatusCode::OK);
     }
+
+    #[tokio::test]
+    async fn test_convert_hostile_file_names() {
+        use super::*;
+        use crate::utils::HOSTILE_FILE_NAMES;
+        use axum_test::TestServer;
+        use std::path::Path;
+
+        let server = TestServer::new(app()).unwrap();
+        let dir = Path::new("target/hostile/command_server");
+        std::fs::create_dir_all(dir).unwrap();
+
+        for name in HOSTILE_FILE_NAMES {
+            let input = dir.join(name);
+            std::fs::copy("tests/files/gif2gif_test1.gif", &input).unwrap();
+
+            for extension in ["gif", "webp"] {
+                let output = dir.join(format!("out {name}")).with_extension(extension);
+                let response = server
+                    .post("/")
+                    .json(&serde_json::json!({
+                        "input_path": input,
+                        "output_path": output,
+                        "width": 100,
+                    }))
+                    .await;
+
+                assert_eq!(response.status_code(), StatusCode::OK, "{name}");
+                assert!(output.exists(), "{name}");
+            }
+        }
+        assert!(!Path::new("pwned").exists());
+        assert!(!Path::new("pwned.gif").exists());
+    }
+
+    #[tokio::test]
+    async fn test_custom_converter() {
+        use super::*;
+        use crate::{extensions::JPEG, Error, Options};
+        use axum_test::TestServer;
+
+        let mut registry = Registry::default();
+        registry.register(
+            JPEG,
+            "bmp",
+            |_: &[u8], _: &Options| -> Result<Vec<u8>, Error> { Ok(b"BM".to_vec()) },
+        );
+        let server = TestServer::new(app_with_registry(Arc::new(registry))).unwrap();
+
+        let response = server
+            .post("/")
+            .json(&serde_json::json!({
+                "input_path": "tests/files/command_server_test1.jpg",
+                "output_path": "target/command_server_test1.bmp",
+            }))
+            .await;
+
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(
+            std::fs::read("target/command_server_test1.bmp").unwrap(),
+            b"BM"
+        );
+    }
 }
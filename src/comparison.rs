@@ -0,0 +1,328 @@
+//! Quality metrics of an image compared with a reference, see [`Registry::compare`](crate::Registry::compare).
+
+use std::fmt::{self, Display};
+
+use image::{Rgb, RgbImage, RgbaImage};
+
+use crate::{utils::ssim, Error};
+
+/// A difference in CIELAB that is just noticeable.
+const JND: f32 = 2.3;
+/// The local contrast of the lightness, in L* units, that halves the visibility of a difference.
+const MASKING: f32 = 10.0;
+/// The colors of the heatmap at increasing distances.
+const HEAT: [(f32, [f32; 3]); 5] = [
+    (0.0, [0.0, 0.0, 0.0]),
+    (0.5, [0.0, 0.0, 255.0]),
+    (1.0, [0.0, 255.0, 0.0]),
+    (1.5, [255.0, 255.0, 0.0]),
+    (2.0, [255.0, 0.0, 0.0]),
+];
+
+/// The format, the dimensions and the size of a compared image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageInfo {
+    pub format: String,
+    pub dimensions: (u32, u32),
+    /// The size of the encoded image in bytes.
+    pub size: usize,
+}
+
+impl Display for ImageInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (width, height) = self.dimensions;
+        write!(f, "{} {width}x{height} {} bytes", self.format, self.size)
+    }
+}
+
+/// How much an image differs from its reference.
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    /// The peak signal-to-noise ratio of the premultiplied RGBA samples in dB, infinite for identical images.
+    pub psnr: f64,
+    /// See [`ssim::ssim`].
+    pub ssim: f64,
+    /// See [`ssim::dssim`].
+    pub dssim: f64,
+    /// A butteraugli-like distance: the largest CIELAB difference of the images blurred by the visual acuity,
+    /// masked by the local contrast of the reference and measured in just noticeable differences,
+    /// so about 1 is barely visible. An approximation, not the butteraugli score.
+    pub distance: f64,
+    width: u32,
+    height: u32,
+    /// The distance of every pixel, row by row.
+    distances: Vec<f32>,
+}
+
+impl Metrics {
+    /// The distance of every pixel as a color: black for none, then blue, green and yellow,
+    /// and red from 2 just noticeable differences.
+    #[must_use]
+    pub fn heatmap(&self) -> RgbImage {
+        RgbImage::from_fn(self.width, self.height, |x, y| {
+            heat(self.distances[(y * self.width + x) as usize])
+        })
+    }
+}
+
+impl Display for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "psnr: {:.2} dB", self.psnr)?;
+        writeln!(f, "ssim: {:.6}", self.ssim)?;
+        writeln!(f, "dssim: {:.6}", self.dssim)?;
+        write!(f, "distance: {:.3}", self.distance)
+    }
+}
+
+/// The result of [`Registry::compare`](crate::Registry::compare).
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    pub reference: ImageInfo,
+    pub image: ImageInfo,
+    pub metrics: Metrics,
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "reference: {}", self.reference)?;
+        writeln!(f, "image: {}", self.image)?;
+        write!(f, "{}", self.metrics)
+    }
+}
+
+/// Measures how much the decoded `image` differs from the decoded `reference`.
+/// The alpha of the reference is ignored when the image has none, like by [`ssim::ssim`].
+///
+/// # Errors
+///
+/// Returns an error if the images have different dimensions.
+///
+pub fn metrics(reference: &RgbaImage, image: &RgbaImage) -> Result<Metrics, Error> {
+    if reference.dimensions() != image.dimensions() {
+        return Err(Error::DimensionsMismatch(
+            reference.dimensions(),
+            image.dimensions(),
+        ));
+    }
+    let ssim = ssim::ssim(reference, image).map_err(Error::Metrics)?;
+    let reference = ssim::comparable(reference, image);
+    let distances = distances(&reference, image);
+    Ok(Metrics {
+        psnr: psnr(&reference, image),
+        ssim,
+        dssim: 1.0 / ssim.max(f64::EPSILON) - 1.0,
+        distance: f64::from(distances.iter().copied().fold(0.0, f32::max)),
+        width: image.width(),
+        height: image.height(),
+        distances,
+    })
+}
+
+fn psnr(reference: &RgbaImage, image: &RgbaImage) -> f64 {
+    let premultiplied = |pixel: &image::Rgba<u8>| {
+        let alpha = f64::from(pixel[3]) / 255.0;
+        [
+            f64::from(pixel[0]) * alpha,
+            f64::from(pixel[1]) * alpha,
+            f64::from(pixel[2]) * alpha,
+            f64::from(pixel[3]),
+        ]
+    };
+    let sum: f64 = reference
+        .pixels()
+        .zip(image.pixels())
+        .map(|(reference, image)| {
+            premultiplied(reference)
+                .iter()
+                .zip(premultiplied(image))
+                .map(|(reference, image)| (reference - image).powi(2))
+                .sum::<f64>()
+        })
+        .sum();
+    #[allow(clippy::cast_precision_loss)]
+    let samples = (reference.len().max(1)) as f64;
+    let mean_squared_error = sum / samples;
+    if mean_squared_error == 0.0 {
+        f64::INFINITY
+    } else {
+        10.0 * (255.0 * 255.0 / mean_squared_error).log10()
+    }
+}
+
+/// The butteraugli-like distance of every pixel, the largest over the backgrounds of transparent images.
+fn distances(reference: &RgbaImage, image: &RgbaImage) -> Vec<f32> {
+    let width = reference.width() as usize;
+    let mut distances = vec![0.0_f32; width * reference.height() as usize];
+    for &background in ssim::backgrounds(reference, image) {
+        let reference_lab = lab(reference, background);
+        let lightness = &reference_lab[0];
+        let mean = ssim::gaussian_blur(lightness, width);
+        let mean_squared =
+            ssim::gaussian_blur(&lightness.iter().map(|l| l * l).collect::<Vec<_>>(), width);
+        let reference_lab = reference_lab.map(|plane| ssim::gaussian_blur(&plane, width));
+        let image_lab = lab(image, background).map(|plane| ssim::gaussian_blur(&plane, width));
+
+        for (index, distance) in distances.iter_mut().enumerate() {
+            let contrast = (mean_squared[index] - mean[index] * mean[index])
+                .max(0.0)
+                .sqrt();
+            let difference = (0..3)
+                .map(|channel| (reference_lab[channel][index] - image_lab[channel][index]).powi(2))
+                .sum::<f32>()
+                .sqrt();
+            *distance = distance.max(difference / JND / (1.0 + contrast / MASKING));
+        }
+    }
+    distances
+}
+
+/// The CIELAB planes of the pixels blended over the gray `background` in linear light, with the D65 white point.
+fn lab(image: &RgbaImage, background: f32) -> [Vec<f32>; 3] {
+    let mut planes = [
+        Vec::with_capacity(image.len() / 4),
+        Vec::with_capacity(image.len() / 4),
+        Vec::with_capacity(image.len() / 4),
+    ];
+    for pixel in image.pixels() {
+        let alpha = f32::from(pixel[3]) / 255.0;
+        let [red, green, blue] = [pixel[0], pixel[1], pixel[2]]
+            .map(|channel| linear(channel) * alpha + background * (1.0 - alpha));
+        let x = (0.4124 * red + 0.3576 * green + 0.1805 * blue) / 0.950_47;
+        let y = 0.2126 * red + 0.7152 * green + 0.0722 * blue;
+        let z = (0.0193 * red + 0.1192 * green + 0.9505 * blue) / 1.088_83;
+        let [x, y, z] = [x, y, z].map(lab_f);
+        planes[0].push(116.0 * y - 16.0);
+        planes[1].push(500.0 * (x - y));
+        planes[2].push(200.0 * (y - z));
+    }
+    planes
+}
+
+fn linear(channel: u8) -> f32 {
+    let channel = f32::from(channel) / 255.0;
+    if channel <= 0.04045 {
+        channel / 12.92
+    } else {
+        ((channel + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn lab_f(t: f32) -> f32 {
+    const DELTA: f32 = 6.0 / 29.0;
+    if t > DELTA * DELTA * DELTA {
+        t.cbrt()
+    } else {
+        t / (3.0 * DELTA * DELTA) + 4.0 / 29.0
+    }
+}
+
+fn heat(distance: f32) -> Rgb<u8> {
+    let color = HEAT.windows(2).find(|stops| distance < stops[1].0).map_or(
+        HEAT[HEAT.len() - 1].1,
+        |stops| {
+            let ((start, from), (end, to)) = (stops[0], stops[1]);
+            let t = ((distance - start) / (end - start)).clamp(0.0, 1.0);
+            [0, 1, 2].map(|channel| from[channel] + (to[channel] - from[channel]) * t)
+        },
+    );
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    Rgb(color.map(|channel| channel.round() as u8))
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+
+    fn gradient() -> RgbaImage {
+        #[allow(clippy::cast_possible_truncation)]
+        RgbaImage::from_fn(64, 48, |x, y| {
+            Rgba([(x * 4) as u8, (y * 5) as u8, ((x + y) * 2) as u8, 255])
+        })
+    }
+
+    #[test]
+    fn metrics_identical() {
+        use super::*;
+
+        let image = gradient();
+        let metrics = metrics(&image, &image).unwrap();
+
+        assert!(metrics.psnr.is_infinite());
+        assert!((metrics.ssim - 1.0).abs() < 1e-6);
+        assert!(metrics.dssim.abs() < 1e-6);
+        assert!(metrics.distance.abs() < 1e-6);
+        assert!(metrics
+            .heatmap()
+            .pixels()
+            .all(|pixel| *pixel == Rgb([0, 0, 0])));
+    }
+
+    #[test]
+    fn metrics_different() {
+        use super::*;
+
+        let reference = gradient();
+        let mut image = reference.clone();
+        for pixel in image.pixels_mut().take(64 * 8) {
+            pixel.0[0] = 255 - pixel.0[0];
+        }
+        let metrics = metrics(&reference, &image).unwrap();
+
+        assert!(metrics.psnr.is_finite());
+        assert!(metrics.ssim < 1.0);
+        assert!(metrics.dssim > 0.0);
+        assert!(metrics.distance > 2.0);
+        let heatmap = metrics.heatmap();
+        assert_eq!(heatmap.dimensions(), (64, 48));
+        assert_eq!(*heatmap.get_pixel(0, 0), Rgb([255, 0, 0]));
+        assert_eq!(*heatmap.get_pixel(0, 47), Rgb([0, 0, 0]));
+    }
+
+    #[test]
+    fn heat_colors() {
+        use super::*;
+
+        assert_eq!(heat(0.0), Rgb([0, 0, 0]));
+        assert_eq!(heat(0.25), Rgb([0, 0, 128]));
+        assert_eq!(heat(1.0), Rgb([0, 255, 0]));
+        assert_eq!(heat(5.0), Rgb([255, 0, 0]));
+    }
+
+    #[test]
+    fn display() {
+        use super::*;
+
+        let image = gradient();
+        let comparison = Comparison {
+            reference: ImageInfo {
+                format: "png".to_string(),
+                dimensions: (64, 48),
+                size: 1234,
+            },
+            image: ImageInfo {
+                format: "jpeg".to_string(),
+                dimensions: (64, 48),
+                size: 567,
+            },
+            metrics: metrics(&image, &image).unwrap(),
+        };
+
+        assert_eq!(
+            comparison.to_string(),
+            "reference: png 64x48 1234 bytes\n\
+             image: jpeg 64x48 567 bytes\n\
+             psnr: inf dB\n\
+             ssim: 1.000000\n\
+             dssim: 0.000000\n\
+             distance: 0.000"
+        );
+    }
+
+    #[test]
+    #[should_panic = "DimensionsMismatch((64, 48), (48, 64))"]
+    fn metrics_dimensions_mismatch_panic() {
+        use super::*;
+
+        metrics(&gradient(), &RgbaImage::new(48, 64)).unwrap();
+    }
+}
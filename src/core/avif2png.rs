@@ -0,0 +1,65 @@
+use crate::{
+    extensions::PNG,
+    report::Trace,
+    utils::raster::{self, Filter},
+    Dimensions, PathAccessor, Quality,
+};
+
+/// Converts the input file to the output file like [`convert_bytes`].
+///
+/// # Errors
+///
+/// Returns an error if the conversion fails.
+///
+pub fn convert<T>(config: &T) -> std::result::Result<(), raster::Error>
+where
+    T: PathAccessor + Dimensions + Quality,
+{
+    crate::utils::optimize_file(config, |input| {
+        convert_bytes(input, config, &mut Trace::default())
+    })
+}
+
+/// Decodes AVIF with the libheif delegate of the `magick` module, the pure-Rust backend has no AVIF decoder.
+///
+/// # Errors
+///
+/// Returns an error if the conversion fails.
+///
+pub fn convert_bytes<T>(
+    input: &[u8],
+    config: &T,
+    trace: &mut Trace,
+) -> std::result::Result<Vec<u8>, raster::Error>
+where
+    T: Dimensions + Quality,
+{
+    trace.step(raster::BACKEND, Some(Filter::Lanczos.name()), || {
+        raster::optimize_bytes(input, PNG, config, Filter::Lanczos)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    #[cfg(feature = "native")]
+    fn avif2png() {
+        use super::*;
+        use crate::{core::png2avif, Options};
+
+        let avif = png2avif::convert_bytes(
+            include_bytes!("../../tests/files/png2avif_test2.png"),
+            &Options {
+                width: Some(100),
+                ..Options::default()
+            },
+            &mut Trace::default(),
+        )
+        .unwrap();
+
+        let contents = convert_bytes(&avif, &Options::default(), &mut Trace::default()).unwrap();
+
+        let image = image::load_from_memory(&contents).unwrap();
+        assert_eq!(image.width(), 100);
+    }
+}
@@ -1,8 +1,11 @@
-use crate::{
-    utils::gifsicle::{self, Error},
-    Dimensions, PathAccessor, Quality,
-};
+#[cfg(not(feature = "native"))]
+pub use crate::utils::gif::Error;
+#[cfg(feature = "native")]
+pub use crate::utils::gifsicle::Error;
+use crate::{report::Trace, Dimensions, PathAccessor, Quality};
 
+/// Converts the input file to the output file like [`convert_bytes`].
+///
 /// # Errors
 ///
 /// Returns an error if the conversion fails.
@@ -11,7 +14,35 @@ pub fn convert<T>(config: &T) -> std::result::Result<(), Error>
 where
     T: PathAccessor + Dimensions + Quality,
 {
-    gifsicle::optimize(config)
+    crate::utils::optimize_file(config, |input| {
+        convert_bytes(input, config, &mut Trace::default())
+    })
+}
+
+/// # Errors
+///
+/// Returns an error if the conversion fails.
+///
+pub fn convert_bytes<T>(
+    input: &[u8],
+    config: &T,
+    trace: &mut Trace,
+) -> std::result::Result<Vec<u8>, Error>
+where
+    T: Dimensions + Quality,
+{
+    #[cfg(feature = "native")]
+    {
+        trace.step("gifsicle", Some("mix"), || {
+            crate::utils::gifsicle::optimize_bytes(input, config)
+        })
+    }
+    #[cfg(not(feature = "native"))]
+    {
+        trace.step("gif", Some("lanczos3"), || {
+            crate::utils::gif::optimize_bytes(input, config)
+        })
+    }
 }
 
 #[cfg(test)]
@@ -32,7 +63,7 @@ mod tests {
     }
 
     #[test]
-    #[should_panic = "Exit(1)"]
+    #[should_panic = "NotFound"]
     fn gif2gif_panic() {
         use super::*;
 
@@ -1,46 +1,40 @@
-use thiserror::Error;
-
 use crate::{
-    utils::{gifsicle, webp},
-    Config, Dimensions, PathAccessor,
+    report::Trace,
+    utils::webp::{self, Error},
+    Dimensions, PathAccessor, Quality,
 };
 
-use super::PathIO;
-
-#[derive(Debug, Error)]
-pub enum Error {
-    #[error("Gifsicle({0})")]
-    Gifsicle(gifsicle::Error),
-    #[error("Io({0})")]
-    Io(std::io::Error),
-}
-
+/// Converts the input file to the output file like [`convert_bytes`].
+///
 /// # Errors
 ///
-/// Returns an error if the gifsicle command fails or if the input file does not exist.
+/// Returns an error if the GIF cannot be read or the encoding fails.
 ///
 pub fn convert<T>(config: &T) -> Result<(), Error>
 where
-    T: PathAccessor + Dimensions,
+    T: PathAccessor + Dimensions + Quality,
 {
-    let output_path = config.output_path();
-    let step1_output_path = &output_path.with_extension("step1");
-    let gifsicle_config = Config::new(
-        config.input_path(),
-        step1_output_path,
-        config.width(),
-        config.height(),
-    );
+    crate::utils::optimize_file(config, |input| {
+        convert_bytes(input, config, &mut Trace::default())
+    })
+}
 
-    gifsicle::optimize(&gifsicle_config).map_err(Error::Gifsicle)?;
-    let webp_config = PathIO::new(step1_output_path, config.output_path());
-    webp::optimize_gif(&webp_config).map_err(Error::Io)?;
-    std::fs::remove_file(step1_output_path).map_err(Error::Io)?;
-    Ok(())
+/// # Errors
+///
+/// Returns an error if the GIF cannot be decoded or the encoding fails.
+///
+pub fn convert_bytes<T>(input: &[u8], config: &T, trace: &mut Trace) -> Result<Vec<u8>, Error>
+where
+    T: Dimensions + Quality,
+{
+    trace.step(webp::BACKEND, Some("lanczos3"), || {
+        webp::optimize_animation_bytes(input, config)
+    })
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::{Config, Options};
 
     #[test]
     fn gif2webp() {
@@ -56,7 +50,25 @@ mod tests {
     }
 
     #[test]
-    #[should_panic = "Gifsicle(Exit(1))"]
+    fn gif2webp_bytes() {
+        use super::*;
+
+        let contents = convert_bytes(
+            include_bytes!("../../tests/files/gif2webp_test1.gif"),
+            &Options {
+                width: Some(100),
+                height: Some(100),
+                ..Options::default()
+            },
+            &mut Trace::default(),
+        )
+        .unwrap();
+
+        assert_eq!(&contents[8..12], b"WEBP");
+    }
+
+    #[test]
+    #[should_panic = "Io(Os { code: 2, kind: NotFound"]
     fn gif2webp_panic() {
         use super::*;
 
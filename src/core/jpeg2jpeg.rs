@@ -1,16 +1,40 @@
-use magick_rust::FilterType;
-
-use crate::{utils::magick, Dimensions, PathAccessor, Quality};
+use crate::{
+    extensions::JPEG,
+    report::Trace,
+    utils::raster::{self, Filter},
+    Dimensions, PathAccessor, Quality,
+};
 
+/// Converts the input file to the output file like [`convert_bytes`].
+///
 /// # Errors
 ///
 /// Returns an error if the conversion fails.
 ///
-pub fn convert<T>(config: &T) -> std::result::Result<(), magick::Error>
+pub fn convert<T>(config: &T) -> std::result::Result<(), raster::Error>
 where
     T: PathAccessor + Dimensions + Quality,
 {
-    magick::optimize(config, Some(FilterType::Lanczos))
+    crate::utils::optimize_file(config, |input| {
+        convert_bytes(input, config, &mut Trace::default())
+    })
+}
+
+/// # Errors
+///
+/// Returns an error if the conversion fails.
+///
+pub fn convert_bytes<T>(
+    input: &[u8],
+    config: &T,
+    trace: &mut Trace,
+) -> std::result::Result<Vec<u8>, raster::Error>
+where
+    T: Dimensions + Quality,
+{
+    trace.step(raster::BACKEND, Some(Filter::Lanczos.name()), || {
+        raster::optimize_bytes(input, JPEG, config, Filter::Lanczos)
+    })
 }
 
 #[cfg(test)]
@@ -31,7 +55,7 @@ mod tests {
     }
 
     #[test]
-    #[should_panic = "MagickError(\"unable to open image 'tests/files/jpeg2jpeg_notexisting_test1.jpg':"]
+    #[should_panic = "Io(Os { code: 2, kind: NotFound"]
     fn jpeg2jpeg_panic() {
         use super::*;
 
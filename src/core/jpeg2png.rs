@@ -0,0 +1,36 @@
+use crate::{report::Trace, Dimensions, Quality};
+
+use super::png2png::{self, Error};
+
+/// # Errors
+///
+/// Returns an error if the conversion fails.
+///
+pub fn convert_bytes<T>(input: &[u8], config: &T, trace: &mut Trace) -> Result<Vec<u8>, Error>
+where
+    T: Dimensions + Quality,
+{
+    png2png::convert_bytes(input, config, trace)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Options;
+
+    #[test]
+    fn jpeg2png() {
+        use super::*;
+
+        let contents = convert_bytes(
+            include_bytes!("../../tests/files/jpeg2jpeg_test1.jpg"),
+            &Options {
+                width: Some(100),
+                ..Options::default()
+            },
+            &mut Trace::default(),
+        )
+        .unwrap();
+
+        assert_eq!(&contents[..4], b"\x89PNG");
+    }
+}
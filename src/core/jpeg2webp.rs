@@ -1,8 +1,11 @@
 use crate::{
+    report::Trace,
     utils::webp::{self, Error},
     Dimensions, PathAccessor, Quality,
 };
 
+/// Converts the input file to the output file like [`convert_bytes`].
+///
 /// # Errors
 ///
 /// Returns an error if the conversion fails.
@@ -11,7 +14,22 @@ pub fn convert<T>(config: &T) -> Result<(), Error>
 where
     T: PathAccessor + Dimensions + Quality,
 {
-    webp::optimize(config)
+    crate::utils::optimize_file(config, |input| {
+        convert_bytes(input, config, &mut Trace::default())
+    })
+}
+
+/// # Errors
+///
+/// Returns an error if the conversion fails.
+///
+pub fn convert_bytes<T>(input: &[u8], config: &T, trace: &mut Trace) -> Result<Vec<u8>, Error>
+where
+    T: Dimensions + Quality,
+{
+    trace.step(webp::BACKEND, Some("lanczos3"), || {
+        webp::optimize_bytes(input, config)
+    })
 }
 
 #[cfg(test)]
@@ -2,14 +2,23 @@ use std::path::PathBuf;
 
 use crate::PathAccessor;
 
+pub mod avif2png;
+pub mod gif2avif;
 pub mod gif2gif;
+pub mod gif2png;
 pub mod gif2webp;
+pub mod jpeg2avif;
 pub mod jpeg2jpeg;
+pub mod jpeg2png;
 pub mod jpeg2webp;
 pub mod png2avif;
+pub mod png2gif;
 pub mod png2jpeg;
 pub mod png2png;
 pub mod png2webp;
+pub mod webp2avif;
+pub mod webp2jpeg;
+pub mod webp2png;
 pub mod webp2webp;
 
 pub struct PathIO<'a> {
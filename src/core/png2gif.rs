@@ -0,0 +1,45 @@
+use crate::{
+    extensions::GIF,
+    report::Trace,
+    utils::raster::{self, Filter},
+    Dimensions, Quality,
+};
+
+/// # Errors
+///
+/// Returns an error if the conversion fails.
+///
+pub fn convert_bytes<T>(
+    input: &[u8],
+    config: &T,
+    trace: &mut Trace,
+) -> std::result::Result<Vec<u8>, raster::Error>
+where
+    T: Dimensions + Quality,
+{
+    trace.step(raster::BACKEND, Some(Filter::Adaptive.name()), || {
+        raster::optimize_bytes(input, GIF, config, Filter::Adaptive)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Options;
+
+    #[test]
+    fn png2gif() {
+        use super::*;
+
+        let contents = convert_bytes(
+            include_bytes!("../../tests/files/png2jpeg_test1.png"),
+            &Options {
+                width: Some(100),
+                ..Options::default()
+            },
+            &mut Trace::default(),
+        )
+        .unwrap();
+
+        assert_eq!(&contents[..6], b"GIF89a");
+    }
+}
@@ -1,49 +1,60 @@
 use thiserror::Error;
 
-use crate::utils::magick;
-use crate::{utils, Config};
+use crate::extensions::PNG;
+use crate::utils;
+use crate::utils::raster::{self, Filter};
 
-use crate::{Dimensions, PathAccessor};
-
-use super::PathIO;
+use crate::{report::Trace, Dimensions, Options, PathAccessor, Quality};
 
 #[derive(Debug, Error)]
 pub enum Error {
-    #[error("Magick({0})")]
-    Magick(magick::Error),
+    #[error("Raster({0})")]
+    Raster(raster::Error),
     #[error("Oxipng({0})")]
     Oxipng(oxipng::PngError),
     #[error("Io({0})")]
-    Io(std::io::Error),
+    Io(#[from] std::io::Error),
 }
 
+/// Converts the input file to the output file like [`convert_bytes`].
+///
 /// # Errors
 ///
 /// Returns an error if the conversion fails.
 ///
 pub fn convert<T>(config: &T) -> Result<(), Error>
 where
-    T: PathAccessor + Dimensions,
+    T: PathAccessor + Dimensions + Quality,
 {
-    let output_path = config.output_path();
-    let step1_output_path = &output_path.with_extension("step1.png");
-    let magick_config = Config::new(
-        config.input_path(),
-        step1_output_path,
-        config.width(),
-        config.height(),
-    );
-    utils::magick::optimize(&magick_config, None).map_err(Error::Magick)?;
-    let oxipng_config = PathIO::new(step1_output_path, config.output_path());
-    utils::oxipng::optimize(&oxipng_config).map_err(Error::Oxipng)?;
-    std::fs::remove_file(step1_output_path).map_err(Error::Io)?;
+    utils::optimize_file(config, |input| {
+        convert_bytes(input, config, &mut Trace::default())
+    })
+}
 
-    Ok(())
+/// # Errors
+///
+/// Returns an error if the conversion fails.
+///
+pub fn convert_bytes<T>(input: &[u8], config: &T, trace: &mut Trace) -> Result<Vec<u8>, Error>
+where
+    T: Dimensions + Quality,
+{
+    let raster_options = Options::resize_of(config);
+    let step1 = trace
+        .step(raster::BACKEND, Some(Filter::Adaptive.name()), || {
+            raster::optimize_bytes(input, PNG, &raster_options, Filter::Adaptive)
+        })
+        .map_err(Error::Raster)?;
+    trace
+        .step("oxipng", None, || {
+            utils::oxipng::optimize_bytes(&step1, config.encoder().png)
+        })
+        .map_err(Error::Oxipng)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::ConfigBuilder;
+    use crate::{Config, ConfigBuilder};
 
     #[test]
     fn png2png_test1() {
@@ -75,7 +86,24 @@ mod tests {
     }
 
     #[test]
-    #[should_panic = "Magick(MagickError(\"unable to open image 'tests/files/png2png_notexisting_test1.png':"]
+    fn png2png_bytes() {
+        use super::*;
+
+        let contents = convert_bytes(
+            include_bytes!("../../tests/files/png2png_test1.png"),
+            &Options {
+                width: Some(100),
+                ..Options::default()
+            },
+            &mut Trace::default(),
+        )
+        .unwrap();
+
+        assert_eq!(&contents[..4], b"\x89PNG");
+    }
+
+    #[test]
+    #[should_panic = "Io(Os { code: 2, kind: NotFound"]
     fn png2png_panic() {
         use super::*;
 
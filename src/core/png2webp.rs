@@ -1,8 +1,11 @@
 use crate::{
+    report::Trace,
     utils::webp::{self, Error},
     Dimensions, PathAccessor, Quality,
 };
 
+/// Converts the input file to the output file like [`convert_bytes`].
+///
 /// # Errors
 ///
 /// Returns an error if the conversion fails.
@@ -11,7 +14,22 @@ pub fn convert<T>(config: &T) -> Result<(), Error>
 where
     T: PathAccessor + Dimensions + Quality,
 {
-    webp::optimize(config)
+    crate::utils::optimize_file(config, |input| {
+        convert_bytes(input, config, &mut Trace::default())
+    })
+}
+
+/// # Errors
+///
+/// Returns an error if the conversion fails.
+///
+pub fn convert_bytes<T>(input: &[u8], config: &T, trace: &mut Trace) -> Result<Vec<u8>, Error>
+where
+    T: Dimensions + Quality,
+{
+    trace.step(webp::BACKEND, Some("lanczos3"), || {
+        webp::optimize_bytes(input, config)
+    })
 }
 
 #[cfg(test)]
@@ -30,4 +48,26 @@ mod tests {
         ))
         .unwrap();
     }
+
+    #[test]
+    fn png2webp_alpha_golden() {
+        use super::*;
+        use crate::{utils::ssim, Options};
+
+        let output = convert_bytes(
+            include_bytes!("../../tests/files/png2avif_test2.png"),
+            &Options {
+                width: Some(100),
+                ..Options::default()
+            },
+            &mut Trace::default(),
+        )
+        .unwrap();
+        let reference = image::open("tests/files/png2avif_test2_100.png")
+            .unwrap()
+            .into_rgba8();
+
+        // Lossless without a quality, the resized pixels are kept.
+        assert!(ssim::dssim(&reference, &ssim::decode(&output).unwrap()).unwrap() < 0.001);
+    }
 }
@@ -0,0 +1,51 @@
+use crate::{
+    report::Trace,
+    utils::avif::{self, Error},
+    Dimensions, PathAccessor, Quality,
+};
+
+/// Converts the input file to the output file like [`convert_bytes`].
+///
+/// # Errors
+///
+/// Returns an error if the conversion fails.
+///
+pub fn convert<T>(config: &T) -> Result<(), Error>
+where
+    T: PathAccessor + Dimensions + Quality,
+{
+    crate::utils::optimize_file(config, |input| {
+        convert_bytes(input, config, &mut Trace::default())
+    })
+}
+
+/// # Errors
+///
+/// Returns an error if the conversion fails.
+///
+pub fn convert_bytes<T>(input: &[u8], config: &T, trace: &mut Trace) -> Result<Vec<u8>, Error>
+where
+    T: Dimensions + Quality,
+{
+    trace.step("ravif", Some("lanczos3"), || {
+        avif::optimize_bytes(input, config)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Config;
+
+    #[test]
+    fn webp2avif() {
+        use super::*;
+
+        convert(&Config::new(
+            "tests/files/webp2webp_test1.webp",
+            "target/webp2avif_test1.avif",
+            Some(100),
+            None,
+        ))
+        .unwrap();
+    }
+}
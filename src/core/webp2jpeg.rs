@@ -0,0 +1,56 @@
+use crate::{
+    extensions::JPEG,
+    report::Trace,
+    utils::raster::{self, Filter},
+    Dimensions, PathAccessor, Quality,
+};
+
+/// Converts the input file to the output file like [`convert_bytes`].
+///
+/// # Errors
+///
+/// Returns an error if the conversion fails.
+///
+pub fn convert<T>(config: &T) -> std::result::Result<(), raster::Error>
+where
+    T: PathAccessor + Dimensions + Quality,
+{
+    crate::utils::optimize_file(config, |input| {
+        convert_bytes(input, config, &mut Trace::default())
+    })
+}
+
+/// # Errors
+///
+/// Returns an error if the conversion fails.
+///
+pub fn convert_bytes<T>(
+    input: &[u8],
+    config: &T,
+    trace: &mut Trace,
+) -> std::result::Result<Vec<u8>, raster::Error>
+where
+    T: Dimensions + Quality,
+{
+    trace.step(raster::BACKEND, Some(Filter::Lanczos.name()), || {
+        raster::optimize_bytes(input, JPEG, config, Filter::Lanczos)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Config;
+
+    #[test]
+    fn webp2jpeg() {
+        use super::*;
+
+        convert(&Config::new(
+            "tests/files/webp2webp_test1.webp",
+            "target/webp2jpeg_test1.jpg",
+            Some(100),
+            None,
+        ))
+        .unwrap();
+    }
+}
@@ -0,0 +1,51 @@
+use thiserror::Error;
+
+use crate::{report::Trace, utils::png, Dimensions, Quality};
+
+use super::png2png;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Image({0})")]
+    Image(image::ImageError),
+    #[error("Png2Png({0})")]
+    Png2Png(png2png::Error),
+}
+
+/// Decodes the WebP image and optimizes it like [`png2png`].
+///
+/// # Errors
+///
+/// Returns an error if the conversion fails.
+///
+pub fn convert_bytes<T>(input: &[u8], config: &T, trace: &mut Trace) -> Result<Vec<u8>, Error>
+where
+    T: Dimensions + Quality,
+{
+    let step1 = trace
+        .step("image", None, || png::decode_to_png(input))
+        .map_err(Error::Image)?;
+    png2png::convert_bytes(&step1, config, trace).map_err(Error::Png2Png)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Options;
+
+    #[test]
+    fn webp2png() {
+        use super::*;
+
+        let contents = convert_bytes(
+            include_bytes!("../../tests/files/webp2webp_test1.webp"),
+            &Options {
+                width: Some(100),
+                ..Options::default()
+            },
+            &mut Trace::default(),
+        )
+        .unwrap();
+
+        assert_eq!(&contents[..4], b"\x89PNG");
+    }
+}
@@ -1,8 +1,11 @@
 use crate::{
+    report::Trace,
     utils::webp::{self, Error},
     Dimensions, PathAccessor, Quality,
 };
 
+/// Converts the input file to the output file like [`convert_bytes`].
+///
 /// # Errors
 ///
 /// Returns an error if the conversion fails.
@@ -11,12 +14,27 @@ pub fn convert<T>(config: &T) -> Result<(), Error>
 where
     T: PathAccessor + Dimensions + Quality,
 {
-    webp::optimize(config)
+    crate::utils::optimize_file(config, |input| {
+        convert_bytes(input, config, &mut Trace::default())
+    })
+}
+
+/// # Errors
+///
+/// Returns an error if the conversion fails.
+///
+pub fn convert_bytes<T>(input: &[u8], config: &T, trace: &mut Trace) -> Result<Vec<u8>, Error>
+where
+    T: Dimensions + Quality,
+{
+    trace.step(webp::BACKEND, Some("lanczos3"), || {
+        webp::optimize_bytes(input, config)
+    })
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::Config;
+    use crate::{Config, Options};
 
     #[test]
     fn webp2webp() {
@@ -30,4 +48,29 @@ mod tests {
         ))
         .unwrap();
     }
+
+    #[test]
+    fn webp2webp_animated_bytes() {
+        use super::*;
+
+        let input = include_bytes!("../../tests/files/webp2webp_test1.webp");
+        let frames = |data: &[u8]| {
+            let decoder =
+                image::codecs::webp::WebPDecoder::new(std::io::Cursor::new(data)).unwrap();
+            image::AnimationDecoder::into_frames(decoder).count()
+        };
+
+        let contents = convert_bytes(
+            input,
+            &Options {
+                width: Some(100),
+                ..Options::default()
+            },
+            &mut Trace::default(),
+        )
+        .unwrap();
+
+        assert!(frames(input) > 1);
+        assert_eq!(frames(&contents), frames(input));
+    }
 }
@@ -0,0 +1,344 @@
+//! Options of the encoder of each output format, see [`EncoderOptions`].
+//! The `quality` of the config stays the main knob, these options tune the encoders further.
+
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ParseError {
+    #[error("Unknown chroma subsampling: {0}, expected one of 4:4:4, 4:2:2, 4:2:0")]
+    ChromaSubsampling(String),
+    #[error("Unknown bit depth: {0}, expected 8 or 10")]
+    BitDepth(String),
+    #[error("Unknown color model: {0}, expected ycbcr or rgb")]
+    ColorModel(String),
+}
+
+/// How many chroma samples of a JPEG image are kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(try_from = "String")
+)]
+pub enum ChromaSubsampling {
+    /// Every sample, the sharpest colors.
+    Yuv444,
+    /// Half of the samples horizontally.
+    Yuv422,
+    /// Half of the samples in both directions, the smallest files.
+    Yuv420,
+}
+
+impl FromStr for ChromaSubsampling {
+    type Err = ParseError;
+
+    /// Parses `4:4:4`, `4:2:2` or `4:2:0`, the colons are optional.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.replace(':', "").as_str() {
+            "444" => Ok(ChromaSubsampling::Yuv444),
+            "422" => Ok(ChromaSubsampling::Yuv422),
+            "420" => Ok(ChromaSubsampling::Yuv420),
+            _ => Err(ParseError::ChromaSubsampling(s.to_string())),
+        }
+    }
+}
+
+try_from_string!(ChromaSubsampling);
+
+impl Display for ChromaSubsampling {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ChromaSubsampling::Yuv444 => "4:4:4",
+            ChromaSubsampling::Yuv422 => "4:2:2",
+            ChromaSubsampling::Yuv420 => "4:2:0",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// The bit depth of the color channels of an AVIF image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(try_from = "String")
+)]
+pub enum BitDepth {
+    Eight,
+    Ten,
+}
+
+impl FromStr for BitDepth {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "8" => Ok(BitDepth::Eight),
+            "10" => Ok(BitDepth::Ten),
+            _ => Err(ParseError::BitDepth(s.to_string())),
+        }
+    }
+}
+
+try_from_string!(BitDepth);
+
+impl Display for BitDepth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BitDepth::Eight => write!(f, "8"),
+            BitDepth::Ten => write!(f, "10"),
+        }
+    }
+}
+
+/// How the colors of an AVIF image are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(try_from = "String")
+)]
+pub enum ColorModel {
+    /// Luma and chroma, the smallest files.
+    YCbCr,
+    /// Red, green and blue, sharper colors in larger files.
+    Rgb,
+}
+
+impl FromStr for ColorModel {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ycbcr" => Ok(ColorModel::YCbCr),
+            "rgb" => Ok(ColorModel::Rgb),
+            _ => Err(ParseError::ColorModel(s.to_string())),
+        }
+    }
+}
+
+try_from_string!(ColorModel);
+
+impl Display for ColorModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ColorModel::YCbCr => write!(f, "ycbcr"),
+            ColorModel::Rgb => write!(f, "rgb"),
+        }
+    }
+}
+
+/// Options of the WebP encoder, used with the `native` feature, the pure-Rust encoder is always lossless.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct WebpOptions {
+    /// The compression method, from 0 (the fastest) to 6 (the smallest), 4 by default.
+    pub method: Option<u8>,
+    /// Preprocesses the pixels of lossless images for smaller files, from 0 (the strongest) to 100 (off).
+    pub near_lossless: Option<u8>,
+    /// Converts the colors of lossy images more accurately and more slowly.
+    pub sharp_yuv: bool,
+    /// The quality of the alpha channel of lossy images, from 0 to 100.
+    pub alpha_quality: Option<u8>,
+    /// Keeps the colors of transparent pixels.
+    pub exact: bool,
+}
+
+/// Options of the JPEG encoder, used with the `native` feature.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct JpegOptions {
+    /// Writes a progressive JPEG, shown in growing detail while it loads.
+    pub progressive: bool,
+    pub chroma_subsampling: Option<ChromaSubsampling>,
+    /// Computes optimal Huffman tables for a smaller file.
+    pub optimize_coding: bool,
+}
+
+/// Options of the oxipng optimizer.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PngOptions {
+    /// The optimization level, from 0 (the fastest) to 6 (the smallest), 2 by default.
+    pub level: Option<u8>,
+    /// Writes an interlaced (Adam7) PNG, shown in growing detail while it loads.
+    pub interlace: bool,
+    /// Compresses with Zopfli, much slower and a little smaller.
+    pub zopfli: bool,
+}
+
+/// Options of the AVIF encoder.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AvifOptions {
+    /// The encoding speed, from 1 (the smallest) to 10 (the fastest), 4 by default.
+    pub speed: Option<u8>,
+    /// The quality of the alpha channel, from 1 to 100, the quality by default.
+    pub alpha_quality: Option<u8>,
+    /// Chosen from the image by default.
+    pub bit_depth: Option<BitDepth>,
+    /// [`ColorModel::YCbCr`] by default.
+    pub color_model: Option<ColorModel>,
+}
+
+/// Options of gifsicle, used with the `native` feature.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GifOptions {
+    /// Reduces the palette to the number of colors, from 2 to 256.
+    pub colors: Option<u16>,
+    /// The strength of the lossy compression, overrides the one derived from the quality.
+    pub lossy: Option<u32>,
+    /// The optimization level, from 1 to 3, overrides the tuning arguments of the tool.
+    pub optimization_level: Option<u8>,
+}
+
+/// Options of the encoder of each output format, options of other formats are ignored.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct EncoderOptions {
+    pub webp: WebpOptions,
+    pub jpeg: JpegOptions,
+    pub png: PngOptions,
+    pub avif: AvifOptions,
+    pub gif: GifOptions,
+}
+
+/// The encoder options with a flat name each, e.g. `webp_method`,
+/// as given to the CLI (`--webp-method`), the server query and the command-server JSON.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::Args))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize), serde(default))]
+pub struct EncoderParams {
+    /// WebP compression method, from 0 (the fastest) to 6 (the smallest)
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub webp_method: Option<u8>,
+    /// WebP near lossless preprocessing without a quality, from 0 (the strongest) to 100 (off)
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub webp_near_lossless: Option<u8>,
+    /// Convert the colors of lossy WebP more accurately and more slowly
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub webp_sharp_yuv: bool,
+    /// Quality of the alpha channel of lossy WebP, from 0 to 100
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub webp_alpha_quality: Option<u8>,
+    /// Keep the colors of transparent pixels of WebP
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub webp_exact: bool,
+    /// Write a progressive JPEG
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub jpeg_progressive: bool,
+    /// JPEG chroma subsampling: 4:4:4, 4:2:2 or 4:2:0
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub jpeg_chroma_subsampling: Option<ChromaSubsampling>,
+    /// Compute optimal Huffman tables of JPEG
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub jpeg_optimize_coding: bool,
+    /// PNG optimization level of oxipng, from 0 (the fastest) to 6 (the smallest)
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub png_level: Option<u8>,
+    /// Write an interlaced PNG
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub png_interlace: bool,
+    /// Compress PNG with Zopfli, much slower and a little smaller
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub png_zopfli: bool,
+    /// AVIF encoding speed, from 1 (the smallest) to 10 (the fastest)
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub avif_speed: Option<u8>,
+    /// Quality of the alpha channel of AVIF, from 1 to 100
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub avif_alpha_quality: Option<u8>,
+    /// AVIF bit depth: 8 or 10
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub avif_bit_depth: Option<BitDepth>,
+    /// AVIF color model: ycbcr or rgb
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub avif_color_model: Option<ColorModel>,
+    /// Number of colors of the GIF palette, from 2 to 256
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub gif_colors: Option<u16>,
+    /// Strength of the lossy GIF compression, overrides the one derived from the quality
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub gif_lossy: Option<u32>,
+    /// GIF optimization level of gifsicle, from 1 to 3
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub gif_optimization_level: Option<u8>,
+}
+
+impl From<&EncoderParams> for EncoderOptions {
+    fn from(params: &EncoderParams) -> Self {
+        EncoderOptions {
+            webp: WebpOptions {
+                method: params.webp_method,
+                near_lossless: params.webp_near_lossless,
+                sharp_yuv: params.webp_sharp_yuv,
+                alpha_quality: params.webp_alpha_quality,
+                exact: params.webp_exact,
+            },
+            jpeg: JpegOptions {
+                progressive: params.jpeg_progressive,
+                chroma_subsampling: params.jpeg_chroma_subsampling,
+                optimize_coding: params.jpeg_optimize_coding,
+            },
+            png: PngOptions {
+                level: params.png_level,
+                interlace: params.png_interlace,
+                zopfli: params.png_zopfli,
+            },
+            avif: AvifOptions {
+                speed: params.avif_speed,
+                alpha_quality: params.avif_alpha_quality,
+                bit_depth: params.avif_bit_depth,
+                color_model: params.avif_color_model,
+            },
+            gif: GifOptions {
+                colors: params.gif_colors,
+                lossy: params.gif_lossy,
+                optimization_level: params.gif_optimization_level,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn parse_chroma_subsampling() {
+        use super::*;
+
+        assert_eq!("4:2:0".parse(), Ok(ChromaSubsampling::Yuv420));
+        assert_eq!("444".parse(), Ok(ChromaSubsampling::Yuv444));
+        assert_eq!(ChromaSubsampling::Yuv422.to_string(), "4:2:2");
+        assert!("4:1:1".parse::<ChromaSubsampling>().is_err());
+    }
+
+    #[test]
+    fn parse_avif_options() {
+        use super::*;
+
+        assert_eq!("10".parse(), Ok(BitDepth::Ten));
+        assert!("12".parse::<BitDepth>().is_err());
+        assert_eq!("RGB".parse(), Ok(ColorModel::Rgb));
+        assert!("cmyk".parse::<ColorModel>().is_err());
+    }
+
+    #[test]
+    fn params_to_options() {
+        use super::*;
+
+        let options = EncoderOptions::from(&EncoderParams {
+            webp_method: Some(6),
+            jpeg_progressive: true,
+            png_level: Some(4),
+            avif_color_model: Some(ColorModel::Rgb),
+            gif_colors: Some(64),
+            ..EncoderParams::default()
+        });
+
+        assert_eq!(options.webp.method, Some(6));
+        assert!(options.jpeg.progressive);
+        assert_eq!(options.png.level, Some(4));
+        assert_eq!(options.avif.color_model, Some(ColorModel::Rgb));
+        assert_eq!(options.gif.colors, Some(64));
+    }
+}
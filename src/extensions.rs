@@ -4,3 +4,102 @@ pub const WEBP: &str = "webp";
 pub const JPG: &str = "jpg";
 pub const JPEG: &str = "jpeg";
 pub const JFIF: &str = "jfif";
+pub const AVIF: &str = "avif";
+/// Not a format: the smallest of WebP, AVIF and JPEG or PNG that meets a quality threshold,
+/// see [`Registry::convert_bytes_with_report`](crate::Registry::convert_bytes_with_report).
+pub const AUTO: &str = "auto";
+
+/// Returns the lowercase format name with the jpeg aliases ([`JPG`], [`JFIF`]) mapped to [`JPEG`].
+#[must_use]
+pub fn canonical(format: &str) -> String {
+    match format.to_lowercase().as_str() {
+        JPG | JFIF => JPEG.to_string(),
+        format => format.to_string(),
+    }
+}
+
+/// Returns the media type of the format, e.g. `image/webp`, or `None` for unknown formats.
+#[must_use]
+pub fn media_type(format: &str) -> Option<&'static str> {
+    match canonical(format).as_str() {
+        GIF => Some("image/gif"),
+        PNG => Some("image/png"),
+        WEBP => Some("image/webp"),
+        JPEG => Some("image/jpeg"),
+        AVIF => Some("image/avif"),
+        _ => None,
+    }
+}
+
+/// Number of leading bytes needed by [`from_magic_bytes`] to recognize every supported format.
+pub const MAGIC_BYTES_LEN: usize = 12;
+
+/// Detects the image format from the leading bytes of the file content.
+///
+/// Returns one of the extension constants or `None` if the content does not match any supported format.
+///
+#[must_use]
+pub fn from_magic_bytes(bytes: &[u8]) -> Option<&'static str> {
+    match bytes {
+        [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => Some(PNG),
+        [0xFF, 0xD8, 0xFF, ..] => Some(JPEG),
+        [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some(GIF),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some(WEBP),
+        [_, _, _, _, b'f', b't', b'y', b'p', b'a', b'v', b'i', b'f' | b's', ..] => Some(AVIF),
+        _ => None,
+    }
+}
+
+/// Whether the format is detected by [`from_magic_bytes`].
+#[must_use]
+pub fn is_detected(format: &str) -> bool {
+    matches!(canonical(format).as_str(), PNG | JPEG | GIF | WEBP | AVIF)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn from_magic_bytes() {
+        use super::*;
+
+        assert_eq!(
+            from_magic_bytes(include_bytes!("../tests/files/convert_test2.jpg")),
+            Some(PNG)
+        );
+        assert_eq!(
+            from_magic_bytes(include_bytes!("../tests/files/png2png_test2.png")),
+            Some(JPEG)
+        );
+        assert_eq!(
+            from_magic_bytes(include_bytes!(
+                "../tests/files/webp/command_server_test2.jpg"
+            )),
+            Some(WEBP)
+        );
+        assert_eq!(
+            from_magic_bytes(include_bytes!("../tests/files/test1.gif")),
+            Some(GIF)
+        );
+        assert_eq!(from_magic_bytes(b"\0\0\0\x1cftypavif\0\0\0\0"), Some(AVIF));
+        assert_eq!(from_magic_bytes(b"GIF8"), None);
+        assert_eq!(from_magic_bytes(b"not an image"), None);
+    }
+
+    #[test]
+    fn canonical() {
+        use super::*;
+
+        assert_eq!(canonical("JPG"), JPEG);
+        assert_eq!(canonical(JFIF), JPEG);
+        assert_eq!(canonical("WebP"), WEBP);
+    }
+
+    #[test]
+    fn media_type() {
+        use super::*;
+
+        assert_eq!(media_type(JPG), Some("image/jpeg"));
+        assert_eq!(media_type("AVIF"), Some("image/avif"));
+        assert_eq!(media_type(AUTO), None);
+    }
+}
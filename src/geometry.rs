@@ -0,0 +1,745 @@
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+use thiserror::Error;
+
+use crate::{utils::fit, Dimensions};
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ParseError {
+    #[error("Unknown resize mode: {0}, expected one of fit, inside, cover, exact, pad")]
+    ResizeMode(String),
+    #[error("Invalid color: {0}, expected #rrggbb or #rrggbbaa")]
+    Color(String),
+    #[error(
+        "Unknown gravity: {0}, expected center or a compass direction like north or south-east"
+    )]
+    Gravity(String),
+    #[error("Invalid focal point: {0}, expected x,y in percent between 0 and 100")]
+    FocalPoint(String),
+}
+
+/// How the image is fitted into the width and height box.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(try_from = "String")
+)]
+pub enum ResizeMode {
+    /// Scale to fit inside the box keeping the aspect ratio.
+    #[default]
+    Fit,
+    /// Like [`ResizeMode::Fit`], but never enlarge the image.
+    Inside,
+    /// Scale to fill the box keeping the aspect ratio, then crop the overflow,
+    /// keeping the part placed by the [`Gravity`] or the area around the [`FocalPoint`].
+    Cover,
+    /// Stretch to the box, ignoring the aspect ratio.
+    Exact,
+    /// Scale to fit inside the box, then center on a canvas of the box size filled with the background color.
+    Pad,
+}
+
+impl FromStr for ResizeMode {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "fit" | "contain" => Ok(ResizeMode::Fit),
+            "inside" => Ok(ResizeMode::Inside),
+            "cover" | "crop" => Ok(ResizeMode::Cover),
+            "exact" | "fill" => Ok(ResizeMode::Exact),
+            "pad" => Ok(ResizeMode::Pad),
+            _ => Err(ParseError::ResizeMode(s.to_string())),
+        }
+    }
+}
+
+try_from_string!(ResizeMode);
+
+impl Display for ResizeMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ResizeMode::Fit => "fit",
+            ResizeMode::Inside => "inside",
+            ResizeMode::Cover => "cover",
+            ResizeMode::Exact => "exact",
+            ResizeMode::Pad => "pad",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// The part of the image kept when cropping and the position of the image on the canvas when padding.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(try_from = "String")
+)]
+pub enum Gravity {
+    #[default]
+    Center,
+    North,
+    NorthEast,
+    East,
+    SouthEast,
+    South,
+    SouthWest,
+    West,
+    NorthWest,
+    /// Keep the most interesting part of the image, scored by edges, saturation and skin tones.
+    /// Placed like [`Gravity::Center`] when padding.
+    Attention,
+}
+
+impl Gravity {
+    /// The horizontal and vertical position as a fraction of the free space.
+    fn fractions(self) -> (f64, f64) {
+        match self {
+            Gravity::Center | Gravity::Attention => (0.5, 0.5),
+            Gravity::North => (0.5, 0.0),
+            Gravity::NorthEast => (1.0, 0.0),
+            Gravity::East => (1.0, 0.5),
+            Gravity::SouthEast => (1.0, 1.0),
+            Gravity::South => (0.5, 1.0),
+            Gravity::SouthWest => (0.0, 1.0),
+            Gravity::West => (0.0, 0.5),
+            Gravity::NorthWest => (0.0, 0.0),
+        }
+    }
+}
+
+impl FromStr for Gravity {
+    type Err = ParseError;
+
+    /// Parses names like `north`, `south-east`, `southeast` or `se`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name: String = s
+            .chars()
+            .filter(|c| !matches!(c, '-' | '_' | ' '))
+            .collect::<String>()
+            .to_lowercase();
+        match name.as_str() {
+            "center" | "centre" | "c" => Ok(Gravity::Center),
+            "north" | "n" => Ok(Gravity::North),
+            "northeast" | "ne" => Ok(Gravity::NorthEast),
+            "east" | "e" => Ok(Gravity::East),
+            "southeast" | "se" => Ok(Gravity::SouthEast),
+            "south" | "s" => Ok(Gravity::South),
+            "southwest" | "sw" => Ok(Gravity::SouthWest),
+            "west" | "w" => Ok(Gravity::West),
+            "northwest" | "nw" => Ok(Gravity::NorthWest),
+            "attention" | "smart" | "entropy" => Ok(Gravity::Attention),
+            _ => Err(ParseError::Gravity(s.to_string())),
+        }
+    }
+}
+
+try_from_string!(Gravity);
+
+impl Display for Gravity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Gravity::Center => "center",
+            Gravity::North => "north",
+            Gravity::NorthEast => "north-east",
+            Gravity::East => "east",
+            Gravity::SouthEast => "south-east",
+            Gravity::South => "south",
+            Gravity::SouthWest => "south-west",
+            Gravity::West => "west",
+            Gravity::NorthWest => "north-west",
+            Gravity::Attention => "attention",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// The point of interest of the image in percent of its width and height,
+/// cropping keeps the area around it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(try_from = "String")
+)]
+pub struct FocalPoint {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl FromStr for FocalPoint {
+    type Err = ParseError;
+
+    /// Parses `x,y`, e.g. `50,25` or `50%,25%`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseError::FocalPoint(s.to_string());
+        let percent = |value: &str| {
+            value
+                .trim()
+                .trim_end_matches('%')
+                .parse::<f64>()
+                .ok()
+                .filter(|value| (0.0..=100.0).contains(value))
+                .ok_or_else(error)
+        };
+        let (x, y) = s.split_once(',').ok_or_else(error)?;
+        Ok(FocalPoint {
+            x: percent(x)?,
+            y: percent(y)?,
+        })
+    }
+}
+
+try_from_string!(FocalPoint);
+
+impl Display for FocalPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}%,{}%", self.x, self.y)
+    }
+}
+
+/// An RGBA color, black by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(try_from = "String")
+)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Default for Color {
+    fn default() -> Self {
+        Color {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 255,
+        }
+    }
+}
+
+impl FromStr for Color {
+    type Err = ParseError;
+
+    /// Parses `#rrggbb` or `#rrggbbaa`, the `#` is optional.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseError::Color(s.to_string());
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+            return Err(error());
+        }
+        let channel = |index: usize| {
+            hex.get(index..index + 2)
+                .and_then(|channel| u8::from_str_radix(channel, 16).ok())
+                .ok_or_else(error)
+        };
+        Ok(Color {
+            r: channel(0)?,
+            g: channel(2)?,
+            b: channel(4)?,
+            a: if hex.len() == 8 { channel(6)? } else { 255 },
+        })
+    }
+}
+
+try_from_string!(Color);
+
+impl Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "#{:02x}{:02x}{:02x}{:02x}",
+            self.r, self.g, self.b, self.a
+        )
+    }
+}
+
+/// A rectangle of the source image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// The operations applied to the source image, in order: crop, resize and place on the canvas.
+/// Every backend follows it, so all formats produce the same dimensions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Geometry {
+    pub source_width: u32,
+    pub source_height: u32,
+    /// The part of the source image that is kept.
+    pub crop: Rect,
+    /// The size the cropped image is resized to.
+    pub width: u32,
+    pub height: u32,
+    /// The size of the output image, larger than the resized image only for [`ResizeMode::Pad`].
+    pub canvas_width: u32,
+    pub canvas_height: u32,
+    /// The position of the resized image on the canvas, set by the [`Gravity`].
+    pub x: u32,
+    pub y: u32,
+}
+
+impl Geometry {
+    fn scale(width: u32, height: u32, new_width: u32, new_height: u32) -> Self {
+        Geometry {
+            source_width: width,
+            source_height: height,
+            crop: Rect {
+                x: 0,
+                y: 0,
+                width,
+                height,
+            },
+            width: new_width.max(1),
+            height: new_height.max(1),
+            canvas_width: new_width.max(1),
+            canvas_height: new_height.max(1),
+            x: 0,
+            y: 0,
+        }
+    }
+
+    /// Whether the image is cropped.
+    #[must_use]
+    pub fn crops(&self) -> bool {
+        self.crop.width != self.source_width || self.crop.height != self.source_height
+    }
+
+    /// Whether the image is resized.
+    #[must_use]
+    pub fn resizes(&self) -> bool {
+        self.crop.width != self.width || self.crop.height != self.height
+    }
+
+    /// Whether the image is placed on a larger canvas.
+    #[must_use]
+    pub fn pads(&self) -> bool {
+        self.canvas_width != self.width || self.canvas_height != self.height
+    }
+}
+
+/// The offset of a `window` inside `size`, placed by the gravity `fraction`
+/// or centered on the `focus` percent when given.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn offset(size: u32, window: u32, fraction: f64, focus: Option<f64>) -> u32 {
+    let free = size.saturating_sub(window);
+    let offset = match focus {
+        Some(focus) => f64::from(size) * focus / 100.0 - f64::from(window) / 2.0,
+        None => f64::from(free) * fraction,
+    };
+    (offset.round().max(0.0) as u32).min(free)
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn proportional(value: u32, numerator: u32, denominator: u32) -> u32 {
+    (f64::from(value) * f64::from(numerator) / f64::from(denominator)).round() as u32
+}
+
+/// The box the image is fitted into, the missing side follows the aspect ratio of the image.
+fn box_size<T>(width: u32, height: u32, config: &T) -> (u32, u32)
+where
+    T: Dimensions,
+{
+    match (config.width(), config.height()) {
+        (Some(box_width), Some(box_height)) => (box_width, box_height),
+        (Some(box_width), None) => (box_width, proportional(height, box_width, width).max(1)),
+        (None, Some(box_height)) => (proportional(width, box_height, height).max(1), box_height),
+        (None, None) => (width, height),
+    }
+}
+
+/// Computes how an image of the given size is cropped, resized and padded for the configured
+/// width, height and resize mode.
+/// With [`Dimensions::without_enlargement`] the scale is at most 1, the crop of [`ResizeMode::Cover`]
+/// and the canvas of [`ResizeMode::Pad`] are still applied.
+#[must_use]
+pub fn geometry<T>(width: u32, height: u32, config: &T) -> Geometry
+where
+    T: Dimensions,
+{
+    let geometry = resize_geometry(width, height, config);
+    let crop = geometry.crop;
+    let enlarges = geometry.width > crop.width || geometry.height > crop.height;
+    if !config.without_enlargement() || !enlarges {
+        return geometry;
+    }
+    let (new_width, new_height) = match config.resize_mode() {
+        // The sides are stretched independently, so each one is clamped.
+        ResizeMode::Exact => (
+            geometry.width.min(crop.width),
+            geometry.height.min(crop.height),
+        ),
+        _ => (crop.width, crop.height),
+    };
+    let unscaled = Geometry {
+        crop,
+        ..Geometry::scale(width, height, new_width, new_height)
+    };
+    if config.resize_mode() != ResizeMode::Pad {
+        return unscaled;
+    }
+    let (fraction_x, fraction_y) = config.gravity().fractions();
+    Geometry {
+        canvas_width: geometry.canvas_width,
+        canvas_height: geometry.canvas_height,
+        x: offset(geometry.canvas_width, new_width, fraction_x, None),
+        y: offset(geometry.canvas_height, new_height, fraction_y, None),
+        ..unscaled
+    }
+}
+
+fn resize_geometry<T>(width: u32, height: u32, config: &T) -> Geometry
+where
+    T: Dimensions,
+{
+    if config.width().is_none() && config.height().is_none() {
+        return Geometry::scale(width, height, width, height);
+    }
+
+    match config.resize_mode() {
+        ResizeMode::Fit => {
+            let (new_width, new_height) = fit(
+                width,
+                height,
+                config.width().unwrap_or(width),
+                config.height().unwrap_or(height),
+            );
+            Geometry::scale(width, height, new_width, new_height)
+        }
+        ResizeMode::Inside => {
+            let (box_width, box_height) = box_size(width, height, config);
+            if width <= box_width && height <= box_height {
+                return Geometry::scale(width, height, width, height);
+            }
+            let (new_width, new_height) = fit(width, height, box_width, box_height);
+            Geometry::scale(width, height, new_width, new_height)
+        }
+        ResizeMode::Exact => {
+            let (box_width, box_height) = box_size(width, height, config);
+            Geometry::scale(width, height, box_width, box_height)
+        }
+        ResizeMode::Cover => {
+            let (box_width, box_height) = box_size(width, height, config);
+            let (crop_width, crop_height) = if u64::from(width) * u64::from(box_height)
+                > u64::from(height) * u64::from(box_width)
+            {
+                (
+                    proportional(height, box_width, box_height).clamp(1, width),
+                    height,
+                )
+            } else {
+                (
+                    width,
+                    proportional(width, box_height, box_width).clamp(1, height),
+                )
+            };
+            let (fraction_x, fraction_y) = config.gravity().fractions();
+            let focal_point = config.focal_point();
+            let crop = Rect {
+                x: offset(
+                    width,
+                    crop_width,
+                    fraction_x,
+                    focal_point.map(|point| point.x),
+                ),
+                y: offset(
+                    height,
+                    crop_height,
+                    fraction_y,
+                    focal_point.map(|point| point.y),
+                ),
+                width: crop_width,
+                height: crop_height,
+            };
+            Geometry {
+                crop,
+                ..Geometry::scale(width, height, box_width, box_height)
+            }
+        }
+        ResizeMode::Pad => {
+            let (box_width, box_height) = box_size(width, height, config);
+            let (new_width, new_height) = fit(width, height, box_width, box_height);
+            let new_width = new_width.clamp(1, box_width);
+            let new_height = new_height.clamp(1, box_height);
+            let (fraction_x, fraction_y) = config.gravity().fractions();
+            Geometry {
+                canvas_width: box_width,
+                canvas_height: box_height,
+                x: offset(box_width, new_width, fraction_x, None),
+                y: offset(box_height, new_height, fraction_y, None),
+                ..Geometry::scale(width, height, new_width, new_height)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Options;
+
+    fn options(width: Option<u32>, height: Option<u32>, resize_mode: super::ResizeMode) -> Options {
+        Options {
+            width,
+            height,
+            resize_mode,
+            ..Options::default()
+        }
+    }
+
+    #[test]
+    fn parse_resize_mode() {
+        use super::*;
+
+        assert_eq!("Cover".parse(), Ok(ResizeMode::Cover));
+        assert_eq!("fill".parse(), Ok(ResizeMode::Exact));
+        assert_eq!(
+            "stretch".parse::<ResizeMode>(),
+            Err(ParseError::ResizeMode("stretch".to_string()))
+        );
+    }
+
+    #[test]
+    fn parse_color() {
+        use super::*;
+
+        assert_eq!(
+            "#ff8000".parse(),
+            Ok(Color {
+                r: 255,
+                g: 128,
+                b: 0,
+                a: 255
+            })
+        );
+        assert_eq!("ffffff00".parse::<Color>().unwrap().a, 0);
+        assert!("#fff".parse::<Color>().is_err());
+        assert!("#gggggg".parse::<Color>().is_err());
+    }
+
+    #[test]
+    fn geometry_fit() {
+        use super::*;
+
+        let geometry = geometry(400, 300, &options(Some(200), Some(200), ResizeMode::Fit));
+
+        assert_eq!((geometry.width, geometry.height), (200, 150));
+        assert!(!geometry.pads());
+    }
+
+    #[test]
+    fn geometry_inside() {
+        use super::*;
+
+        let geometry = geometry(400, 300, &options(Some(800), Some(800), ResizeMode::Inside));
+
+        assert_eq!((geometry.width, geometry.height), (400, 300));
+        assert!(!geometry.resizes());
+    }
+
+    #[test]
+    fn geometry_cover() {
+        use super::*;
+
+        let geometry = geometry(400, 300, &options(Some(200), Some(200), ResizeMode::Cover));
+
+        assert_eq!(
+            geometry.crop,
+            Rect {
+                x: 50,
+                y: 0,
+                width: 300,
+                height: 300
+            }
+        );
+        assert_eq!((geometry.canvas_width, geometry.canvas_height), (200, 200));
+    }
+
+    #[test]
+    fn parse_gravity() {
+        use super::*;
+
+        assert_eq!("south-east".parse(), Ok(Gravity::SouthEast));
+        assert_eq!("NorthWest".parse(), Ok(Gravity::NorthWest));
+        assert_eq!("n".parse(), Ok(Gravity::North));
+        assert!("up".parse::<Gravity>().is_err());
+    }
+
+    #[test]
+    fn parse_focal_point() {
+        use super::*;
+
+        assert_eq!("30%,75%".parse(), Ok(FocalPoint { x: 30.0, y: 75.0 }));
+        assert_eq!("50, 0".parse(), Ok(FocalPoint { x: 50.0, y: 0.0 }));
+        assert!("150,0".parse::<FocalPoint>().is_err());
+        assert!("50".parse::<FocalPoint>().is_err());
+    }
+
+    #[test]
+    fn geometry_cover_gravity() {
+        use super::*;
+
+        let portrait = Options {
+            gravity: Gravity::North,
+            ..options(Some(200), Some(200), ResizeMode::Cover)
+        };
+        assert_eq!(geometry(300, 400, &portrait).crop.y, 0);
+
+        let landscape = Options {
+            gravity: Gravity::SouthEast,
+            ..options(Some(200), Some(200), ResizeMode::Cover)
+        };
+        assert_eq!(geometry(400, 300, &landscape).crop.x, 100);
+    }
+
+    #[test]
+    fn geometry_cover_focal_point() {
+        use super::*;
+
+        let options = Options {
+            focal_point: Some(FocalPoint { x: 10.0, y: 80.0 }),
+            ..options(Some(100), Some(100), ResizeMode::Cover)
+        };
+
+        // Centered on x = 40 the crop would start before the left edge, so it is clamped.
+        assert_eq!(geometry(400, 300, &options).crop.x, 0);
+        assert_eq!(geometry(300, 400, &options).crop.y, 100);
+    }
+
+    #[test]
+    fn geometry_pad_gravity() {
+        use super::*;
+
+        let options = Options {
+            gravity: Gravity::South,
+            ..options(Some(200), Some(200), ResizeMode::Pad)
+        };
+
+        let geometry = geometry(400, 300, &options);
+
+        assert_eq!((geometry.x, geometry.y), (0, 50));
+    }
+
+    #[test]
+    fn geometry_exact() {
+        use super::*;
+
+        let geometry = geometry(400, 300, &options(Some(100), Some(200), ResizeMode::Exact));
+
+        assert_eq!((geometry.canvas_width, geometry.canvas_height), (100, 200));
+        assert!(!geometry.crops());
+    }
+
+    #[test]
+    fn geometry_pad() {
+        use super::*;
+
+        let geometry = geometry(400, 300, &options(Some(200), Some(200), ResizeMode::Pad));
+
+        assert_eq!((geometry.width, geometry.height), (200, 150));
+        assert_eq!((geometry.canvas_width, geometry.canvas_height), (200, 200));
+        assert_eq!((geometry.x, geometry.y), (0, 25));
+    }
+
+    #[test]
+    fn geometry_without_enlargement() {
+        use super::*;
+
+        for resize_mode in [ResizeMode::Fit, ResizeMode::Cover, ResizeMode::Exact] {
+            let options = Options {
+                without_enlargement: true,
+                ..options(Some(1200), None, resize_mode)
+            };
+
+            let geometry = geometry(50, 50, &options);
+
+            assert_eq!((geometry.canvas_width, geometry.canvas_height), (50, 50));
+            assert!(!geometry.resizes());
+        }
+
+        let options = Options {
+            without_enlargement: true,
+            ..options(Some(25), None, ResizeMode::Fit)
+        };
+        assert_eq!(geometry(50, 50, &options).width, 25);
+    }
+
+    #[test]
+    fn geometry_cover_without_enlargement() {
+        use super::*;
+
+        // Only the width of the box is larger than the source.
+        let options = Options {
+            without_enlargement: true,
+            ..options(Some(500), Some(100), ResizeMode::Cover)
+        };
+
+        let geometry = geometry(400, 300, &options);
+
+        assert_eq!(
+            geometry.crop,
+            Rect {
+                x: 0,
+                y: 110,
+                width: 400,
+                height: 80
+            }
+        );
+        assert_eq!((geometry.canvas_width, geometry.canvas_height), (400, 80));
+        assert!(!geometry.resizes());
+    }
+
+    #[test]
+    fn geometry_pad_without_enlargement() {
+        use super::*;
+
+        // Only the height of the box is larger than the source.
+        let options = Options {
+            without_enlargement: true,
+            ..options(Some(400), Some(600), ResizeMode::Pad)
+        };
+
+        let geometry = geometry(500, 250, &options);
+
+        assert_eq!((geometry.width, geometry.height), (400, 200));
+        assert_eq!((geometry.canvas_width, geometry.canvas_height), (400, 600));
+        assert_eq!((geometry.x, geometry.y), (0, 200));
+    }
+
+    #[test]
+    fn geometry_pad_without_enlargement_on_larger_canvas() {
+        use super::*;
+
+        // Both sides of the box are larger, the image is centered on the canvas without being scaled.
+        let options = Options {
+            without_enlargement: true,
+            ..options(Some(800), Some(600), ResizeMode::Pad)
+        };
+
+        let geometry = geometry(500, 250, &options);
+
+        assert_eq!((geometry.width, geometry.height), (500, 250));
+        assert_eq!((geometry.canvas_width, geometry.canvas_height), (800, 600));
+        assert_eq!((geometry.x, geometry.y), (150, 175));
+    }
+
+    #[test]
+    fn geometry_single_side() {
+        use super::*;
+
+        let geometry = geometry(400, 300, &options(Some(200), None, ResizeMode::Pad));
+
+        assert_eq!((geometry.canvas_width, geometry.canvas_height), (200, 150));
+    }
+}
@@ -1,19 +1,53 @@
+/// Implements `TryFrom<String>` with the `FromStr` implementation, used to deserialize the value from a string.
+macro_rules! try_from_string {
+    ($type:ty) => {
+        impl TryFrom<String> for $type {
+            type Error = ParseError;
+
+            fn try_from(value: String) -> Result<Self, Self::Error> {
+                value.parse()
+            }
+        }
+    };
+}
+
 #[cfg(feature = "server-app-error")]
 pub mod app_error;
+pub mod capabilities;
+pub mod color_profile;
 #[cfg(feature = "command-server")]
 pub mod command_server;
+pub mod comparison;
 pub mod core;
+pub mod encoder;
 pub mod extensions;
+pub mod geometry;
+pub mod metadata;
+pub mod registry;
+pub mod report;
 #[cfg(feature = "web-service")]
 pub mod server;
+pub mod tools;
 pub mod utils;
 
-use core::{gif2gif, gif2webp, jpeg2jpeg, jpeg2webp, png2jpeg, png2png, png2webp, webp2webp};
+use core::{gif2gif, gif2png, png2png, webp2png};
 use derive_builder::Builder;
-use extensions::{GIF, JFIF, JPEG, JPG, PNG, WEBP};
-use std::path::{Path, PathBuf};
+use registry::default_registry;
+use std::{
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
 use thiserror::Error;
-use utils::{gifsicle, magick, webp};
+use utils::{avif, raster, webp};
+
+pub use capabilities::{Capabilities, Capability};
+pub use color_profile::ColorProfile;
+pub use comparison::Comparison;
+pub use encoder::{EncoderOptions, EncoderParams};
+pub use geometry::{Color, FocalPoint, Gravity, ResizeMode};
+pub use metadata::Metadata;
+pub use registry::{Converter, Plan, Registry};
+pub use report::{Candidate, ConversionReport, Step, Trace};
 
 pub trait PathAccessor {
     fn input_path(&self) -> &PathBuf;
@@ -23,10 +57,43 @@ pub trait PathAccessor {
 pub trait Dimensions {
     fn width(&self) -> Option<u32>;
     fn height(&self) -> Option<u32>;
+
+    fn resize_mode(&self) -> ResizeMode {
+        ResizeMode::default()
+    }
+
+    /// The color of the canvas around the image in [`ResizeMode::Pad`].
+    fn background(&self) -> Color {
+        Color::default()
+    }
+
+    /// Whether the image is never scaled up, the crop and the canvas of the resize mode are still applied.
+    fn without_enlargement(&self) -> bool {
+        false
+    }
+
+    fn gravity(&self) -> Gravity {
+        Gravity::default()
+    }
+
+    /// Overrides the [`Gravity`] when cropping.
+    fn focal_point(&self) -> Option<FocalPoint> {
+        None
+    }
+
+    /// The color profile the pixels are converted to, from the embedded ICC profile of the input or from sRGB.
+    fn color_profile(&self) -> ColorProfile {
+        ColorProfile::default()
+    }
 }
 
 pub trait Quality {
     fn quality(&self) -> Option<u32>;
+
+    /// The options of the encoder of each output format.
+    fn encoder(&self) -> EncoderOptions {
+        EncoderOptions::default()
+    }
 }
 
 #[derive(Default, Builder, Debug)]
@@ -35,12 +102,27 @@ pub struct Config {
     pub input_path: PathBuf,
     #[builder(setter(into))]
     pub output_path: PathBuf,
-    #[builder(default)]
-    pub width: Option<u32>,
-    #[builder(default)]
-    pub height: Option<u32>,
-    #[builder(default)]
-    pub quality: Option<u32>,
+    /// The conversion options, the same as the ones of the in-memory API.
+    #[builder(default, setter(into))]
+    pub options: Options,
+}
+
+/// Setters of the most common options, the others are set with [`ConfigBuilder::options`].
+impl ConfigBuilder {
+    pub fn width(&mut self, width: Option<u32>) -> &mut Self {
+        self.options.get_or_insert_with(Options::default).width = width;
+        self
+    }
+
+    pub fn height(&mut self, height: Option<u32>) -> &mut Self {
+        self.options.get_or_insert_with(Options::default).height = height;
+        self
+    }
+
+    pub fn quality(&mut self, quality: Option<u32>) -> &mut Self {
+        self.options.get_or_insert_with(Options::default).quality = quality;
+        self
+    }
 }
 
 impl Config {
@@ -53,13 +135,175 @@ impl Config {
         Config {
             input_path: input_path.as_ref().to_path_buf(),
             output_path: output_path.as_ref().to_path_buf(),
-            width,
-            height,
+            options: Options {
+                width,
+                height,
+                ..Options::default()
+            },
+        }
+    }
+}
+
+/// Conversion options without any paths, used by the in-memory API.
+///
+/// The command line, the query of the server and the body of the command server parse the same options,
+/// the encoder options are given by [`EncoderParams`].
+///
+/// Without the `native` feature WebP is written lossless only, so a quality, a size limit or a DSSIM target
+/// for WebP output fails with [`Error::BackendUnavailable`].
+#[derive(Default, Builder, Debug, Clone)]
+#[cfg_attr(feature = "clap", derive(clap::Args))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize), serde(default))]
+pub struct Options {
+    /// Width of the output image
+    /// If not set, the width will be the same as the input image
+    #[builder(default)]
+    #[cfg_attr(feature = "clap", arg(short, long))]
+    pub width: Option<u32>,
+    /// Height of the output image
+    /// If not set, the height will be the same as the input image
+    #[builder(default)]
+    #[cfg_attr(feature = "clap", arg(short, long))]
+    pub height: Option<u32>,
+    /// Quality of the output image.
+    /// If not set, the quality will be the same as the input image.
+    /// The value must be between 1 and 100.
+    /// The higher the value, the better the quality.
+    #[builder(default)]
+    #[cfg_attr(feature = "clap", arg(short, long))]
+    pub quality: Option<u32>,
+    /// How the image is fitted into the width and height:
+    /// fit, inside (fit without enlarging), cover (fill and crop), exact (stretch) or pad.
+    #[builder(default)]
+    #[cfg_attr(feature = "clap", arg(short, long, default_value = "fit"))]
+    pub resize_mode: ResizeMode,
+    /// Background color of the canvas in the pad mode, as #rrggbb or #rrggbbaa.
+    #[builder(default)]
+    #[cfg_attr(feature = "clap", arg(short, long, default_value = "#000000"))]
+    pub background: Color,
+    /// Never scale the image up, the crop of the cover mode and the canvas of the pad mode are kept
+    #[builder(default)]
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub without_enlargement: bool,
+    /// The part of the image kept by the cover mode and the position of the image in the pad mode:
+    /// center, north, north-east, east, south-east, south, south-west, west, north-west
+    /// or attention to keep the most interesting part chosen from the content.
+    #[builder(default)]
+    #[cfg_attr(feature = "clap", arg(short, long, default_value = "center"))]
+    pub gravity: Gravity,
+    /// The point of interest kept by the cover mode, as x,y in percent of the width and height.
+    /// Overrides the gravity when cropping.
+    #[builder(default)]
+    #[cfg_attr(feature = "clap", arg(short, long))]
+    pub focal_point: Option<FocalPoint>,
+    #[builder(default)]
+    #[cfg_attr(feature = "clap", arg(skip))]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub encoder: EncoderOptions,
+    /// Maximum size of the output file in bytes.
+    /// The quality of JPEG, WebP, AVIF and GIF is lowered until the output fits.
+    #[builder(default)]
+    #[cfg_attr(feature = "clap", arg(short, long))]
+    pub max_bytes: Option<usize>,
+    /// Highest DSSIM of JPEG, WebP and AVIF compared with the resized source, e.g. 0.001.
+    /// The lowest quality that meets it is used instead of the quality.
+    /// With the auto extension, the DSSIM the kept format must meet, 0.01 by default.
+    /// AVIF is decoded for it by the libheif delegate of imagemagick, so it fails without it.
+    #[builder(default)]
+    #[cfg_attr(feature = "clap", arg(short, long))]
+    pub target_dssim: Option<f64>,
+    /// Metadata of the input kept in the output:
+    /// strip-all, keep-all, keep-icc, keep-copyright (artist and copyright) or strip-gps (without the location).
+    #[builder(default)]
+    #[cfg_attr(feature = "clap", arg(long, default_value = "strip-all"))]
+    pub metadata: Metadata,
+    /// Color profile the pixels are converted to from the embedded ICC profile of the input:
+    /// srgb, display-p3 or adobe-rgb.
+    #[builder(default)]
+    #[cfg_attr(feature = "clap", arg(long, default_value = "srgb"))]
+    pub color_profile: ColorProfile,
+    /// Embed the color profile in the output, recommended for profiles other than srgb
+    #[builder(default)]
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub embed_profile: bool,
+}
+
+impl Options {
+    /// Copies the resize settings and the color profile of the config,
+    /// without the quality, the encoder options, the targets and the metadata.
+    pub(crate) fn resize_of<T>(config: &T) -> Self
+    where
+        T: Dimensions,
+    {
+        Options {
+            width: config.width(),
+            height: config.height(),
             quality: None,
+            resize_mode: config.resize_mode(),
+            background: config.background(),
+            without_enlargement: config.without_enlargement(),
+            gravity: config.gravity(),
+            focal_point: config.focal_point(),
+            encoder: EncoderOptions::default(),
+            max_bytes: None,
+            target_dssim: None,
+            metadata: Metadata::default(),
+            color_profile: config.color_profile(),
+            embed_profile: false,
         }
     }
 }
 
+impl From<&Config> for Options {
+    fn from(config: &Config) -> Self {
+        config.options.clone()
+    }
+}
+
+impl Dimensions for Options {
+    fn width(&self) -> Option<u32> {
+        self.width
+    }
+
+    fn height(&self) -> Option<u32> {
+        self.height
+    }
+
+    fn resize_mode(&self) -> ResizeMode {
+        self.resize_mode
+    }
+
+    fn background(&self) -> Color {
+        self.background
+    }
+
+    fn without_enlargement(&self) -> bool {
+        self.without_enlargement
+    }
+
+    fn gravity(&self) -> Gravity {
+        self.gravity
+    }
+
+    fn focal_point(&self) -> Option<FocalPoint> {
+        self.focal_point
+    }
+
+    fn color_profile(&self) -> ColorProfile {
+        self.color_profile
+    }
+}
+
+impl Quality for Options {
+    fn quality(&self) -> Option<u32> {
+        self.quality
+    }
+
+    fn encoder(&self) -> EncoderOptions {
+        self.encoder
+    }
+}
+
 impl PathAccessor for Config {
     fn input_path(&self) -> &PathBuf {
         &self.input_path
@@ -72,88 +316,193 @@ impl PathAccessor for Config {
 
 impl Dimensions for Config {
     fn width(&self) -> Option<u32> {
-        self.width
+        self.options.width()
     }
 
     fn height(&self) -> Option<u32> {
-        self.height
+        self.options.height()
+    }
+
+    fn resize_mode(&self) -> ResizeMode {
+        self.options.resize_mode()
+    }
+
+    fn background(&self) -> Color {
+        self.options.background()
+    }
+
+    fn without_enlargement(&self) -> bool {
+        self.options.without_enlargement()
+    }
+
+    fn gravity(&self) -> Gravity {
+        self.options.gravity()
+    }
+
+    fn focal_point(&self) -> Option<FocalPoint> {
+        self.options.focal_point()
+    }
+
+    fn color_profile(&self) -> ColorProfile {
+        self.options.color_profile()
     }
 }
 
 impl Quality for Config {
     fn quality(&self) -> Option<u32> {
-        self.quality
+        self.options.quality()
+    }
+
+    fn encoder(&self) -> EncoderOptions {
+        self.options.encoder()
     }
 }
 
 #[derive(Debug, Error)]
 pub enum Error {
+    #[error("Io({0})")]
+    Io(std::io::Error),
     #[error("Input file has no extension")]
     InputFileHasNoExtension,
     #[error("Output file has no extension")]
     OutputFileHasNoExtension,
+    #[error("Input file content does not match any supported format")]
+    UnsupportedInputFormat,
     #[error("Unsupported conversion: {0} -> {1}")]
     UnsupportedConversion(String, String),
+    #[error("Backend unavailable: {0} ({1})")]
+    BackendUnavailable(String, String),
+    #[error("Output exceeds the limit of {0} bytes, the smallest is {1} bytes")]
+    MaxBytesExceeded(usize, usize),
+    #[error("Metrics({0})")]
+    Metrics(utils::ssim::Error),
+    #[error("Dimensions differ: {0:?} and {1:?}")]
+    DimensionsMismatch((u32, u32), (u32, u32)),
+    #[error("Metadata({0})")]
+    Metadata(utils::container::Error),
+    #[error("Invalid environment variable {0}: {1}")]
+    InvalidEnvironmentVariable(String, String),
     #[error("Error converting png to png: {0}")]
     Png2Png(png2png::Error),
     #[error("Error converting png to jpg: {0}")]
-    Png2Jpeg(magick::Error),
+    Png2Jpeg(raster::Error),
     #[error("Error converting png to webp: {0}")]
     Png2Webp(webp::Error),
     #[error("Error converting jpg to jpg: {0}")]
-    Jpeg2Jpeg(magick::Error),
+    Jpeg2Jpeg(raster::Error),
     #[error("Error converting jpg to webp: {0}")]
     Jpeg2Webp(webp::Error),
     #[error("Error converting gif to gif: {0}")]
-    Gif2Gif(gifsicle::Error),
+    Gif2Gif(gif2gif::Error),
     #[error("Error converting gif to webp: {0}")]
-    Gif2Webp(gif2webp::Error),
+    Gif2Webp(webp::Error),
     #[error("Error converting webp to webp: {0}")]
     Webp2Webp(webp::Error),
+    #[error("Error converting webp to jpg: {0}")]
+    Webp2Jpeg(raster::Error),
+    #[error("Error converting png to avif: {0}")]
+    Png2Avif(avif::Error),
+    #[error("Error converting jpg to avif: {0}")]
+    Jpeg2Avif(avif::Error),
+    #[error("Error converting webp to avif: {0}")]
+    Webp2Avif(avif::Error),
+    #[error("Error converting gif to avif: {0}")]
+    Gif2Avif(avif::Error),
+    #[error("Error converting gif to png: {0}")]
+    Gif2Png(gif2png::Error),
+    #[error("Error converting jpg to png: {0}")]
+    Jpeg2Png(png2png::Error),
+    #[error("Error converting webp to png: {0}")]
+    Webp2Png(webp2png::Error),
+    #[error("Error converting png to gif: {0}")]
+    Png2Gif(raster::Error),
+    #[error("Error converting avif to png: {0}")]
+    Avif2Png(raster::Error),
+    #[error("{0}")]
+    Custom(Box<dyn std::error::Error + Send + Sync>),
 }
 
+/// Converts the image data to the `output_format`, given as one of the [`extensions`].
+/// The input format is detected from the data.
+///
 /// # Errors
 ///
 /// Returns an error if:
-/// * The input file path has no extension
-/// * The output file path has no extension
+/// * The input data does not match any supported format
 /// * The conversion is not supported
 /// * An error occurs during the conversion
 ///
-pub fn convert(config: &Config) -> Result<(), Error> {
-    match (
-        config
-            .input_path()
-            .extension()
-            .and_then(std::ffi::OsStr::to_str)
-            .map(str::to_lowercase),
-        config
-            .output_path()
-            .extension()
-            .and_then(std::ffi::OsStr::to_str)
-            .map(str::to_lowercase),
-    ) {
-        (Some(input_extension), Some(output_extension)) => {
-            match (input_extension.as_str(), output_extension.as_str()) {
-                (GIF, GIF) => gif2gif::convert(config).map_err(Error::Gif2Gif),
-                (GIF, WEBP) => gif2webp::convert(config).map_err(Error::Gif2Webp),
-                (PNG, WEBP) => png2webp::convert(config).map_err(Error::Png2Webp),
-                (WEBP, WEBP) => webp2webp::convert(config).map_err(Error::Webp2Webp),
-                (JPG | JPEG | JFIF, WEBP) => jpeg2webp::convert(config).map_err(Error::Jpeg2Webp),
-                (JPG | JPEG | JFIF, JPG | JPEG | JFIF) => {
-                    jpeg2jpeg::convert(config).map_err(Error::Jpeg2Jpeg)
-                }
-                (PNG, PNG) => png2png::convert(config).map_err(Error::Png2Png),
-                (PNG, JPG | JPEG | JFIF) => png2jpeg::convert(config).map_err(Error::Png2Jpeg),
-                (input_extension, output_extension) => Err(Error::UnsupportedConversion(
-                    input_extension.to_string(),
-                    output_extension.to_string(),
-                )),
-            }
-        }
-        (None, _) => Err(Error::InputFileHasNoExtension),
-        (_, None) => Err(Error::OutputFileHasNoExtension),
-    }
+pub fn convert_bytes(
+    input: &[u8],
+    output_format: &str,
+    options: &Options,
+) -> Result<Vec<u8>, Error> {
+    default_registry().convert_bytes(input, output_format, options)
+}
+
+/// Converts the image data like [`convert_bytes`] and returns the report of the conversion with it,
+/// e.g. with the quality picked for [`Options::target_dssim`] and the DSSIM of the output.
+///
+/// # Errors
+///
+/// Returns an error if the conversion fails, see [`convert_bytes`].
+///
+pub fn convert_bytes_with_report(
+    input: &[u8],
+    output_format: &str,
+    options: &Options,
+) -> Result<(Vec<u8>, ConversionReport), Error> {
+    default_registry().convert_bytes_with_report(input, output_format, options)
+}
+
+/// Measures how much the `image` differs from the `reference`, see [`Registry::compare`].
+///
+/// # Errors
+///
+/// Returns an error if an image cannot be decoded or the images have different dimensions.
+///
+pub fn compare(reference: &[u8], image: &[u8]) -> Result<Comparison, Error> {
+    default_registry().compare(reference, image)
+}
+
+/// Returns the chain of built-in converters used to convert `input_format` to `output_format`,
+/// or `None` if the conversion is not supported.
+#[must_use]
+pub fn plan(input_format: &str, output_format: &str) -> Option<Plan> {
+    default_registry().plan(input_format, output_format)
+}
+
+/// Reads the whole image from `reader` and writes the converted image to `writer`.
+///
+/// # Errors
+///
+/// Returns an error if reading, writing or the conversion fails, see [`convert_bytes`].
+///
+pub fn convert_stream<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    output_format: &str,
+    options: &Options,
+) -> Result<(), Error>
+where
+    R: Read,
+    W: Write,
+{
+    default_registry().convert_stream(reader, writer, output_format, options)
+}
+
+/// Converts the input file to the output file, the output format is taken from the output path extension.
+/// Returns a report with the formats, dimensions and sizes of both images and the timing of every step.
+///
+/// # Errors
+///
+/// Returns an error if:
+/// * The output file path has no extension
+/// * The input file cannot be read or the output file cannot be written
+/// * The conversion fails, see [`convert_bytes`]
+///
+pub fn convert(config: &Config) -> Result<ConversionReport, Error> {
+    default_registry().convert(config)
 }
 
 #[cfg(test)]
@@ -221,6 +570,528 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn convert_report() -> Result<(), Error> {
+        use super::*;
+
+        let report = convert(&Config::new(
+            "tests/files/png2png_test1.png",
+            "target/convert_report1.webp",
+            Some(100),
+            None,
+        ))?;
+
+        assert_eq!(report.input_format, "png");
+        assert_eq!(report.output_format, "webp");
+        assert_eq!(report.converter, "png -> webp");
+        assert_eq!(
+            report.input_size,
+            include_bytes!("../tests/files/png2png_test1.png").len()
+        );
+        assert_eq!(
+            report.output_size,
+            std::fs::read("target/convert_report1.webp")
+                .map_err(Error::Io)?
+                .len()
+        );
+        assert_eq!(report.output_dimensions.map(|(width, _)| width), Some(100));
+        assert_eq!(report.steps.len(), 1);
+        assert_eq!(report.steps[0].name, utils::webp::BACKEND);
+        assert_eq!(report.filters(), vec!["lanczos3"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn resize_modes_same_dimensions() -> Result<(), Error> {
+        use super::*;
+
+        let input = include_bytes!("../tests/files/png2png_test1.png");
+        for (resize_mode, expected) in [
+            (ResizeMode::Cover, (100, 40)),
+            (ResizeMode::Exact, (100, 40)),
+            (ResizeMode::Pad, (100, 40)),
+        ] {
+            let options = Options {
+                width: Some(100),
+                height: Some(40),
+                resize_mode,
+                ..Options::default()
+            };
+            for format in ["webp", "avif"] {
+                let output = convert_bytes(input, format, &options)?;
+                assert_eq!(report::dimensions(&output, format), Some(expected));
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn convert_chained() -> Result<(), Error> {
+        use super::*;
+
+        assert_eq!(plan("webp", "gif").unwrap().to_string(), "webp -> png -> gif");
+
+        convert(&Config::new(
+            "tests/files/webp2webp_test1.webp",
+            "target/convert_chained1.gif",
+            Some(100),
+            None,
+        ))?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn convert_hostile_file_names() -> Result<(), Error> {
+        use super::*;
+        use crate::utils::HOSTILE_FILE_NAMES;
+
+        let dir = Path::new("target/hostile/convert");
+        std::fs::create_dir_all(dir).map_err(Error::Io)?;
+        for name in HOSTILE_FILE_NAMES {
+            let input = dir.join(name);
+            std::fs::copy("tests/files/gif2gif_test1.gif", &input).map_err(Error::Io)?;
+
+            for extension in ["gif", "webp"] {
+                let output = dir.join(format!("out {name}")).with_extension(extension);
+                convert(&Config::new(
+                    input.to_str().unwrap(),
+                    output.to_str().unwrap(),
+                    Some(100),
+                    None,
+                ))?;
+                assert!(output.exists(), "{name}");
+            }
+        }
+        assert!(!Path::new("pwned").exists());
+        assert!(!Path::new("pwned.gif").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn convert_to_avif() -> Result<(), Error> {
+        use super::*;
+
+        convert(&Config::new(
+            "tests/files/convert_test1.png",
+            "target/convert_test1.avif",
+            Some(100),
+            None,
+        ))?;
+
+        convert(&Config::new(
+            "tests/files/orientation_test.jpeg",
+            "target/convert_test2.avif",
+            Some(100),
+            None,
+        ))?;
+
+        convert(&Config::new(
+            "tests/files/webp2webp_test1.webp",
+            "target/convert_test3.avif",
+            Some(100),
+            None,
+        ))?;
+
+        convert(&Config::new(
+            "tests/files/convert_test1.gif",
+            "target/convert_test4.avif",
+            Some(100),
+            None,
+        ))?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn convert_max_bytes() -> Result<(), Error> {
+        use super::*;
+
+        // A photo, the size of a flat image with alpha barely depends on the quality.
+        let input = include_bytes!("../tests/files/convert_test1.JPG");
+        let mut options = Options {
+            width: Some(100),
+            ..Options::default()
+        };
+        options.encoder.avif.speed = Some(10);
+        let max_bytes = convert_bytes(input, "avif", &options)?.len() * 9 / 10;
+
+        let output = convert_bytes(
+            input,
+            "avif",
+            &Options {
+                max_bytes: Some(max_bytes),
+                ..options
+            },
+        )?;
+
+        assert!(output.len() <= max_bytes);
+        assert_eq!(&output[4..12], b"ftypavif");
+
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic = "MaxBytesExceeded(10, "]
+    fn convert_max_bytes_panic() {
+        use super::*;
+
+        convert_bytes(
+            include_bytes!("../tests/files/convert_test1.png"),
+            "avif",
+            &Options {
+                width: Some(100),
+                max_bytes: Some(10),
+                encoder: EncoderOptions {
+                    avif: encoder::AvifOptions {
+                        speed: Some(10),
+                        ..encoder::AvifOptions::default()
+                    },
+                    ..EncoderOptions::default()
+                },
+                ..Options::default()
+            },
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn convert_target_dssim() -> Result<(), Error> {
+        use super::*;
+
+        let (output, report) = convert_bytes_with_report(
+            include_bytes!("../tests/files/convert_test1.png"),
+            "jpg",
+            &Options {
+                width: Some(100),
+                target_dssim: Some(0.01),
+                ..Options::default()
+            },
+        )?;
+
+        assert!(report.dssim.is_some_and(|dssim| dssim <= 0.01));
+        assert!(report.quality.is_some());
+        assert_eq!(report.output_size, output.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn convert_auto() -> Result<(), Error> {
+        use super::*;
+
+        let report = convert(
+            &ConfigBuilder::default()
+                .input_path("tests/files/convert_test1.png")
+                .output_path("target/convert_auto.auto")
+                .width(Some(100))
+                .quality(Some(80))
+                .build()
+                .unwrap(),
+        )?;
+
+        let output = std::fs::read(
+            Path::new("target/convert_auto.auto").with_extension(&report.output_format),
+        )
+        .map_err(Error::Io)?;
+        assert_eq!(output.len(), report.output_size);
+        let formats: Vec<_> = report
+            .candidates
+            .iter()
+            .map(|candidate| candidate.format.as_str())
+            .collect();
+        // Lossy WebP needs libwebp and AVIF is tried only when libheif can decode it.
+        let libheif = capabilities::capabilities()
+            .require(&[capabilities::LIBHEIF])
+            .is_ok();
+        match (cfg!(feature = "native"), libheif) {
+            (true, true) => assert_eq!(formats, ["webp", "avif", "png"]),
+            (true, false) => assert_eq!(formats, ["webp", "png"]),
+            (false, _) => assert_eq!(formats, ["png"]),
+        }
+        assert!(formats.contains(&report.output_format.as_str()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn compare_converted() -> Result<(), Error> {
+        use super::*;
+
+        let reference = convert_bytes(
+            include_bytes!("../tests/files/convert_test1.png"),
+            "png",
+            &Options {
+                width: Some(100),
+                ..Options::default()
+            },
+        )?;
+        let image = convert_bytes(
+            include_bytes!("../tests/files/convert_test1.png"),
+            "jpg",
+            &Options {
+                width: Some(100),
+                quality: Some(50),
+                ..Options::default()
+            },
+        )?;
+
+        let comparison = compare(&reference, &image)?;
+
+        assert_eq!(comparison.reference.format, "png");
+        assert_eq!(comparison.image.format, "jpeg");
+        assert_eq!(comparison.image.size, image.len());
+        assert_eq!(
+            comparison.reference.dimensions,
+            comparison.image.dimensions
+        );
+        assert!(comparison.metrics.psnr.is_finite());
+        assert!(comparison.metrics.dssim > 0.0);
+
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic = "DimensionsMismatch("]
+    fn compare_panic_dimensions() {
+        use super::*;
+
+        compare(
+            include_bytes!("../tests/files/convert_test1.png"),
+            include_bytes!("../tests/files/orientation_test.jpg"),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    #[should_panic = "Png2Avif(Image("]
+    fn convert_panic_png_to_avif() {
+        use super::*;
+
+        convert_bytes(
+            &include_bytes!("../tests/files/convert_test1.png")[..32],
+            "avif",
+            &Options::default(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn convert_bytes() -> Result<(), Error> {
+        use super::*;
+
+        let output = convert_bytes(
+            include_bytes!("../tests/files/convert_test1.png"),
+            extensions::WEBP,
+            &OptionsBuilder::default().width(Some(100)).build().unwrap(),
+        )?;
+
+        assert_eq!(
+            extensions::from_magic_bytes(&output),
+            Some(extensions::WEBP)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn convert_stream() -> Result<(), Error> {
+        use super::*;
+
+        let mut reader = std::io::Cursor::new(include_bytes!("../tests/files/convert_test1.png"));
+        let mut output = Vec::new();
+        convert_stream(
+            &mut reader,
+            &mut output,
+            "AVIF",
+            &OptionsBuilder::default().width(Some(100)).build().unwrap(),
+        )?;
+
+        assert_eq!(&output[4..12], b"ftypavif");
+
+        Ok(())
+    }
+
+    #[test]
+    fn convert_orientation_every_format() -> Result<(), Error> {
+        use super::*;
+        use image::metadata::Orientation;
+        use utils::orientation;
+
+        let input = include_bytes!("../tests/files/orientation_test.jpg");
+        let mut options = Options {
+            width: Some(100),
+            ..Options::default()
+        };
+        options.encoder.avif.speed = Some(10);
+
+        for format in ["jpeg", "png", "webp", "avif", "gif"] {
+            let (output, report) = convert_bytes_with_report(input, format, &options)?;
+
+            let (width, height) = report.output_dimensions.unwrap();
+            assert_eq!(width, 100, "{format}");
+            assert!(height > width, "{format}");
+            if format != "avif" {
+                assert_eq!(
+                    orientation::orientation(&output).ok(),
+                    Some(Orientation::NoTransforms),
+                    "{format}"
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn convert_metadata_policies() -> Result<(), Error> {
+        use super::*;
+        use image::metadata::Orientation;
+        use utils::{
+            container::{jpeg, Chunks},
+            exif::tests::{sample, tags, LATITUDE},
+            orientation,
+        };
+
+        let input = jpeg::write(
+            include_bytes!("../tests/files/orientation_test.jpg"),
+            &Chunks {
+                icc: Some(ColorProfile::DisplayP3.icc()),
+                exif: Some(sample()),
+                xmp: Some(b"<x:xmpmeta/>".to_vec()),
+            },
+        )
+        .unwrap();
+        let mut options = Options {
+            width: Some(100),
+            ..Options::default()
+        };
+        options.encoder.avif.speed = Some(10);
+
+        for format in ["jpeg", "png", "webp", "avif"] {
+            for policy in [
+                Metadata::StripAll,
+                Metadata::KeepAll,
+                Metadata::KeepIcc,
+                Metadata::KeepCopyright,
+                Metadata::StripGps,
+            ] {
+                let output = convert_bytes(
+                    &input,
+                    format,
+                    &Options {
+                        metadata: policy,
+                        ..options.clone()
+                    },
+                )?;
+                let kept = metadata::read(&output, format);
+                let selected = policy.select(metadata::read(&input, "jpeg"));
+
+                // The pixels are converted to sRGB, so its profile replaces the kept one.
+                assert_eq!(
+                    kept,
+                    Chunks {
+                        icc: selected.icc.map(|_| ColorProfile::Srgb.icc()),
+                        ..selected
+                    },
+                    "{format} {policy}"
+                );
+                if policy == Metadata::KeepAll {
+                    assert_eq!(tags(&kept.exif.unwrap())[0], (0x0112, vec![1, 0]));
+                }
+                if policy != Metadata::KeepAll {
+                    assert!(!output.windows(LATITUDE.len()).any(|bytes| bytes == LATITUDE));
+                }
+                if format != "avif" {
+                    assert_eq!(
+                        orientation::orientation(&output).ok(),
+                        Some(Orientation::NoTransforms),
+                        "{format} {policy}"
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn convert_color_profiles() -> Result<(), Error> {
+        use super::*;
+        use image::{ImageFormat, RgbaImage};
+        use utils::{
+            container::{png, Chunks},
+            icc, ssim,
+        };
+
+        let pixel = [200, 100, 50, 255];
+        let mut untagged = std::io::Cursor::new(Vec::new());
+        RgbaImage::from_pixel(16, 16, image::Rgba(pixel))
+            .write_to(&mut untagged, ImageFormat::Png)
+            .unwrap();
+        let untagged = untagged.into_inner();
+        let display_p3 = png::write(
+            &untagged,
+            &Chunks {
+                icc: Some(ColorProfile::DisplayP3.icc()),
+                ..Chunks::default()
+            },
+        )
+        .unwrap();
+        let mut options = Options {
+            width: Some(8),
+            ..Options::default()
+        };
+        options.encoder.avif.speed = Some(10);
+
+        for (input, icc, profile) in [
+            (
+                &display_p3,
+                Some(ColorProfile::DisplayP3.icc()),
+                ColorProfile::Srgb,
+            ),
+            (&untagged, None, ColorProfile::DisplayP3),
+        ] {
+            let mut expected = pixel;
+            icc::convert(&mut expected, icc.as_deref(), profile);
+            for format in ["jpeg", "png", "webp", "avif"] {
+                for embed_profile in [false, true] {
+                    let output = convert_bytes(
+                        input,
+                        format,
+                        &Options {
+                            color_profile: profile,
+                            embed_profile,
+                            ..options.clone()
+                        },
+                    )?;
+
+                    assert_eq!(
+                        metadata::read(&output, format).icc,
+                        embed_profile.then(|| profile.icc()),
+                        "{format} {profile}"
+                    );
+                    if format != "avif" {
+                        let center = *ssim::decode(&output).unwrap().get_pixel(4, 4);
+                        assert!(
+                            center
+                                .0
+                                .iter()
+                                .zip(expected)
+                                .all(|(value, expected)| value.abs_diff(expected) <= 4),
+                            "{format} {profile}: {center:?} instead of {expected:?}"
+                        );
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn convert_jfif_to_webp() -> Result<(), Error> {
         use super::*;
@@ -249,12 +1120,12 @@ mod tests {
     }
 
     #[test]
-    fn extension_in_uppercase() {
+    fn detect_input_format_without_extension() {
         use super::*;
 
         convert(&Config::new(
-            "tests/files/convert_test1.JPG",
-            "target/convert_test1.webp",
+            "tests/files/convert_test1",
+            "target/convert_test9.webp",
             Some(100),
             None,
         ))
@@ -262,13 +1133,12 @@ mod tests {
     }
 
     #[test]
-    #[should_panic = "UnsupportedConversion(\"jpg\", \"tiff\")"]
-    fn convert_panic() {
+    fn detect_wrongly_named_webp() {
         use super::*;
 
         convert(&Config::new(
-            "tests/files/not_existing.jpg",
-            "target/test1.tiff",
+            "tests/files/webp/command_server_test2.jpg",
+            "target/convert_test10.webp",
             Some(100),
             None,
         ))
@@ -276,13 +1146,13 @@ mod tests {
     }
 
     #[test]
-    #[should_panic = "Jpeg2Webp(Io(Os { code: 2, kind: NotFound, message: \"No such file or directory\" }))"]
-    fn convert_panic_jpg_to_webp() {
+    #[should_panic = "UnsupportedInputFormat"]
+    fn convert_panic_unsupported_input_format() {
         use super::*;
 
         convert(&Config::new(
-            "tests/files/not_existing.jpg",
-            "target/test1.webp",
+            "tests/files/not_an_image.png",
+            "target/not_an_image.webp",
             Some(100),
             None,
         ))
@@ -290,13 +1160,14 @@ mod tests {
     }
 
     #[test]
-    #[should_panic = "Jpeg2Jpeg(Magick(MagickError(\"unable to open image 'tests/files/not_existing.jpg':"]
-    fn convert_panic_jpg_to_jpg() {
+    #[should_panic = "InputFileHasNoExtension"]
+    fn convert_panic_unsupported_input_without_extension() {
         use super::*;
 
+        std::fs::write("target/not_an_image", b"not an image").unwrap();
         convert(&Config::new(
-            "tests/files/not_existing.jpg",
-            "target/test1.jpg",
+            "target/not_an_image",
+            "target/not_an_image.webp",
             Some(100),
             None,
         ))
@@ -304,13 +1175,12 @@ mod tests {
     }
 
     #[test]
-    #[should_panic = "Png2Png(Magick(Magick(MagickError(\"unable to open image 'tests/files/not_existing.png':"]
-    fn convert_panic_png_to_png() {
+    fn extension_in_uppercase() {
         use super::*;
 
         convert(&Config::new(
-            "tests/files/not_existing.png",
-            "target/test1.png",
+            "tests/files/convert_test1.JPG",
+            "target/convert_test1.webp",
             Some(100),
             None,
         ))
@@ -318,13 +1188,13 @@ mod tests {
     }
 
     #[test]
-    #[should_panic = "Webp2Webp(Io(Os { code: 2, kind: NotFound, message: \"No such file or directory\" }))"]
-    fn convert_panic_webp_to_webp() {
+    #[should_panic = "UnsupportedConversion(\"jpeg\", \"tiff\")"]
+    fn convert_panic() {
         use super::*;
 
         convert(&Config::new(
-            "tests/files/not_existing.webp",
-            "target/test1.webp",
+            "tests/files/orientation_test.jpg",
+            "target/test1.tiff",
             Some(100),
             None,
         ))
@@ -332,12 +1202,67 @@ mod tests {
     }
 
     #[test]
-    #[should_panic = "InputFileHasNoExtension"]
-    fn convert_panic_no_input_extension() {
+    #[should_panic = "Jpeg2Webp(Image("]
+    fn convert_panic_jpg_to_webp() {
+        use super::*;
+
+        convert_bytes(
+            &include_bytes!("../tests/files/orientation_test.jpg")[..32],
+            "webp",
+            &Options::default(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(feature = "native", should_panic = "Jpeg2Jpeg(Magick(MagickError(")]
+    #[cfg_attr(not(feature = "native"), should_panic = "Jpeg2Jpeg(Image(")]
+    fn convert_panic_jpg_to_jpg() {
+        use super::*;
+
+        convert_bytes(
+            &include_bytes!("../tests/files/orientation_test.jpg")[..32],
+            "jpg",
+            &Options::default(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(feature = "native", should_panic = "Png2Png(Raster(Magick(MagickError(")]
+    #[cfg_attr(not(feature = "native"), should_panic = "Png2Png(Raster(Image(")]
+    fn convert_panic_png_to_png() {
+        use super::*;
+
+        convert_bytes(
+            &include_bytes!("../tests/files/convert_test1.png")[..32],
+            "png",
+            &Options::default(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(feature = "native", should_panic = "Webp2Webp(LibWebPError(AnimDecoding))")]
+    #[cfg_attr(not(feature = "native"), should_panic = "Webp2Webp(Image(")]
+    fn convert_panic_webp_to_webp() {
+        use super::*;
+
+        convert_bytes(
+            &include_bytes!("../tests/files/webp2webp_test1.webp")[..32],
+            "webp",
+            &Options::default(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    #[should_panic = "Io(Os { code: 2, kind: NotFound, message: \"No such file or directory\" })"]
+    fn convert_panic_no_input_file() {
         use super::*;
 
         convert(&Config::new(
-            "tests/files/not_existing",
+            "tests/files/not_existing.jpg",
             "target/test1.webp",
             Some(100),
             None,
@@ -360,58 +1285,56 @@ mod tests {
     }
 
     #[test]
-    #[should_panic = "Gif2Gif(Exit(1))"]
+    #[cfg_attr(feature = "native", should_panic = "Gif2Gif(Exit(1))")]
+    #[cfg_attr(not(feature = "native"), should_panic = "Gif2Gif(Decoding(")]
     fn convert_panic_gif_to_gif() {
         use super::*;
 
-        convert(&Config::new(
-            "tests/files/not_existing.gif",
-            "target/test1.gif",
-            Some(100),
-            None,
-        ))
+        convert_bytes(
+            &include_bytes!("../tests/files/convert_test1.gif")[..32],
+            "gif",
+            &Options::default(),
+        )
         .unwrap();
     }
 
     #[test]
-    #[should_panic = "Gif2Webp(Gifsicle(Exit(1)))"]
+    #[should_panic = "Gif2Webp(Gif(Decoding("]
     fn convert_panic_gif_to_webp() {
         use super::*;
 
-        convert(&Config::new(
-            "tests/files/not_existing.gif",
-            "target/test1.webp",
-            Some(100),
-            None,
-        ))
+        convert_bytes(
+            &include_bytes!("../tests/files/convert_test1.gif")[..32],
+            "webp",
+            &Options::default(),
+        )
         .unwrap();
     }
 
     #[test]
-    #[should_panic = "Png2Webp(Io(Os { code: 2, kind: NotFound, message: \"No such file or directory\" }))"]
+    #[should_panic = "Png2Webp(Image("]
     fn convert_panic_png_to_webp() {
         use super::*;
 
-        convert(&Config::new(
-            "tests/files/not_existing.png",
-            "target/test1.webp",
-            Some(100),
-            None,
-        ))
+        convert_bytes(
+            &include_bytes!("../tests/files/convert_test1.png")[..32],
+            "webp",
+            &Options::default(),
+        )
         .unwrap();
     }
 
     #[test]
-    #[should_panic = "Png2Jpeg(Magick(MagickError(\"unable to open image 'tests/files/not_existing.png':"]
+    #[cfg_attr(feature = "native", should_panic = "Png2Jpeg(Magick(MagickError(")]
+    #[cfg_attr(not(feature = "native"), should_panic = "Png2Jpeg(Image(")]
     fn convert_panic_png_to_jpg() {
         use super::*;
 
-        convert(&Config::new(
-            "tests/files/not_existing.png",
-            "target/test1.jpg",
-            Some(100),
-            None,
-        ))
+        convert_bytes(
+            &include_bytes!("../tests/files/convert_test1.png")[..32],
+            "jpg",
+            &Options::default(),
+        )
         .unwrap();
     }
 }
@@ -2,7 +2,12 @@
 mod cli {
     use axum::Router;
     use clap::{Parser, Subcommand};
-    use std::path::PathBuf;
+    use respicta::{
+        capabilities::capabilities,
+        tools::{self, Tools},
+        EncoderParams, Options,
+    };
+    use std::{path::PathBuf, time::Duration};
     use tokio::{net::TcpListener, signal};
 
     #[derive(Parser)]
@@ -10,6 +15,43 @@ mod cli {
     pub struct Cli {
         #[command(subcommand)]
         pub command: Option<Commands>,
+        #[clap(
+            long,
+            global = true,
+            help = "Path of the gifsicle binary (env: RESPICTA_GIFSICLE_PATH, default: gifsicle)"
+        )]
+        pub gifsicle_path: Option<PathBuf>,
+        #[clap(
+            long,
+            global = true,
+            allow_hyphen_values = true,
+            help = "Tuning arguments of gifsicle, separated by whitespace (env: RESPICTA_GIFSICLE_ARGS, default: -O3)"
+        )]
+        pub gifsicle_args: Option<String>,
+        #[clap(
+            long,
+            global = true,
+            value_parser = tools::parse_timeout,
+            help = "Seconds after which gifsicle is killed (env: RESPICTA_GIFSICLE_TIMEOUT, default: none)"
+        )]
+        pub gifsicle_timeout: Option<Duration>,
+    }
+
+    impl Cli {
+        /// The tools configured by the environment, overridden by the options.
+        pub fn tools(&self) -> Result<Tools, respicta::Error> {
+            let mut tools = Tools::from_env()?;
+            if let Some(path) = &self.gifsicle_path {
+                tools.gifsicle.path.clone_from(path);
+            }
+            if let Some(args) = &self.gifsicle_args {
+                tools.gifsicle.args = tools::split_args(args);
+            }
+            if let Some(timeout) = self.gifsicle_timeout {
+                tools.gifsicle.timeout = Some(timeout);
+            }
+            Ok(tools)
+        }
     }
 
     #[derive(Subcommand)]
@@ -20,29 +62,29 @@ mod cli {
             after_help = "\
                 Examples: \n\
                 \n\
-                respicta convert --width 100 --height 100 --quality 75 input.jpg output.jpg
+                respicta convert --width 100 --height 100 --quality 75 input.jpg output.jpg\n\
+                respicta convert --width 100 --height 100 --resize-mode pad --background '#ffffff' input.jpg output.jpg\n\
+                respicta convert --width 100 --height 100 --resize-mode cover --focal-point 50,20 input.jpg output.jpg\n\
+                respicta convert --width 100 --quality 80 --jpeg-progressive --jpeg-chroma-subsampling 4:2:0 input.png output.jpg\n\
+                respicta convert --width 600 --max-bytes 150000 input.png output.jpg\n\
+                respicta convert --width 600 --target-dssim 0.001 input.png output.webp\n\
+                respicta convert --width 600 --metadata keep-copyright input.jpg output.jpg\n\
+                respicta convert --width 600 --color-profile display-p3 --embed-profile input.jpg output.avif\n\
+                respicta convert --width 600 input.jpg output.auto
                 "
         )]
         /// Convert images from one format to another
         Convert {
             /// Input image path
             input_path: PathBuf,
-            /// Output image path
+            /// Output image path.
+            /// With the auto extension the smallest of WebP, AVIF and JPEG or PNG that meets the DSSIM target
+            /// is written with its own extension.
             output_path: PathBuf,
-            /// Width of the output image
-            /// If not set, the width will be the same as the input image
-            #[clap(short, long)]
-            width: Option<u32>,
-            /// Height of the output image
-            /// If not set, the height will be the same as the input image
-            #[clap(short, long)]
-            height: Option<u32>,
-            /// Quality of the output image.
-            /// If not set, the quality will be the same as the input image.
-            /// The value must be between 1 and 100.
-            /// The higher the value, the better the quality.
-            #[clap(short, long)]
-            quality: Option<u32>,
+            #[command(flatten)]
+            options: Options,
+            #[command(flatten)]
+            encoder: EncoderParams,
             #[clap(long, action = clap::ArgAction::HelpLong)]
             help: Option<bool>,
         },
@@ -61,6 +103,28 @@ mod cli {
             #[clap(short, long)]
             address: Option<String>,
         },
+        /// Check the external tools and libraries used by the conversions
+        Doctor,
+        #[clap(
+            arg_required_else_help = true,
+            after_help = "\
+                Examples: \n\
+                \n\
+                respicta convert --width 100 input.png reference.png\n\
+                respicta convert --width 100 --quality 75 input.png output.jpg\n\
+                respicta compare --heatmap heatmap.png reference.png output.jpg
+                "
+        )]
+        /// Compare an image with a reference and print quality metrics
+        Compare {
+            /// Reference image path, e.g. the source resized to the dimensions of the image
+            reference_path: PathBuf,
+            /// Image path, e.g. the converted image
+            image_path: PathBuf,
+            /// Write a heatmap of the differences to this path, in the format given by its extension
+            #[clap(long)]
+            heatmap: Option<PathBuf>,
+        },
     }
 
     pub async fn start_server(address: Option<String>, service: Router) -> std::io::Result<()> {
@@ -72,6 +136,7 @@ mod cli {
                 if let Some(version) = version {
                     println!("Respicta v{version}");
                 }
+                println!("{}", capabilities());
                 println!("Server started at http://{address}");
                 axum::serve(listener, service)
                     .with_graceful_shutdown(shutdown_signal())
@@ -117,35 +182,75 @@ mod cli {
 async fn main() {
     use crate::cli::{start_server, Cli, Commands};
     use clap::Parser;
-    use respicta::{command_server, convert, server, ConfigBuilder};
+    use respicta::{
+        capabilities::capabilities, command_server, server, tools, Config, EncoderOptions, Options,
+        Registry,
+    };
 
     let cli = Cli::parse();
+    tools::set_tools(cli.tools().unwrap());
+    let registry = Registry::default();
 
     match cli.command {
         Some(Commands::Convert {
             input_path,
             output_path,
-            width,
-            height,
-            quality,
+            options,
+            encoder,
             ..
-        }) => convert(
-            &ConfigBuilder::default()
-                .input_path(input_path)
-                .output_path(output_path)
-                .width(width)
-                .height(height)
-                .quality(quality)
-                .build()
-                .unwrap(),
-        )
-        .unwrap(),
+        }) => {
+            let report = registry
+                .convert(&Config {
+                    input_path,
+                    output_path,
+                    options: Options {
+                        encoder: EncoderOptions::from(&encoder),
+                        ..options
+                    },
+                })
+                .unwrap();
+            if let Some(quality) = report.quality {
+                println!("quality: {quality}");
+            }
+            if let Some(dssim) = report.dssim {
+                println!("dssim: {dssim:.6}");
+            }
+            for candidate in &report.candidates {
+                println!(
+                    "{}: {} bytes, dssim: {:.6}",
+                    candidate.format, candidate.size, candidate.dssim
+                );
+            }
+            if !report.candidates.is_empty() {
+                println!("format: {}", report.output_format);
+            }
+        }
         Some(Commands::Server { address, limit }) => {
             start_server(address, server::app(limit)).await.unwrap();
         }
         Some(Commands::CommandServer { address }) => {
             start_server(address, command_server::app()).await.unwrap();
         }
+        Some(Commands::Compare {
+            reference_path,
+            image_path,
+            heatmap,
+        }) => {
+            let reference = std::fs::read(reference_path).unwrap();
+            let image = std::fs::read(image_path).unwrap();
+            let comparison = registry.compare(&reference, &image).unwrap();
+            println!("{comparison}");
+            if let Some(heatmap) = heatmap {
+                comparison.metrics.heatmap().save(heatmap).unwrap();
+            }
+        }
+        Some(Commands::Doctor) => {
+            let capabilities = capabilities();
+            println!("{capabilities}");
+            if !capabilities.is_complete() {
+                std::process::exit(1);
+            }
+        }
         None => unreachable!(),
     }
 }
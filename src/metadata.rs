@@ -0,0 +1,213 @@
+//! Which metadata of the input is kept in the output, see [`Metadata`].
+//!
+//! The encoders write no metadata, the kept chunks are read from the input and written into the container
+//! of the output by [`utils::container`](crate::utils::container), after the last step of the conversion.
+
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+use thiserror::Error;
+
+use crate::{
+    extensions::{self, AVIF, JPEG, PNG, WEBP},
+    utils::{
+        container::{self, avif, jpeg, png, webp, Chunks},
+        exif,
+    },
+};
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ParseError {
+    #[error("Unknown metadata policy: {0}, expected one of strip_all, keep_all, keep_icc, keep_copyright, strip_gps")]
+    Metadata(String),
+}
+
+/// The metadata of the input kept in JPEG, PNG, WebP and AVIF outputs, GIF outputs have none.
+///
+/// The pixels are turned upright when they are decoded, so a kept EXIF orientation is reset,
+/// and converted to the [`ColorProfile`](crate::ColorProfile), which replaces a kept ICC profile.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(try_from = "String")
+)]
+pub enum Metadata {
+    /// No metadata, the smallest files.
+    #[default]
+    StripAll,
+    /// The ICC profile, EXIF and XMP.
+    KeepAll,
+    /// Only the ICC profile, so the colors look the same.
+    KeepIcc,
+    /// The ICC profile and EXIF with only the artist and the copyright.
+    KeepCopyright,
+    /// The ICC profile and EXIF without the GPS tags, XMP is removed as it can hold the location too.
+    StripGps,
+}
+
+impl FromStr for Metadata {
+    type Err = ParseError;
+
+    /// Parses the snake case name, dashes are accepted instead of underscores.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('-', "_").as_str() {
+            "strip_all" => Ok(Metadata::StripAll),
+            "keep_all" => Ok(Metadata::KeepAll),
+            "keep_icc" => Ok(Metadata::KeepIcc),
+            "keep_copyright" => Ok(Metadata::KeepCopyright),
+            "strip_gps" => Ok(Metadata::StripGps),
+            _ => Err(ParseError::Metadata(s.to_string())),
+        }
+    }
+}
+
+try_from_string!(Metadata);
+
+impl Display for Metadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Metadata::StripAll => "strip_all",
+            Metadata::KeepAll => "keep_all",
+            Metadata::KeepIcc => "keep_icc",
+            Metadata::KeepCopyright => "keep_copyright",
+            Metadata::StripGps => "strip_gps",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl Metadata {
+    /// The chunks kept by the policy. EXIF that cannot be read is dropped, it could hold the orientation or the location.
+    #[must_use]
+    pub fn select(self, chunks: Chunks) -> Chunks {
+        let Chunks { icc, exif, xmp } = chunks;
+        match self {
+            Metadata::StripAll => Chunks::default(),
+            Metadata::KeepAll => Chunks {
+                icc,
+                exif: exif.and_then(exif::upright),
+                xmp,
+            },
+            Metadata::KeepIcc => Chunks {
+                icc,
+                ..Chunks::default()
+            },
+            Metadata::KeepCopyright => Chunks {
+                icc,
+                exif: exif.as_deref().and_then(exif::copyright),
+                xmp: None,
+            },
+            Metadata::StripGps => Chunks {
+                icc,
+                exif: exif.and_then(exif::upright).and_then(exif::without_gps),
+                xmp: None,
+            },
+        }
+    }
+}
+
+/// Reads the metadata of the image, none for GIF and images whose container cannot be read.
+#[must_use]
+pub fn read(data: &[u8], format: &str) -> Chunks {
+    let chunks = match extensions::canonical(format).as_str() {
+        JPEG => jpeg::read(data),
+        PNG => png::read(data),
+        WEBP => webp::read(data),
+        AVIF => avif::read(data),
+        _ => return Chunks::default(),
+    };
+    chunks.unwrap_or_default()
+}
+
+/// Writes the metadata into the container of the image, GIF images are returned unchanged.
+///
+/// # Errors
+///
+/// Returns an error if the container cannot be read or a chunk does not fit in it.
+///
+pub fn write(data: &[u8], format: &str, chunks: &Chunks) -> Result<Vec<u8>, container::Error> {
+    match extensions::canonical(format).as_str() {
+        JPEG => jpeg::write(data, chunks),
+        PNG => png::write(data, chunks),
+        WEBP => webp::write(data, chunks),
+        AVIF => avif::write(data, chunks),
+        _ => Ok(data.to_vec()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn metadata_from_str() {
+        use super::*;
+
+        assert_eq!("strip_all".parse(), Ok(Metadata::StripAll));
+        assert_eq!("keep-all".parse(), Ok(Metadata::KeepAll));
+        assert_eq!("KEEP_ICC".parse(), Ok(Metadata::KeepIcc));
+        assert_eq!("keep_copyright".parse(), Ok(Metadata::KeepCopyright));
+        assert_eq!("strip-gps".parse(), Ok(Metadata::StripGps));
+        assert_eq!(
+            "keep".parse::<Metadata>(),
+            Err(ParseError::Metadata("keep".to_string()))
+        );
+        for metadata in [
+            Metadata::StripAll,
+            Metadata::KeepAll,
+            Metadata::KeepIcc,
+            Metadata::KeepCopyright,
+            Metadata::StripGps,
+        ] {
+            assert_eq!(metadata.to_string().parse(), Ok(metadata));
+        }
+    }
+
+    #[test]
+    fn select() {
+        use super::*;
+        use crate::utils::exif::tests::{sample, tags};
+
+        let chunks = Chunks {
+            icc: Some(b"icc".to_vec()),
+            exif: Some(sample()),
+            xmp: Some(b"<x:xmpmeta/>".to_vec()),
+        };
+        let tag_names = |chunks: &Chunks| {
+            chunks.exif.as_deref().map(|exif| {
+                tags(exif)
+                    .into_iter()
+                    .map(|(tag, _)| tag)
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        assert_eq!(Metadata::StripAll.select(chunks.clone()), Chunks::default());
+        let all = Metadata::KeepAll.select(chunks.clone());
+        assert_eq!((&all.icc, &all.xmp), (&chunks.icc, &chunks.xmp));
+        assert_eq!(tag_names(&all), Some(vec![0x0112, 0x013B, 0x8298, 0x8825]));
+        assert_eq!(tags(all.exif.as_deref().unwrap())[0].1, [1, 0]);
+        assert_eq!(
+            Metadata::KeepIcc.select(chunks.clone()),
+            Chunks {
+                icc: chunks.icc.clone(),
+                ..Chunks::default()
+            }
+        );
+        let copyright = Metadata::KeepCopyright.select(chunks.clone());
+        assert_eq!((&copyright.icc, &copyright.xmp), (&chunks.icc, &None));
+        assert_eq!(tag_names(&copyright), Some(vec![0x013B, 0x8298]));
+        let without_gps = Metadata::StripGps.select(chunks.clone());
+        assert_eq!((&without_gps.icc, &without_gps.xmp), (&chunks.icc, &None));
+        assert_eq!(tag_names(&without_gps), Some(vec![0x0112, 0x013B, 0x8298]));
+        assert_eq!(
+            Metadata::StripGps
+                .select(Chunks {
+                    exif: Some(b"not exif".to_vec()),
+                    ..Chunks::default()
+                })
+                .exif,
+            None
+        );
+    }
+}
@@ -0,0 +1,1696 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::{self, Display},
+    fs::{create_dir_all, read, write},
+    io::{Read, Write},
+    path::Path,
+    sync::{Arc, OnceLock},
+    time::Instant,
+};
+
+use image::RgbaImage;
+
+use crate::{
+    capabilities::{capabilities, GIFSICLE, IMAGEMAGICK, LIBHEIF, LIBWEBP},
+    comparison::{self, Comparison, ImageInfo},
+    core::{
+        avif2png, gif2avif, gif2gif, gif2png, gif2webp, jpeg2avif, jpeg2jpeg, jpeg2png, jpeg2webp,
+        png2avif, png2gif, png2jpeg, png2png, png2webp, webp2avif, webp2jpeg, webp2png, webp2webp,
+    },
+    encoder::{GifOptions, JpegOptions, WebpOptions},
+    extensions::{self, AUTO, AVIF, GIF, JPEG, PNG, WEBP},
+    metadata,
+    report::{self, Candidate, ConversionReport, Trace},
+    utils::{container::Chunks, ssim},
+    Config, Error, Metadata, Options, PathAccessor,
+};
+
+/// Converts image data from one format to another.
+///
+/// It is implemented for every `Fn(&[u8], &Options) -> Result<Vec<u8>, Error>`,
+/// so a closure can be registered directly.
+pub trait Converter: Send + Sync {
+    /// # Errors
+    ///
+    /// Returns an error if the conversion fails.
+    ///
+    fn convert(&self, input: &[u8], options: &Options) -> Result<Vec<u8>, Error>;
+
+    /// Converts like [`Converter::convert`] and records the steps of the conversion in the `trace`.
+    /// By default the whole conversion is recorded as a single `custom` step.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the conversion fails.
+    ///
+    fn convert_traced(
+        &self,
+        input: &[u8],
+        options: &Options,
+        trace: &mut Trace,
+    ) -> Result<Vec<u8>, Error> {
+        trace.step("custom", None, || self.convert(input, options))
+    }
+
+    /// The names of the [`capabilities`](crate::capabilities) the converter needs with the options,
+    /// a conversion is refused before any step runs when one of them is unavailable.
+    fn requires(&self, _options: &Options) -> &[&'static str] {
+        &[]
+    }
+}
+
+impl<F> Converter for F
+where
+    F: Fn(&[u8], &Options) -> Result<Vec<u8>, Error> + Send + Sync,
+{
+    fn convert(&self, input: &[u8], options: &Options) -> Result<Vec<u8>, Error> {
+        self(input, options)
+    }
+}
+
+type BuiltinFn = fn(&[u8], &Options, &mut Trace) -> Result<Vec<u8>, Error>;
+
+/// The capabilities needed by a built-in converter with the given options.
+type Requires = fn(&Options) -> &'static [&'static str];
+
+fn requires_nothing(_options: &Options) -> &'static [&'static str] {
+    &[]
+}
+
+/// The capabilities needed by the converters using the `magick` module with the `native` feature.
+fn requires_raster(_options: &Options) -> &'static [&'static str] {
+    if cfg!(feature = "native") {
+        &[IMAGEMAGICK]
+    } else {
+        &[]
+    }
+}
+
+/// The capabilities needed by the converters writing WebP.
+/// The pure-Rust encoder is lossless only and has no [`WebpOptions`],
+/// so a quality, a size limit, a DSSIM target or the options need libwebp.
+fn requires_webp_encoder(options: &Options) -> &'static [&'static str] {
+    let lossy =
+        options.quality.is_some() || options.max_bytes.is_some() || options.target_dssim.is_some();
+    if cfg!(feature = "native") || lossy || options.encoder.webp != WebpOptions::default() {
+        &[LIBWEBP]
+    } else {
+        &[]
+    }
+}
+
+/// The capabilities needed by the converters writing JPEG.
+/// The pure-Rust encoder has no [`JpegOptions`], so they need the `magick` module.
+fn requires_jpeg_encoder(options: &Options) -> &'static [&'static str] {
+    if cfg!(feature = "native") || options.encoder.jpeg != JpegOptions::default() {
+        &[IMAGEMAGICK]
+    } else {
+        &[]
+    }
+}
+
+/// The capabilities needed by the AVIF to PNG converter, AVIF is read by the libheif delegate of `imagemagick`
+/// and cannot be read by the pure-Rust backend.
+fn requires_avif_decoder(_options: &Options) -> &'static [&'static str] {
+    &[LIBHEIF]
+}
+
+/// The capabilities needed by the GIF to GIF converter.
+/// The pure-Rust encoder is lossless only, so a quality, a size limit or [`GifOptions`] need gifsicle.
+fn requires_gif_optimizer(options: &Options) -> &'static [&'static str] {
+    let lossy = options.quality.is_some()
+        || options.max_bytes.is_some()
+        || options.encoder.gif != GifOptions::default();
+    if cfg!(feature = "native") || lossy {
+        &[GIFSICLE]
+    } else {
+        &[]
+    }
+}
+
+/// A built-in converter and the capabilities it needs, records its own steps.
+struct Builtin(BuiltinFn, Requires);
+
+impl Converter for Builtin {
+    fn convert(&self, input: &[u8], options: &Options) -> Result<Vec<u8>, Error> {
+        (self.0)(input, options, &mut Trace::default())
+    }
+
+    fn convert_traced(
+        &self,
+        input: &[u8],
+        options: &Options,
+        trace: &mut Trace,
+    ) -> Result<Vec<u8>, Error> {
+        (self.0)(input, options, trace)
+    }
+
+    fn requires(&self, options: &Options) -> &[&'static str] {
+        (self.1)(options)
+    }
+}
+
+/// The last converter of a plan, writes the metadata kept by [`Options::metadata`] and the embedded color profile
+/// into each of its outputs, so the size limit and the quality search see the final files.
+struct WithMetadata<'a> {
+    converter: &'a dyn Converter,
+    output_format: &'a str,
+    chunks: &'a Chunks,
+}
+
+impl Converter for WithMetadata<'_> {
+    fn convert(&self, input: &[u8], options: &Options) -> Result<Vec<u8>, Error> {
+        self.convert_traced(input, options, &mut Trace::default())
+    }
+
+    fn convert_traced(
+        &self,
+        input: &[u8],
+        options: &Options,
+        trace: &mut Trace,
+    ) -> Result<Vec<u8>, Error> {
+        let output = self.converter.convert_traced(input, options, trace)?;
+        if self.chunks.is_empty() {
+            return Ok(output);
+        }
+        trace
+            .step("metadata", None, || {
+                metadata::write(&output, self.output_format, self.chunks)
+            })
+            .map_err(Error::Metadata)
+    }
+
+    fn requires(&self, options: &Options) -> &[&'static str] {
+        self.converter.requires(options)
+    }
+}
+
+/// The output formats whose size is lowered with the quality.
+const LOSSY: [&str; 4] = [JPEG, WEBP, AVIF, GIF];
+/// The output formats whose quality is searched for [`Options::target_dssim`].
+const PERCEPTUAL: [&str; 3] = [JPEG, WEBP, AVIF];
+
+/// The highest DSSIM of an [`AUTO`] candidate without [`Options::target_dssim`].
+pub const AUTO_DSSIM: f64 = 0.01;
+
+/// The output formats tried for [`AUTO`], PNG instead of JPEG for transparent images and lossless sources.
+fn auto_formats(input: &[u8], input_format: &str, reference: &RgbaImage) -> [&'static str; 3] {
+    let lossless = match input_format {
+        PNG | GIF => true,
+        WEBP => input.get(12..16) == Some(b"VP8L"),
+        _ => false,
+    };
+    if lossless || !ssim::is_opaque(reference) {
+        [WEBP, AVIF, PNG]
+    } else {
+        [WEBP, AVIF, JPEG]
+    }
+}
+
+/// Converts with the options, and if the output is bigger than [`Options::max_bytes`]
+/// searches for the highest quality that fits, up to the configured quality.
+/// Every attempt is recorded in the `trace`.
+///
+/// The search assumes the size grows with the quality, which holds for the built-in lossy encoders.
+fn convert_within_max_bytes(
+    converter: &dyn Converter,
+    output_format: &str,
+    input: &[u8],
+    options: &Options,
+    trace: &mut Trace,
+) -> Result<Vec<u8>, Error> {
+    let output = converter.convert_traced(input, options, trace)?;
+    let Some(max_bytes) = options.max_bytes else {
+        return Ok(output);
+    };
+    if output.len() <= max_bytes {
+        return Ok(output);
+    }
+    let mut smallest = output.len();
+    if !LOSSY.contains(&output_format) {
+        return Err(Error::MaxBytesExceeded(max_bytes, smallest));
+    }
+
+    let mut best = None;
+    let mut low = 1;
+    // The configured quality was already too big, no quality means the best the encoder can do.
+    let mut high = options
+        .quality
+        .map_or(100, |quality| quality.min(101).saturating_sub(1));
+    while low <= high {
+        let quality = low + (high - low) / 2;
+        let output = converter.convert_traced(
+            input,
+            &Options {
+                quality: Some(quality),
+                ..options.clone()
+            },
+            trace,
+        )?;
+        if output.len() <= max_bytes {
+            best = Some((quality, output));
+            low = quality + 1;
+        } else {
+            smallest = smallest.min(output.len());
+            high = quality - 1;
+        }
+    }
+    let (quality, output) = best.ok_or(Error::MaxBytesExceeded(max_bytes, smallest))?;
+    trace.set_quality(quality);
+    Ok(output)
+}
+
+/// The conversions needed to get from the input format to the output format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plan {
+    /// The (input format, output format) pair of every step, in order of execution.
+    pub steps: Vec<(String, String)>,
+}
+
+impl Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut formats = self.steps.iter().map(|(input_format, _)| input_format);
+        if let Some(first) = formats.next() {
+            write!(f, "{first}")?;
+        }
+        for (_, output_format) in &self.steps {
+            write!(f, " -> {output_format}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Maps (input format, output format) pairs to converters.
+///
+/// [`Registry::default`] contains the built-in converters,
+/// registering a converter for the same pair replaces the built-in one.
+#[derive(Clone)]
+pub struct Registry {
+    converters: HashMap<(String, String), Arc<dyn Converter>>,
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(std::ffi::OsStr::to_str)
+        .map(str::to_lowercase)
+}
+
+impl Registry {
+    /// Creates a registry without any converters.
+    #[must_use]
+    pub fn empty() -> Self {
+        Registry {
+            converters: HashMap::new(),
+        }
+    }
+
+    /// Registers the converter, replacing the one registered for the same formats.
+    pub fn register<C>(
+        &mut self,
+        input_format: &str,
+        output_format: &str,
+        converter: C,
+    ) -> &mut Self
+    where
+        C: Converter + 'static,
+    {
+        self.converters.insert(
+            (
+                extensions::canonical(input_format),
+                extensions::canonical(output_format),
+            ),
+            Arc::new(converter),
+        );
+        self
+    }
+
+    #[must_use]
+    pub fn get(&self, input_format: &str, output_format: &str) -> Option<&Arc<dyn Converter>> {
+        self.converters.get(&(
+            extensions::canonical(input_format),
+            extensions::canonical(output_format),
+        ))
+    }
+
+    /// Finds the shortest chain of registered converters from `input_format` to `output_format`.
+    /// A direct converter is always preferred, lossless PNG is preferred as an intermediate format.
+    #[must_use]
+    pub fn plan(&self, input_format: &str, output_format: &str) -> Option<Plan> {
+        let input_format = extensions::canonical(input_format);
+        let output_format = extensions::canonical(output_format);
+        if self.get(&input_format, &output_format).is_some() {
+            return Some(Plan {
+                steps: vec![(input_format, output_format)],
+            });
+        }
+        let mut previous: HashMap<&str, &str> = HashMap::new();
+        let mut visited = HashSet::from([input_format.as_str()]);
+        let mut queue = VecDeque::from([input_format.as_str()]);
+
+        while let Some(format) = queue.pop_front() {
+            if format == output_format {
+                let mut steps = Vec::new();
+                let mut current = format;
+                while let Some(&from) = previous.get(current) {
+                    steps.push((from.to_string(), current.to_string()));
+                    current = from;
+                }
+                steps.reverse();
+                return (!steps.is_empty()).then_some(Plan { steps });
+            }
+            let mut next: Vec<&str> = self
+                .converters
+                .keys()
+                .filter(|(from, _)| from == format)
+                .map(|(_, to)| to.as_str())
+                .filter(|to| !visited.contains(to))
+                .collect();
+            next.sort_by_key(|to| (*to != output_format, *to != PNG, *to));
+            for to in next {
+                visited.insert(to);
+                previous.insert(to, format);
+                queue.push_back(to);
+            }
+        }
+
+        None
+    }
+
+    /// Converts the image data of a known `input_format` to the `output_format`.
+    /// Use it for input formats that cannot be detected from the data.
+    ///
+    /// Without a direct converter the conversion is chained through intermediate formats, see [`Registry::plan`].
+    /// The options are applied only by the last step, the intermediate steps neither resize nor lower the quality.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no chain of converters exists or any step fails.
+    ///
+    pub fn convert_from(
+        &self,
+        input: &[u8],
+        input_format: &str,
+        output_format: &str,
+        options: &Options,
+    ) -> Result<Vec<u8>, Error> {
+        if extensions::canonical(output_format) == AUTO {
+            let (_, output, _) =
+                self.convert_auto(input, input_format, options, &mut Trace::default())?;
+            return Ok(output);
+        }
+        let plan = self.plan_or_error(input_format, output_format)?;
+        self.run(&plan, input, options, &mut Trace::default())
+    }
+
+    fn plan_or_error(&self, input_format: &str, output_format: &str) -> Result<Plan, Error> {
+        self.plan(input_format, output_format).ok_or_else(|| {
+            Error::UnsupportedConversion(
+                extensions::canonical(input_format),
+                extensions::canonical(output_format),
+            )
+        })
+    }
+
+    fn run(
+        &self,
+        plan: &Plan,
+        input: &[u8],
+        options: &Options,
+        trace: &mut Trace,
+    ) -> Result<Vec<u8>, Error> {
+        // The DSSIM search decodes every attempt, so a missing decoder fails before the first one.
+        if let Some((_, to)) = plan.steps.last() {
+            if options.target_dssim.is_some() && PERCEPTUAL.contains(&to.as_str()) {
+                self.require_decoder(to)?;
+            }
+        }
+        let intermediate_options = Options::default();
+        for (index, (from, to)) in plan.steps.iter().enumerate() {
+            if let Some(converter) = self.get(from, to) {
+                let options = if index + 1 < plan.steps.len() {
+                    &intermediate_options
+                } else {
+                    options
+                };
+                capabilities().require(converter.requires(options))?;
+            }
+        }
+        let mut chunks = match plan.steps.first() {
+            Some((input_format, _)) if options.metadata != Metadata::StripAll => {
+                options.metadata.select(metadata::read(input, input_format))
+            }
+            _ => Chunks::default(),
+        };
+        // The pixels are converted to the color profile, so it replaces the profile of the input.
+        if options.embed_profile || chunks.icc.is_some() {
+            chunks.icc = Some(options.color_profile.icc());
+        }
+        let mut data = input.to_vec();
+        for (index, (from, to)) in plan.steps.iter().enumerate() {
+            if let Some(converter) = self.get(from, to) {
+                trace.set_conversion(from, to);
+                if index + 1 < plan.steps.len() {
+                    data = converter.convert_traced(&data, &intermediate_options, trace)?;
+                    continue;
+                }
+                let converter = WithMetadata {
+                    converter: converter.as_ref(),
+                    output_format: to,
+                    chunks: &chunks,
+                };
+                data = if let Some(target) = options
+                    .target_dssim
+                    .filter(|_| PERCEPTUAL.contains(&to.as_str()))
+                {
+                    self.convert_to_dssim(&converter, (from, to), &data, options, target, trace)?
+                } else {
+                    convert_within_max_bytes(&converter, to, &data, options, trace)?
+                };
+            }
+        }
+        Ok(data)
+    }
+
+    /// Converts with the lowest quality whose DSSIM compared with the resized source is at most the `target`,
+    /// or with the highest quality if none is low enough. The DSSIM and the quality are recorded in the `trace`.
+    ///
+    /// The source is resized by the conversion to PNG, both images are decoded with the `image` crate
+    /// or with the registered converters to PNG for the formats it cannot read, e.g. AVIF.
+    fn convert_to_dssim(
+        &self,
+        converter: &dyn Converter,
+        (input_format, output_format): (&str, &str),
+        input: &[u8],
+        options: &Options,
+        target: f64,
+        trace: &mut Trace,
+    ) -> Result<Vec<u8>, Error> {
+        let reference =
+            self.convert_from(input, input_format, PNG, &Options::resize_of(options))?;
+        let reference = self.decode(&reference, PNG)?;
+
+        let mut best = None;
+        let mut highest = None;
+        let (mut low, mut high) = (1, 100);
+        while low <= high {
+            let quality = low + (high - low) / 2;
+            let output = converter.convert_traced(
+                input,
+                &Options {
+                    quality: Some(quality),
+                    ..options.clone()
+                },
+                trace,
+            )?;
+            let dssim = ssim::dssim(&reference, &self.decode(&output, output_format)?)
+                .map_err(Error::Metrics)?;
+            if dssim <= target {
+                best = Some((quality, dssim, output));
+                high = quality - 1;
+            } else {
+                highest = Some((quality, dssim, output));
+                low = quality + 1;
+            }
+        }
+        let Some((quality, dssim, output)) = best.or(highest) else {
+            unreachable!("the quality range is never empty")
+        };
+        trace.set_quality(quality);
+        trace.set_dssim(dssim);
+
+        match options.max_bytes {
+            Some(max_bytes) if output.len() > max_bytes => {
+                Err(Error::MaxBytesExceeded(max_bytes, output.len()))
+            }
+            _ => Ok(output),
+        }
+    }
+
+    /// Converts to every format of [`auto_formats`] and keeps the smallest output whose DSSIM
+    /// is at most [`Options::target_dssim`] or [`AUTO_DSSIM`], or the one with the lowest DSSIM if none is.
+    /// Returns the plan of the kept output and every candidate, the steps of all of them are recorded in the `trace`.
+    ///
+    /// A format the registry cannot decode to measure its DSSIM, e.g. AVIF without libheif, is never chosen,
+    /// so it is skipped before encoding like a format whose conversion is not registered, not available or over [`Options::max_bytes`] is skipped.
+    fn convert_auto(
+        &self,
+        input: &[u8],
+        input_format: &str,
+        options: &Options,
+        trace: &mut Trace,
+    ) -> Result<(Plan, Vec<u8>, Vec<Candidate>), Error> {
+        let reference =
+            self.convert_from(input, input_format, PNG, &Options::resize_of(options))?;
+        let reference = self.decode(&reference, PNG)?;
+        let threshold = options.target_dssim.unwrap_or(AUTO_DSSIM);
+
+        let mut outputs = Vec::new();
+        let mut skipped = None;
+        for format in auto_formats(input, input_format, &reference) {
+            // An output that cannot be decoded has no DSSIM, so it could never be chosen.
+            if let Err(error) = self.require_decoder(format) {
+                skipped.get_or_insert(error);
+                continue;
+            }
+            let mut candidate_trace = Trace::default();
+            let result = self.plan_or_error(input_format, format).and_then(|plan| {
+                Ok((self.run(&plan, input, options, &mut candidate_trace)?, plan))
+            });
+            let (quality, dssim) = (candidate_trace.quality(), candidate_trace.dssim());
+            trace.append(candidate_trace);
+            let (output, plan) = match result {
+                Ok(result) => result,
+                Err(
+                    error @ (Error::UnsupportedConversion(..)
+                    | Error::BackendUnavailable(..)
+                    | Error::MaxBytesExceeded(..)),
+                ) => {
+                    skipped.get_or_insert(error);
+                    continue;
+                }
+                Err(error) => return Err(error),
+            };
+            let dssim = match dssim {
+                Some(dssim) => dssim,
+                None => ssim::dssim(&reference, &self.decode(&output, format)?)
+                    .map_err(Error::Metrics)?,
+            };
+            let candidate = Candidate {
+                format: format.to_string(),
+                size: output.len(),
+                quality,
+                dssim,
+            };
+            outputs.push((candidate, plan, output));
+        }
+
+        let meets = |candidate: &Candidate| candidate.dssim <= threshold;
+        // The smallest output that meets the threshold, or the closest to it.
+        let (best, _) = outputs
+            .iter()
+            .enumerate()
+            .min_by(|(_, (a, ..)), (_, (b, ..))| {
+                meets(b).cmp(&meets(a)).then_with(|| {
+                    if meets(a) {
+                        a.size.cmp(&b.size)
+                    } else {
+                        a.dssim.total_cmp(&b.dssim)
+                    }
+                })
+            })
+            .ok_or_else(|| {
+                skipped.unwrap_or_else(|| {
+                    Error::UnsupportedConversion(
+                        extensions::canonical(input_format),
+                        AUTO.to_string(),
+                    )
+                })
+            })?;
+        let candidates = outputs
+            .iter()
+            .map(|(candidate, ..)| candidate.clone())
+            .collect();
+        let (candidate, plan, output) = outputs.swap_remove(best);
+        if let Some(quality) = candidate.quality {
+            trace.set_quality(quality);
+        }
+        trace.set_dssim(candidate.dssim);
+        Ok((plan, output, candidates))
+    }
+
+    /// Checks that [`Registry::decode`] can read the format, the `image` crate reads every built-in format but AVIF,
+    /// which is converted to PNG by the registered converters whose capabilities must be available.
+    fn require_decoder(&self, format: &str) -> Result<(), Error> {
+        if extensions::canonical(format) != AVIF {
+            return Ok(());
+        }
+        for (from, to) in self.plan_or_error(AVIF, PNG)?.steps {
+            if let Some(converter) = self.get(&from, &to) {
+                capabilities().require(converter.requires(&Options::default()))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Decodes both images and measures how much the `image` differs from the `reference`,
+    /// e.g. the source and the converted image resized to the same dimensions.
+    ///
+    /// Formats the `image` crate cannot read are decoded by the registered converters to PNG.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an image cannot be decoded or the images have different dimensions.
+    ///
+    pub fn compare(&self, reference: &[u8], image: &[u8]) -> Result<Comparison, Error> {
+        let decode = |data: &[u8]| -> Result<(ImageInfo, RgbaImage), Error> {
+            let format = extensions::from_magic_bytes(data).ok_or(Error::UnsupportedInputFormat)?;
+            let pixels = self.decode(data, format)?;
+            let info = ImageInfo {
+                format: format.to_string(),
+                dimensions: pixels.dimensions(),
+                size: data.len(),
+            };
+            Ok((info, pixels))
+        };
+        let (reference, reference_pixels) = decode(reference)?;
+        let (image, image_pixels) = decode(image)?;
+        Ok(Comparison {
+            reference,
+            image,
+            metrics: comparison::metrics(&reference_pixels, &image_pixels)?,
+        })
+    }
+
+    /// Decodes the image data, converting it to PNG first when the `image` crate cannot read the format.
+    fn decode(&self, data: &[u8], format: &str) -> Result<RgbaImage, Error> {
+        match ssim::decode(data) {
+            Err(ssim::Error::Image(image::ImageError::Unsupported(_))) => {
+                let png = self.convert_from(data, format, PNG, &Options::default())?;
+                ssim::decode(&png).map_err(Error::Metrics)
+            }
+            result => result.map_err(Error::Metrics),
+        }
+    }
+
+    /// Converts the image data to the `output_format`, given as one of the [`extensions`].
+    /// The input format is detected from the data.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// * The input data does not match any supported format
+    /// * The conversion is not registered
+    /// * An error occurs during the conversion
+    ///
+    pub fn convert_bytes(
+        &self,
+        input: &[u8],
+        output_format: &str,
+        options: &Options,
+    ) -> Result<Vec<u8>, Error> {
+        let input_format = self.input_format(input, None)?;
+        self.convert_from(input, &input_format, output_format, options)
+    }
+
+    /// Detects the input format from the data, or takes it from the `extension` of the input, e.g. of its file name,
+    /// when the data does not match any format [`extensions::from_magic_bytes`] detects,
+    /// so the input formats of custom converters are found too.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::UnsupportedInputFormat`] if the data does not match any detected format
+    /// and the extension is not the input format of a registered converter.
+    ///
+    pub fn input_format(&self, input: &[u8], extension: Option<&str>) -> Result<String, Error> {
+        if let Some(format) = extensions::from_magic_bytes(input) {
+            return Ok(format.to_string());
+        }
+        // The content of a detected format would have been recognized, so its extension is only a wrong name.
+        extension
+            .map(extensions::canonical)
+            .filter(|format| {
+                !extensions::is_detected(format)
+                    && self.converters.keys().any(|(from, _)| from == format)
+            })
+            .ok_or(Error::UnsupportedInputFormat)
+    }
+
+    /// Reads the whole image from `reader` and writes the converted image to `writer`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading, writing or the conversion fails, see [`Registry::convert_bytes`].
+    ///
+    pub fn convert_stream<R, W>(
+        &self,
+        reader: &mut R,
+        writer: &mut W,
+        output_format: &str,
+        options: &Options,
+    ) -> Result<(), Error>
+    where
+        R: Read,
+        W: Write,
+    {
+        let mut input = Vec::new();
+        reader.read_to_end(&mut input).map_err(Error::Io)?;
+        let output = self.convert_bytes(&input, output_format, options)?;
+        writer.write_all(&output).map_err(Error::Io)
+    }
+
+    /// Converts the input file to the output file, the output format is taken from the output path extension.
+    /// For the [`AUTO`] extension the extension is replaced by the format that was kept, e.g. `logo.auto` by `logo.webp`.
+    /// Returns a report with the formats, dimensions and sizes of both images and the timing of every step.
+    ///
+    /// The input format is detected from the content, the extension of the input path is used
+    /// only for content of no detected format, see [`Registry::input_format`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// * The output file path has no extension
+    /// * The input file content does not match any detected format and the input file path has no extension
+    /// * The input file cannot be read or the output file cannot be written
+    /// * The conversion fails, see [`Registry::convert_bytes`]
+    ///
+    pub fn convert(&self, config: &Config) -> Result<ConversionReport, Error> {
+        let start = Instant::now();
+        let output_format =
+            extension(config.output_path()).ok_or(Error::OutputFileHasNoExtension)?;
+        let input = read(config.input_path()).map_err(Error::Io)?;
+        let input_extension = extension(config.input_path());
+        let input_format = match self.input_format(&input, input_extension.as_deref()) {
+            Err(Error::UnsupportedInputFormat) if input_extension.is_none() => {
+                Err(Error::InputFileHasNoExtension)
+            }
+            result => result,
+        }?;
+        let (output, report) = self.convert_from_with_report(
+            &input,
+            &input_format,
+            &output_format,
+            &Options::from(config),
+        )?;
+
+        if let Some(parent) = config.output_path().parent() {
+            create_dir_all(parent).map_err(Error::Io)?;
+        }
+        let output_path = if extensions::canonical(&output_format) == AUTO {
+            config.output_path().with_extension(&report.output_format)
+        } else {
+            config.output_path().clone()
+        };
+        write(output_path, output).map_err(Error::Io)?;
+
+        Ok(ConversionReport {
+            duration: start.elapsed(),
+            ..report
+        })
+    }
+
+    /// Converts the image data like [`Registry::convert_bytes`] and returns the report of the conversion with it.
+    ///
+    /// For the [`AUTO`] output format the image is converted to WebP, AVIF and JPEG, or PNG instead of JPEG
+    /// for transparent images and lossless sources. The smallest output whose DSSIM compared with the resized source
+    /// is at most [`Options::target_dssim`] or [`AUTO_DSSIM`] is kept, its format is the output format of the report
+    /// and every format tried is in [`ConversionReport::candidates`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the conversion fails, see [`Registry::convert_bytes`].
+    ///
+    pub fn convert_bytes_with_report(
+        &self,
+        input: &[u8],
+        output_format: &str,
+        options: &Options,
+    ) -> Result<(Vec<u8>, ConversionReport), Error> {
+        let input_format = self.input_format(input, None)?;
+        self.convert_from_with_report(input, &input_format, output_format, options)
+    }
+
+    /// Converts the image data of a known `input_format` like [`Registry::convert_from`]
+    /// and returns the report of the conversion with it, see [`Registry::convert_bytes_with_report`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the conversion fails, see [`Registry::convert_from`].
+    ///
+    pub fn convert_from_with_report(
+        &self,
+        input: &[u8],
+        input_format: &str,
+        output_format: &str,
+        options: &Options,
+    ) -> Result<(Vec<u8>, ConversionReport), Error> {
+        let start = Instant::now();
+        let input_format = extensions::canonical(input_format);
+        let input_format = input_format.as_str();
+        let mut trace = Trace::default();
+        let (plan, output, candidates) = if extensions::canonical(output_format) == AUTO {
+            self.convert_auto(input, input_format, options, &mut trace)?
+        } else {
+            let plan = self.plan_or_error(input_format, output_format)?;
+            let output = self.run(&plan, input, options, &mut trace)?;
+            (plan, output, Vec::new())
+        };
+        // The last step writes the output, for `auto` in the format that was kept.
+        let output_format = plan
+            .steps
+            .last()
+            .map_or(output_format, |(_, to)| to.as_str());
+
+        let report = ConversionReport {
+            input_format: input_format.to_string(),
+            input_dimensions: report::dimensions(input, input_format),
+            input_size: input.len(),
+            output_format: extensions::canonical(output_format),
+            output_dimensions: report::dimensions(&output, output_format),
+            output_size: output.len(),
+            converter: plan.to_string(),
+            quality: trace.quality(),
+            dssim: trace.dssim(),
+            candidates,
+            steps: trace.into_steps(),
+            duration: start.elapsed(),
+        };
+        Ok((output, report))
+    }
+}
+
+impl Default for Registry {
+    /// Creates a registry with the built-in converters.
+    #[allow(clippy::too_many_lines)]
+    fn default() -> Self {
+        let mut registry = Registry::empty();
+        registry
+            .register(
+                GIF,
+                GIF,
+                Builtin(
+                    |input, options, trace| {
+                        gif2gif::convert_bytes(input, options, trace).map_err(Error::Gif2Gif)
+                    },
+                    requires_gif_optimizer,
+                ),
+            )
+            .register(
+                GIF,
+                WEBP,
+                Builtin(
+                    |input, options, trace| {
+                        gif2webp::convert_bytes(input, options, trace).map_err(Error::Gif2Webp)
+                    },
+                    requires_webp_encoder,
+                ),
+            )
+            .register(
+                GIF,
+                AVIF,
+                Builtin(
+                    |input, options, trace| {
+                        gif2avif::convert_bytes(input, options, trace).map_err(Error::Gif2Avif)
+                    },
+                    requires_nothing,
+                ),
+            )
+            .register(
+                GIF,
+                PNG,
+                Builtin(
+                    |input, options, trace| {
+                        gif2png::convert_bytes(input, options, trace).map_err(Error::Gif2Png)
+                    },
+                    requires_raster,
+                ),
+            )
+            .register(
+                PNG,
+                PNG,
+                Builtin(
+                    |input, options, trace| {
+                        png2png::convert_bytes(input, options, trace).map_err(Error::Png2Png)
+                    },
+                    requires_raster,
+                ),
+            )
+            .register(
+                PNG,
+                JPEG,
+                Builtin(
+                    |input, options, trace| {
+                        png2jpeg::convert_bytes(input, options, trace).map_err(Error::Png2Jpeg)
+                    },
+                    requires_jpeg_encoder,
+                ),
+            )
+            .register(
+                PNG,
+                WEBP,
+                Builtin(
+                    |input, options, trace| {
+                        png2webp::convert_bytes(input, options, trace).map_err(Error::Png2Webp)
+                    },
+                    requires_webp_encoder,
+                ),
+            )
+            .register(
+                PNG,
+                AVIF,
+                Builtin(
+                    |input, options, trace| {
+                        png2avif::convert_bytes(input, options, trace).map_err(Error::Png2Avif)
+                    },
+                    requires_nothing,
+                ),
+            )
+            .register(
+                PNG,
+                GIF,
+                Builtin(
+                    |input, options, trace| {
+                        png2gif::convert_bytes(input, options, trace).map_err(Error::Png2Gif)
+                    },
+                    requires_raster,
+                ),
+            )
+            .register(
+                JPEG,
+                JPEG,
+                Builtin(
+                    |input, options, trace| {
+                        jpeg2jpeg::convert_bytes(input, options, trace).map_err(Error::Jpeg2Jpeg)
+                    },
+                    requires_jpeg_encoder,
+                ),
+            )
+            .register(
+                JPEG,
+                WEBP,
+                Builtin(
+                    |input, options, trace| {
+                        jpeg2webp::convert_bytes(input, options, trace).map_err(Error::Jpeg2Webp)
+                    },
+                    requires_webp_encoder,
+                ),
+            )
+            .register(
+                JPEG,
+                AVIF,
+                Builtin(
+                    |input, options, trace| {
+                        jpeg2avif::convert_bytes(input, options, trace).map_err(Error::Jpeg2Avif)
+                    },
+                    requires_nothing,
+                ),
+            )
+            .register(
+                JPEG,
+                PNG,
+                Builtin(
+                    |input, options, trace| {
+                        jpeg2png::convert_bytes(input, options, trace).map_err(Error::Jpeg2Png)
+                    },
+                    requires_raster,
+                ),
+            )
+            .register(
+                WEBP,
+                WEBP,
+                Builtin(
+                    |input, options, trace| {
+                        webp2webp::convert_bytes(input, options, trace).map_err(Error::Webp2Webp)
+                    },
+                    requires_webp_encoder,
+                ),
+            )
+            .register(
+                WEBP,
+                JPEG,
+                Builtin(
+                    |input, options, trace| {
+                        webp2jpeg::convert_bytes(input, options, trace).map_err(Error::Webp2Jpeg)
+                    },
+                    requires_jpeg_encoder,
+                ),
+            )
+            .register(
+                WEBP,
+                AVIF,
+                Builtin(
+                    |input, options, trace| {
+                        webp2avif::convert_bytes(input, options, trace).map_err(Error::Webp2Avif)
+                    },
+                    requires_nothing,
+                ),
+            )
+            .register(
+                WEBP,
+                PNG,
+                Builtin(
+                    |input, options, trace| {
+                        webp2png::convert_bytes(input, options, trace).map_err(Error::Webp2Png)
+                    },
+                    requires_raster,
+                ),
+            )
+            .register(
+                AVIF,
+                PNG,
+                Builtin(
+                    |input, options, trace| {
+                        avif2png::convert_bytes(input, options, trace).map_err(Error::Avif2Png)
+                    },
+                    requires_avif_decoder,
+                ),
+            );
+        registry
+    }
+}
+
+/// The registry with the built-in converters used by the free functions of the crate.
+pub(crate) fn default_registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(Registry::default)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Error, Options};
+
+    #[allow(clippy::unnecessary_wraps)]
+    fn fake_webp(_input: &[u8], _options: &Options) -> Result<Vec<u8>, Error> {
+        Ok(b"RIFF\0\0\0\0WEBP".to_vec())
+    }
+
+    #[test]
+    fn register_overrides_builtin() {
+        use super::*;
+
+        let mut registry = Registry::default();
+        registry.register(PNG, WEBP, fake_webp);
+
+        let output = registry
+            .convert_bytes(
+                include_bytes!("../tests/files/convert_test1.png"),
+                WEBP,
+                &Options::default(),
+            )
+            .unwrap();
+
+        assert_eq!(output, b"RIFF\0\0\0\0WEBP");
+    }
+
+    #[test]
+    fn register_custom_input_format() {
+        use super::*;
+
+        let mut registry = Registry::empty();
+        registry.register("bmp", WEBP, fake_webp);
+
+        assert!(registry.get("BMP", WEBP).is_some());
+        assert!(registry
+            .convert_from(b"BM", "bmp", WEBP, &Options::default())
+            .is_ok());
+    }
+
+    #[test]
+    fn input_format_from_extension() {
+        use super::*;
+
+        let mut registry = Registry::default();
+        registry.register("bmp", WEBP, fake_webp);
+        std::fs::write("target/custom_input.bmp", b"BM").unwrap();
+
+        let report = registry
+            .convert(&Config::new(
+                "target/custom_input.bmp",
+                "target/custom_input.webp",
+                None,
+                None,
+            ))
+            .unwrap();
+
+        assert_eq!(report.input_format, "bmp");
+        assert_eq!(
+            registry.input_format(b"BM", Some("BMP")).unwrap(),
+            "bmp".to_string()
+        );
+        // Detected content wins over the extension, a detected format is never taken from the extension.
+        assert_eq!(
+            registry
+                .input_format(include_bytes!("../tests/files/issue-159.png"), Some("bmp"))
+                .unwrap(),
+            PNG
+        );
+        assert!(matches!(
+            registry.input_format(b"BM", Some(PNG)),
+            Err(Error::UnsupportedInputFormat)
+        ));
+        assert!(matches!(
+            registry.input_format(b"BM", Some("tiff")),
+            Err(Error::UnsupportedInputFormat)
+        ));
+    }
+
+    #[test]
+    fn aliases_share_converter() {
+        use super::*;
+
+        let registry = Registry::default();
+
+        assert!(registry.get("jpg", "jfif").is_some());
+        assert!(registry.get(JPEG, "JPG").is_some());
+    }
+
+    #[test]
+    fn plan_direct() {
+        use super::*;
+
+        let plan = Registry::default().plan("jpg", WEBP).unwrap();
+
+        assert_eq!(plan.steps, vec![(JPEG.to_string(), WEBP.to_string())]);
+    }
+
+    #[test]
+    fn plan_chain_prefers_png() {
+        use super::*;
+
+        let plan = Registry::default().plan(WEBP, GIF).unwrap();
+
+        assert_eq!(plan.to_string(), "webp -> png -> gif");
+    }
+
+    #[test]
+    fn plan_builtin_chains() {
+        use super::*;
+
+        let registry = Registry::default();
+
+        for input_format in [GIF, PNG, JPEG, WEBP] {
+            for output_format in [GIF, PNG, JPEG, WEBP, AVIF] {
+                assert!(registry.plan(input_format, output_format).is_some());
+            }
+        }
+        // AVIF is read only by the libheif delegate of imagemagick, which the plan does not check.
+        assert!(registry.plan(AVIF, PNG).is_some());
+    }
+
+    #[test]
+    fn convert_chain() {
+        use super::*;
+
+        let mut registry = Registry::empty();
+        registry
+            .register("bmp", PNG, |input: &[u8], options: &Options| {
+                assert!(options.width.is_none());
+                Ok([input, b"|png"].concat())
+            })
+            .register(PNG, WEBP, |input: &[u8], options: &Options| {
+                assert_eq!(options.width, Some(100));
+                Ok([input, b"|webp"].concat())
+            });
+
+        let output = registry
+            .convert_from(
+                b"bmp",
+                "bmp",
+                WEBP,
+                &Options {
+                    width: Some(100),
+                    ..Options::default()
+                },
+            )
+            .unwrap();
+
+        assert_eq!(output, b"bmp|png|webp");
+    }
+
+    #[test]
+    fn convert_refused_without_capability() {
+        use super::*;
+
+        struct Missing;
+
+        impl Converter for Missing {
+            fn convert(&self, _input: &[u8], _options: &Options) -> Result<Vec<u8>, Error> {
+                unreachable!("the conversion must be refused before it runs")
+            }
+
+            fn requires(&self, _options: &Options) -> &[&'static str] {
+                &["missing"]
+            }
+        }
+
+        let mut registry = Registry::empty();
+        registry
+            .register("bmp", PNG, |input: &[u8], _options: &Options| {
+                Ok(input.to_vec())
+            })
+            .register(PNG, WEBP, Missing);
+
+        let result = registry.convert_from(b"bmp", "bmp", WEBP, &Options::default());
+
+        assert!(matches!(result, Err(Error::BackendUnavailable(name, _)) if name == "missing"));
+    }
+
+    #[test]
+    #[cfg(not(feature = "native"))]
+    fn convert_lossy_webp_refused_without_libwebp() {
+        use super::*;
+        use crate::EncoderOptions;
+
+        let registry = Registry::default();
+        let input = include_bytes!("../tests/files/issue-159.png");
+
+        for options in [
+            Options {
+                quality: Some(80),
+                ..Options::default()
+            },
+            Options {
+                max_bytes: Some(100_000),
+                ..Options::default()
+            },
+            Options {
+                target_dssim: Some(0.01),
+                ..Options::default()
+            },
+            Options {
+                encoder: EncoderOptions {
+                    webp: WebpOptions {
+                        method: Some(6),
+                        ..WebpOptions::default()
+                    },
+                    ..EncoderOptions::default()
+                },
+                ..Options::default()
+            },
+        ] {
+            let result = registry.convert_from(input, PNG, WEBP, &options);
+
+            assert!(
+                matches!(result, Err(Error::BackendUnavailable(ref name, _)) if name == LIBWEBP),
+                "{options:?}"
+            );
+        }
+        assert!(registry
+            .convert_from(input, PNG, WEBP, &Options::default())
+            .is_ok());
+    }
+
+    #[test]
+    #[cfg(not(feature = "native"))]
+    fn convert_typed_options_refused_without_backend() {
+        use super::*;
+        use crate::EncoderOptions;
+
+        let registry = Registry::default();
+        let png = include_bytes!("../tests/files/issue-159.png");
+        let gif = include_bytes!("../tests/files/gif2gif_test1.gif");
+        let unavailable = |result: Result<Vec<u8>, Error>, capability: &str| match result {
+            Err(Error::BackendUnavailable(name, _)) => name == capability,
+            _ => false,
+        };
+
+        let jpeg = Options {
+            encoder: EncoderOptions {
+                jpeg: JpegOptions {
+                    progressive: true,
+                    ..JpegOptions::default()
+                },
+                ..EncoderOptions::default()
+            },
+            ..Options::default()
+        };
+        assert!(unavailable(
+            registry.convert_from(png, PNG, JPEG, &jpeg),
+            IMAGEMAGICK
+        ));
+        assert!(registry
+            .convert_from(png, PNG, JPEG, &Options::default())
+            .is_ok());
+
+        for options in [
+            Options {
+                quality: Some(80),
+                ..Options::default()
+            },
+            Options {
+                max_bytes: Some(100_000),
+                ..Options::default()
+            },
+            Options {
+                encoder: EncoderOptions {
+                    gif: GifOptions {
+                        colors: Some(16),
+                        ..GifOptions::default()
+                    },
+                    ..EncoderOptions::default()
+                },
+                ..Options::default()
+            },
+        ] {
+            assert!(
+                unavailable(registry.convert_from(gif, GIF, GIF, &options), GIFSICLE),
+                "{options:?}"
+            );
+        }
+        assert!(registry
+            .convert_from(gif, GIF, GIF, &Options::default())
+            .is_ok());
+    }
+
+    /// Writes `quality` times 10 bytes, 1000 without a quality.
+    #[allow(clippy::unnecessary_wraps)]
+    fn sized_by_quality(_input: &[u8], options: &Options) -> Result<Vec<u8>, Error> {
+        Ok(vec![0; options.quality.unwrap_or(100) as usize * 10])
+    }
+
+    #[test]
+    fn max_bytes_highest_quality() {
+        use super::*;
+
+        let mut registry = Registry::empty();
+        registry.register(PNG, JPEG, sized_by_quality);
+
+        let convert = |quality, max_bytes| {
+            registry.convert_from(
+                b"png",
+                PNG,
+                JPEG,
+                &Options {
+                    quality,
+                    max_bytes: Some(max_bytes),
+                    ..Options::default()
+                },
+            )
+        };
+
+        assert_eq!(convert(None, 555).unwrap().len(), 550);
+        assert_eq!(convert(None, 10).unwrap().len(), 10);
+        assert_eq!(convert(Some(80), 2000).unwrap().len(), 800);
+        assert_eq!(convert(Some(80), 799).unwrap().len(), 790);
+        assert!(matches!(
+            convert(None, 9),
+            Err(Error::MaxBytesExceeded(9, 10))
+        ));
+    }
+
+    #[test]
+    fn max_bytes_lossless() {
+        use super::*;
+
+        let mut registry = Registry::empty();
+        registry.register(JPEG, PNG, sized_by_quality);
+
+        let result = registry.convert_from(
+            b"jpeg",
+            JPEG,
+            PNG,
+            &Options {
+                max_bytes: Some(999),
+                ..Options::default()
+            },
+        );
+
+        assert!(matches!(result, Err(Error::MaxBytesExceeded(999, 1000))));
+    }
+
+    #[allow(clippy::unnecessary_wraps)]
+    fn png_identity(input: &[u8], _options: &Options) -> Result<Vec<u8>, Error> {
+        Ok(input.to_vec())
+    }
+
+    /// Prefixes the PNG so that it cannot be read by the `image` crate.
+    #[allow(clippy::unnecessary_wraps)]
+    fn fake_avif(input: &[u8], _options: &Options) -> Result<Vec<u8>, Error> {
+        Ok([b"avif", input].concat())
+    }
+
+    /// Encodes JPEG with the `image` crate at the quality of the options.
+    fn image_jpeg(input: &[u8], options: &Options) -> Result<Vec<u8>, Error> {
+        let image = image::load_from_memory(input).map_err(|error| Error::Custom(error.into()))?;
+        let mut output = Vec::new();
+        #[allow(clippy::cast_possible_truncation)]
+        let quality = options.quality.unwrap_or(75) as u8;
+        image
+            .to_rgb8()
+            .write_with_encoder(image::codecs::jpeg::JpegEncoder::new_with_quality(
+                &mut output,
+                quality,
+            ))
+            .map_err(|error| Error::Custom(error.into()))?;
+        Ok(output)
+    }
+
+    #[test]
+    fn target_dssim_lowest_quality() {
+        use super::*;
+
+        let mut registry = Registry::empty();
+        registry
+            .register(PNG, PNG, png_identity)
+            .register(PNG, JPEG, image_jpeg);
+        let input = include_bytes!("../tests/files/convert_test1.png");
+
+        let convert = |target_dssim| {
+            registry
+                .convert_bytes_with_report(
+                    input,
+                    JPEG,
+                    &Options {
+                        target_dssim: Some(target_dssim),
+                        ..Options::default()
+                    },
+                )
+                .unwrap()
+                .1
+        };
+        let loose = convert(0.02);
+        let strict = convert(0.002);
+
+        assert!(loose.dssim.unwrap() <= 0.02);
+        assert!(strict.dssim.unwrap() <= 0.002);
+        assert!(loose.quality.unwrap() < strict.quality.unwrap());
+        assert!(loose.output_size < strict.output_size);
+    }
+
+    #[test]
+    fn target_dssim_decodes_with_registered_converter() {
+        use super::*;
+
+        let mut registry = Registry::empty();
+        registry
+            .register(PNG, PNG, png_identity)
+            .register(PNG, AVIF, fake_avif)
+            .register(AVIF, PNG, |input: &[u8], _options: &Options| {
+                Ok(input[b"avif".len()..].to_vec())
+            });
+
+        let (_, report) = registry
+            .convert_bytes_with_report(
+                include_bytes!("../tests/files/convert_test1.png"),
+                AVIF,
+                &Options {
+                    target_dssim: Some(0.001),
+                    ..Options::default()
+                },
+            )
+            .unwrap();
+
+        assert_eq!(report.quality, Some(1));
+        assert!(report.dssim.unwrap().abs() < 1e-6);
+    }
+
+    #[test]
+    #[should_panic = "UnsupportedConversion(\"avif\", \"png\")"]
+    fn target_dssim_without_decoder_panic() {
+        use super::*;
+
+        let mut registry = Registry::empty();
+        registry
+            .register(PNG, PNG, png_identity)
+            .register(PNG, AVIF, fake_avif);
+
+        registry
+            .convert_bytes(
+                include_bytes!("../tests/files/convert_test1.png"),
+                AVIF,
+                &Options {
+                    target_dssim: Some(0.001),
+                    ..Options::default()
+                },
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn target_dssim_avif_decoded_with_libheif() {
+        use super::*;
+
+        let result = Registry::default().convert_bytes_with_report(
+            include_bytes!("../tests/files/convert_test1.png"),
+            AVIF,
+            &Options {
+                width: Some(100),
+                target_dssim: Some(0.01),
+                ..Options::default()
+            },
+        );
+
+        if capabilities().require(&[LIBHEIF]).is_ok() {
+            assert!(result.unwrap().1.dssim.is_some());
+        } else {
+            assert!(matches!(result, Err(Error::BackendUnavailable(name, _)) if name == LIBHEIF));
+        }
+    }
+
+    /// A registry writing JPEG as WebP for `auto`, and AVIF that cannot be decoded, so `auto` skips it.
+    fn auto_registry() -> super::Registry {
+        use super::*;
+
+        let mut registry = Registry::empty();
+        registry
+            .register(PNG, PNG, png_identity)
+            .register(PNG, WEBP, image_jpeg)
+            .register(PNG, AVIF, fake_avif);
+        registry
+    }
+
+    /// A photo as PNG, which is bigger than the lossy formats.
+    fn photo_png() -> Vec<u8> {
+        let photo = image::load_from_memory(include_bytes!("../tests/files/orientation_test.jpg"))
+            .unwrap()
+            .thumbnail(128, 128);
+        let mut output = Vec::new();
+        photo
+            .write_to(
+                &mut std::io::Cursor::new(&mut output),
+                image::ImageFormat::Png,
+            )
+            .unwrap();
+        output
+    }
+
+    #[test]
+    fn auto_smallest_within_threshold() {
+        use super::*;
+
+        let input = photo_png();
+        let (output, report) = auto_registry()
+            .convert_bytes_with_report(&input, AUTO, &Options::default())
+            .unwrap();
+
+        assert_eq!(report.output_format, WEBP);
+        assert_eq!(report.converter, "png -> webp");
+        assert_eq!(report.output_size, output.len());
+        assert!(report.dssim.is_some_and(|dssim| dssim <= AUTO_DSSIM));
+        // AVIF cannot be decoded by this registry, so it is not converted.
+        let formats: Vec<_> = report
+            .candidates
+            .iter()
+            .map(|candidate| (candidate.format.as_str(), candidate.size))
+            .collect();
+        assert_eq!(formats, vec![(WEBP, output.len()), (PNG, input.len())]);
+        assert!(report.candidates[1].dssim.abs() < 1e-6);
+    }
+
+    #[test]
+    fn auto_rejects_outputs_over_threshold() {
+        use super::*;
+
+        let input = photo_png();
+        let convert = |target_dssim| {
+            auto_registry()
+                .convert_bytes_with_report(
+                    &input,
+                    AUTO,
+                    &Options {
+                        quality: Some(5),
+                        target_dssim,
+                        ..Options::default()
+                    },
+                )
+                .unwrap()
+        };
+
+        let (output, report) = convert(None);
+        assert_eq!(report.output_format, PNG);
+        assert_eq!(output, input);
+        assert!(report.candidates[0].dssim > AUTO_DSSIM);
+        assert!(report.candidates[0].size < input.len());
+
+        let (_, report) = convert(Some(1.0));
+        assert_eq!(report.output_format, WEBP);
+        assert_eq!(report.quality, Some(1));
+    }
+
+    #[test]
+    fn auto_encodes_only_decodable_formats() {
+        use super::*;
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        static ENCODED: AtomicBool = AtomicBool::new(false);
+        let input = include_bytes!("../tests/files/convert_test1.png");
+        let mut registry = Registry::empty();
+        registry
+            .register(PNG, PNG, png_identity)
+            .register(PNG, AVIF, |_: &[u8], _: &Options| {
+                ENCODED.store(true, Ordering::SeqCst);
+                Ok(b"avif".to_vec())
+            });
+
+        let (output, report) = registry
+            .convert_bytes_with_report(input, AUTO, &Options::default())
+            .unwrap();
+
+        assert!(!ENCODED.load(Ordering::SeqCst));
+        assert_eq!(report.candidates.len(), 1);
+        assert_eq!(report.output_format, PNG);
+        assert_eq!(output, input);
+
+        // With a decoder, the smallest output is measured and kept.
+        registry.register(AVIF, PNG, |_: &[u8], _: &Options| Ok(input.to_vec()));
+        let (output, report) = registry
+            .convert_bytes_with_report(input, AUTO, &Options::default())
+            .unwrap();
+
+        assert!(ENCODED.load(Ordering::SeqCst));
+        assert_eq!(report.output_format, AVIF);
+        assert_eq!(output, b"avif");
+        assert!(report.candidates[0].dssim.abs() < 1e-6);
+    }
+
+    #[test]
+    fn auto_skips_unregistered_formats() {
+        use super::*;
+
+        let mut registry = Registry::empty();
+        registry.register(PNG, PNG, png_identity);
+        let input = include_bytes!("../tests/files/convert_test1.png");
+
+        let output = registry.convert_bytes(input, AUTO, &Options::default());
+
+        assert_eq!(output.unwrap(), input);
+        assert!(matches!(
+            Registry::empty().convert_bytes(input, AUTO, &Options::default()),
+            Err(Error::UnsupportedConversion(..))
+        ));
+    }
+
+    #[test]
+    fn auto_formats_by_content() {
+        use super::*;
+        use image::Rgba;
+
+        let opaque = RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 255]));
+        let transparent = RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 128]));
+
+        assert_eq!(auto_formats(b"", JPEG, &opaque), [WEBP, AVIF, JPEG]);
+        assert_eq!(auto_formats(b"", JPEG, &transparent), [WEBP, AVIF, PNG]);
+        assert_eq!(auto_formats(b"", GIF, &opaque), [WEBP, AVIF, PNG]);
+        assert_eq!(
+            auto_formats(b"RIFF\0\0\0\0WEBPVP8L", WEBP, &opaque),
+            [WEBP, AVIF, PNG]
+        );
+        assert_eq!(
+            auto_formats(b"RIFF\0\0\0\0WEBPVP8 ", WEBP, &opaque),
+            [WEBP, AVIF, JPEG]
+        );
+    }
+
+    #[test]
+    #[should_panic = "UnsupportedConversion(\"png\", \"webp\")"]
+    fn empty_registry_panic() {
+        use super::*;
+
+        Registry::empty()
+            .convert_bytes(
+                include_bytes!("../tests/files/convert_test1.png"),
+                WEBP,
+                &Options::default(),
+            )
+            .unwrap();
+    }
+}
@@ -0,0 +1,264 @@
+use std::{
+    io::Cursor,
+    time::{Duration, Instant},
+};
+
+use crate::extensions::{self, AVIF};
+
+/// A single step of a conversion, e.g. the gifsicle run of the gif to webp conversion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    /// The conversion the step belongs to, e.g. `gif -> webp`.
+    pub conversion: String,
+    /// The tool or library doing the work, e.g. `gifsicle`.
+    pub name: String,
+    /// The resize filter used by the step, if it resizes.
+    pub filter: Option<String>,
+    pub duration: Duration,
+}
+
+/// Collects the steps of a conversion while it runs.
+#[derive(Debug, Default)]
+pub struct Trace {
+    conversion: String,
+    steps: Vec<Step>,
+    quality: Option<u32>,
+    dssim: Option<f64>,
+}
+
+impl Trace {
+    /// Runs `f` and records it as a step of the current conversion.
+    pub fn step<F, R>(&mut self, name: &str, filter: Option<&str>, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        let start = Instant::now();
+        let result = f();
+        self.steps.push(Step {
+            conversion: self.conversion.clone(),
+            name: name.to_string(),
+            filter: filter.map(str::to_string),
+            duration: start.elapsed(),
+        });
+        result
+    }
+
+    #[must_use]
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    /// The quality picked by a search, see [`Options::max_bytes`](crate::Options::max_bytes)
+    /// and [`Options::target_dssim`](crate::Options::target_dssim).
+    #[must_use]
+    pub fn quality(&self) -> Option<u32> {
+        self.quality
+    }
+
+    /// The DSSIM of the output measured for [`Options::target_dssim`](crate::Options::target_dssim).
+    #[must_use]
+    pub fn dssim(&self) -> Option<f64> {
+        self.dssim
+    }
+
+    pub(crate) fn set_quality(&mut self, quality: u32) {
+        self.quality = Some(quality);
+    }
+
+    pub(crate) fn set_dssim(&mut self, dssim: f64) {
+        self.dssim = Some(dssim);
+    }
+
+    pub(crate) fn set_conversion(&mut self, input_format: &str, output_format: &str) {
+        self.conversion = format!("{input_format} -> {output_format}");
+    }
+
+    /// Appends the steps of the `other` trace, e.g. of a conversion tried on the side.
+    pub(crate) fn append(&mut self, other: Trace) {
+        self.steps.extend(other.steps);
+    }
+
+    pub(crate) fn into_steps(self) -> Vec<Step> {
+        self.steps
+    }
+}
+
+/// An output format tried for [`AUTO`](extensions::AUTO).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub format: String,
+    pub size: usize,
+    /// The quality picked by a search, like [`ConversionReport::quality`].
+    pub quality: Option<u32>,
+    /// The DSSIM compared with the resized source.
+    pub dssim: f64,
+}
+
+/// Describes a finished conversion.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConversionReport {
+    pub input_format: String,
+    /// Width and height of the input image, `None` if they cannot be read.
+    pub input_dimensions: Option<(u32, u32)>,
+    pub input_size: usize,
+    pub output_format: String,
+    /// Width and height of the output image, `None` if they cannot be read.
+    pub output_dimensions: Option<(u32, u32)>,
+    pub output_size: usize,
+    /// The chain of converters used, e.g. `webp -> png -> gif`.
+    pub converter: String,
+    pub steps: Vec<Step>,
+    /// The quality picked by a search, `None` without [`Options::max_bytes`](crate::Options::max_bytes)
+    /// and [`Options::target_dssim`](crate::Options::target_dssim) or when the requested quality was kept.
+    pub quality: Option<u32>,
+    /// The DSSIM of the output, 0 for an output identical to the resized source,
+    /// measured only for [`Options::target_dssim`](crate::Options::target_dssim).
+    pub dssim: Option<f64>,
+    /// The output formats tried for [`AUTO`](extensions::AUTO) in order, empty for other output formats.
+    pub candidates: Vec<Candidate>,
+    pub duration: Duration,
+}
+
+/// The header with [`ConversionReport::quality`] sent by both servers.
+pub const QUALITY_HEADER: &str = "x-quality";
+/// The header with [`ConversionReport::dssim`] sent by both servers.
+pub const DSSIM_HEADER: &str = "x-dssim";
+/// The header with [`ConversionReport::output_format`] sent by both servers for [`AUTO`](extensions::AUTO).
+pub const FORMAT_HEADER: &str = "x-format";
+
+impl ConversionReport {
+    /// The [`QUALITY_HEADER`], [`DSSIM_HEADER`] and [`FORMAT_HEADER`] headers of the values that are set.
+    #[must_use]
+    pub fn headers(&self) -> Vec<(&'static str, String)> {
+        let mut headers = Vec::new();
+        if let Some(quality) = self.quality {
+            headers.push((QUALITY_HEADER, quality.to_string()));
+        }
+        if let Some(dssim) = self.dssim {
+            headers.push((DSSIM_HEADER, dssim.to_string()));
+        }
+        if !self.candidates.is_empty() {
+            headers.push((FORMAT_HEADER, self.output_format.clone()));
+        }
+        headers
+    }
+
+    /// The resize filters used by the steps, in order and without duplicates.
+    #[must_use]
+    pub fn filters(&self) -> Vec<&str> {
+        let mut filters: Vec<&str> = Vec::new();
+        for filter in self.steps.iter().filter_map(|step| step.filter.as_deref()) {
+            if !filters.contains(&filter) {
+                filters.push(filter);
+            }
+        }
+        filters
+    }
+}
+
+/// Reads the width and height of the image without decoding it.
+pub(crate) fn dimensions(data: &[u8], format: &str) -> Option<(u32, u32)> {
+    if extensions::canonical(format) == AVIF {
+        return avif_dimensions(data);
+    }
+    image::ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()
+}
+
+/// Reads the image spatial extents (`ispe`) property of the AVIF container.
+fn avif_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let position = data.windows(4).position(|window| window == b"ispe")?;
+    // The box type is followed by the version and flags, the width and the height.
+    let properties = data.get(position + 8..position + 16)?;
+    let width = u32::from_be_bytes(properties[..4].try_into().ok()?);
+    let height = u32::from_be_bytes(properties[4..].try_into().ok()?);
+    Some((width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn trace_step() {
+        use super::*;
+
+        let mut trace = Trace::default();
+        trace.set_conversion("gif", "webp");
+
+        let result = trace.step("gifsicle", Some("mix"), || 42);
+
+        assert_eq!(result, 42);
+        assert_eq!(trace.steps().len(), 1);
+        assert_eq!(trace.steps()[0].conversion, "gif -> webp");
+        assert_eq!(trace.steps()[0].name, "gifsicle");
+        assert_eq!(trace.steps()[0].filter.as_deref(), Some("mix"));
+    }
+
+    #[test]
+    fn report_headers() {
+        use super::*;
+
+        let report = ConversionReport {
+            input_format: "png".to_string(),
+            input_dimensions: None,
+            input_size: 0,
+            output_format: "jpeg".to_string(),
+            output_dimensions: None,
+            output_size: 0,
+            converter: "png -> jpeg".to_string(),
+            steps: Vec::new(),
+            quality: Some(62),
+            dssim: Some(0.0008),
+            candidates: Vec::new(),
+            duration: Duration::default(),
+        };
+
+        assert_eq!(
+            report.headers(),
+            vec![
+                (QUALITY_HEADER, "62".to_string()),
+                (DSSIM_HEADER, "0.0008".to_string())
+            ]
+        );
+        assert!(ConversionReport {
+            quality: None,
+            dssim: None,
+            ..report.clone()
+        }
+        .headers()
+        .is_empty());
+        assert_eq!(
+            ConversionReport {
+                quality: None,
+                dssim: None,
+                candidates: vec![Candidate {
+                    format: "jpeg".to_string(),
+                    size: 0,
+                    quality: None,
+                    dssim: 0.0,
+                }],
+                ..report
+            }
+            .headers(),
+            vec![(FORMAT_HEADER, "jpeg".to_string())]
+        );
+    }
+
+    #[test]
+    fn read_dimensions() {
+        use super::*;
+
+        assert_eq!(
+            dimensions(
+                include_bytes!("../tests/files/webp2webp_test1.webp"),
+                "webp"
+            ),
+            image::load_from_memory(include_bytes!("../tests/files/webp2webp_test1.webp"))
+                .ok()
+                .map(|image| (image.width(), image.height()))
+        );
+        assert_eq!(dimensions(b"not an image", "png"), None);
+    }
+}
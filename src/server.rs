@@ -1,59 +1,70 @@
 use crate::app_error::AppError;
-use crate::{convert, ConfigBuilder};
-use axum::extract::Query;
+use crate::{extensions, EncoderOptions, EncoderParams, Options, Registry};
+use axum::extract::{Query, State};
 use axum::{
     body::Body,
     extract::{DefaultBodyLimit, Multipart},
-    http::StatusCode,
+    http::{header, StatusCode},
     response::Response,
     routing::post,
     Router,
 };
 use serde::{Deserialize, Serialize};
-use tempfile::tempdir;
-use tokio::fs::{read, write};
+use std::{path::Path, sync::Arc};
 
 #[derive(Deserialize, Serialize)]
 pub struct Params {
     extension: Option<String>,
-    width: Option<u32>,
-    height: Option<u32>,
-    quality: Option<u32>,
 }
 
 async fn convert_method(
+    State(registry): State<Arc<Registry>>,
     params: Query<Params>,
+    options: Query<Options>,
+    encoder: Query<EncoderParams>,
     mut multipart: Multipart,
 ) -> Result<Response, AppError> {
     let output_extension = params.extension.clone().unwrap_or(String::from("webp"));
-    let tempdir = tempdir()?;
+    let options = Options {
+        encoder: EncoderOptions::from(&encoder.0),
+        ..options.0
+    };
     let field = multipart.next_field().await?.unwrap();
-    let file_name = field.file_name().unwrap();
-    let input_path = tempdir.path().join(file_name);
-    let output_path = input_path.with_extension(output_extension);
+    // The extension of the file name is used for content of no detected format.
+    let input_extension = field
+        .file_name()
+        .and_then(|name| Path::new(name).extension())
+        .and_then(std::ffi::OsStr::to_str)
+        .map(str::to_string);
     let data = field.bytes().await?;
-    write(&input_path, &data).await?;
-    convert(
-        &ConfigBuilder::default()
-            .input_path(input_path)
-            .output_path(output_path.clone())
-            .width(params.width)
-            .height(params.height)
-            .quality(params.quality)
-            .build()
-            .unwrap(),
-    )?;
-    let file_content = read(&output_path).await?;
+    let (file_content, report) = registry
+        .input_format(&data, input_extension.as_deref())
+        .and_then(|input_format| {
+            registry.convert_from_with_report(&data, &input_format, &output_extension, &options)
+        })
+        .map_err(AppError::conversion)?;
     let body = Body::from(file_content);
-    let response = Response::builder().status(StatusCode::OK).body(body)?;
-    Ok(response)
+    let mut response = Response::builder().status(StatusCode::OK);
+    if let Some(media_type) = extensions::media_type(&report.output_format) {
+        response = response.header(header::CONTENT_TYPE, media_type);
+    }
+    for (name, value) in report.headers() {
+        response = response.header(name, value);
+    }
+    Ok(response.body(body)?)
 }
 
 pub fn app(limit: Option<usize>) -> Router {
+    app_with_registry(limit, Arc::new(Registry::default()))
+}
+
+/// Creates the service that converts images with the converters of the given registry.
+pub fn app_with_registry(limit: Option<usize>, registry: Arc<Registry>) -> Router {
     let limit = limit.unwrap_or(10 * 1024 * 1024);
     Router::new()
         .route("/", post(convert_method))
         .layer(DefaultBodyLimit::max(limit))
+        .with_state(registry)
 }
 
 mod tests {
@@ -77,7 +88,9 @@ mod tests {
         use axum_test::multipart::MultipartForm;
         use axum_test::{multipart::Part, TestServer};
 
-        let app = Router::new().route("/", post(convert_method));
+        let app = Router::new()
+            .route("/", post(convert_method))
+            .with_state(Arc::new(Registry::default()));
         let server = TestServer::new(app).unwrap();
         let image_bytes = include_bytes!("../tests/files/issue-159.png");
         let image_part = Part::bytes(image_bytes.as_slice()).file_name("issue-159.png");
@@ -88,12 +101,45 @@ mod tests {
         assert_eq!(response.status_code(), StatusCode::OK);
     }
 
+    #[tokio::test]
+    async fn test_convert_hostile_file_names() {
+        use super::*;
+        use crate::utils::HOSTILE_FILE_NAMES;
+        use axum_test::multipart::MultipartForm;
+        use axum_test::{multipart::Part, TestServer};
+
+        let server = TestServer::new(app(None)).unwrap();
+        let image_bytes = include_bytes!("../tests/files/gif2gif_test1.gif");
+
+        // A raw newline cannot be sent in the multipart header.
+        for name in HOSTILE_FILE_NAMES
+            .iter()
+            .filter(|name| !name.contains('\n'))
+        {
+            for extension in ["gif", "webp"] {
+                let image_part = Part::bytes(image_bytes.as_slice()).file_name(name);
+                let multipart_form = MultipartForm::new().add_part("file", image_part);
+                let response = server
+                    .post("/")
+                    .add_query_param("extension", extension)
+                    .multipart(multipart_form)
+                    .await;
+
+                assert_eq!(response.status_code(), StatusCode::OK, "{name}");
+            }
+        }
+        assert!(!std::path::Path::new("pwned").exists());
+        assert!(!std::path::Path::new("pwned.gif").exists());
+    }
+
     #[tokio::test]
     async fn test_convert_no_multipart() {
         use super::*;
         use axum_test::TestServer;
 
-        let app = Router::new().route("/", post(convert_method));
+        let app = Router::new()
+            .route("/", post(convert_method))
+            .with_state(Arc::new(Registry::default()));
         let server = TestServer::new(app).unwrap();
 
         let response = server.post("/").await;
@@ -111,7 +157,9 @@ mod tests {
         use axum_test::multipart::MultipartForm;
         use axum_test::{multipart::Part, TestServer};
 
-        let app = Router::new().route("/", post(convert_method));
+        let app = Router::new()
+            .route("/", post(convert_method))
+            .with_state(Arc::new(Registry::default()));
         let server = TestServer::new(app).unwrap();
         let image_bytes = include_bytes!("../tests/files/issue-159.png");
         let image_part =
@@ -120,10 +168,28 @@ mod tests {
         let multipart_form = MultipartForm::new().add_part("file", image_part);
         let response = server.post("/").multipart(multipart_form).await;
 
-        assert_eq!(response.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(response.status_code(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_convert_unsupported_content() {
+        use super::*;
+        use axum_test::multipart::MultipartForm;
+        use axum_test::{multipart::Part, TestServer};
+
+        let app = Router::new()
+            .route("/", post(convert_method))
+            .with_state(Arc::new(Registry::default()));
+        let server = TestServer::new(app).unwrap();
+        let image_part = Part::bytes(b"not an image".as_slice()).file_name("not_an_image.png");
+
+        let multipart_form = MultipartForm::new().add_part("file", image_part);
+        let response = server.post("/").multipart(multipart_form).await;
+
+        assert_eq!(response.status_code(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
         assert_eq!(
             response.text(),
-            "Unsupported conversion: someunknownextension -> webp"
+            "Input file content does not match any supported format"
         );
     }
 
@@ -133,23 +199,336 @@ mod tests {
         use axum_test::multipart::MultipartForm;
         use axum_test::{multipart::Part, TestServer};
 
-        let app = Router::new().route("/", post(convert_method));
+        let app = Router::new()
+            .route("/", post(convert_method))
+            .with_state(Arc::new(Registry::default()));
         let server = TestServer::new(app).unwrap();
         let image_bytes = include_bytes!("../tests/files/issue-159.png");
         let image_part = Part::bytes(image_bytes.as_slice()).file_name("issue-159.png");
 
+        let multipart_form = MultipartForm::new().add_part("file", image_part);
+        let response = server
+            .post("/?extension=jpeg&width=100&height=100&quality=10")
+            .multipart(multipart_form)
+            .await;
+
+        assert_eq!(response.status_code(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_resize_mode_pad() {
+        use super::*;
+        use axum_test::multipart::MultipartForm;
+        use axum_test::{multipart::Part, TestServer};
+
+        let app = Router::new()
+            .route("/", post(convert_method))
+            .with_state(Arc::new(Registry::default()));
+        let server = TestServer::new(app).unwrap();
+        let image_bytes = include_bytes!("../tests/files/issue-159.png");
+        let image_part = Part::bytes(image_bytes.as_slice()).file_name("issue-159.png");
+
+        let multipart_form = MultipartForm::new().add_part("file", image_part);
+        let response = server
+            .post("/?extension=webp&width=100&height=30&resize_mode=pad&background=ffffff")
+            .multipart(multipart_form)
+            .await;
+
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let image = image::load_from_memory(response.as_bytes()).unwrap();
+        assert_eq!((image.width(), image.height()), (100, 30));
+    }
+
+    #[tokio::test]
+    async fn test_encoder_params() {
+        use super::*;
+        use axum_test::multipart::MultipartForm;
+        use axum_test::{multipart::Part, TestServer};
+
+        let server = TestServer::new(app(None)).unwrap();
+        let image_bytes = include_bytes!("../tests/files/issue-159.png");
+        let image_part = Part::bytes(image_bytes.as_slice()).file_name("issue-159.png");
+
+        let multipart_form = MultipartForm::new().add_part("file", image_part);
+        let response = server
+            .post("/?extension=png&png_level=1&png_interlace=true")
+            .multipart(multipart_form)
+            .await;
+
+        assert_eq!(response.status_code(), StatusCode::OK);
+        // The interlace method is the last byte of the IHDR chunk.
+        assert_eq!(response.as_bytes()[28], 1);
+    }
+
+    #[tokio::test]
+    async fn test_encoder_params_invalid() {
+        use super::*;
+        use axum_test::multipart::MultipartForm;
+        use axum_test::{multipart::Part, TestServer};
+
+        let server = TestServer::new(app(None)).unwrap();
+        let image_bytes = include_bytes!("../tests/files/issue-159.png");
+        let image_part = Part::bytes(image_bytes.as_slice()).file_name("issue-159.png");
+
+        let multipart_form = MultipartForm::new().add_part("file", image_part);
+        let response = server
+            .post("/?extension=avif&avif_bit_depth=12")
+            .multipart(multipart_form)
+            .await;
+
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_max_bytes() {
+        use super::*;
+        use axum_test::multipart::MultipartForm;
+        use axum_test::{multipart::Part, TestServer};
+
+        let server = TestServer::new(app(None)).unwrap();
+        let image_bytes = include_bytes!("../tests/files/issue-159.png");
+
+        for (max_bytes, status_code) in [
+            (6000, StatusCode::OK),
+            (10, StatusCode::INTERNAL_SERVER_ERROR),
+        ] {
+            let image_part = Part::bytes(image_bytes.as_slice()).file_name("issue-159.png");
+            let multipart_form = MultipartForm::new().add_part("file", image_part);
+            let response = server
+                .post(&format!(
+                    "/?extension=avif&width=100&avif_speed=10&max_bytes={max_bytes}"
+                ))
+                .multipart(multipart_form)
+                .await;
+
+            assert_eq!(response.status_code(), status_code);
+            if status_code == StatusCode::OK {
+                assert!(response.as_bytes().len() <= max_bytes);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_target_dssim() {
+        use super::*;
+        use crate::report::{DSSIM_HEADER, QUALITY_HEADER};
+        use axum_test::multipart::MultipartForm;
+        use axum_test::{multipart::Part, TestServer};
+
+        let server = TestServer::new(app(None)).unwrap();
+        let image_bytes = include_bytes!("../tests/files/issue-159.png");
+        let image_part = Part::bytes(image_bytes.as_slice()).file_name("issue-159.png");
+
+        let multipart_form = MultipartForm::new().add_part("file", image_part);
+        let response = server
+            .post("/?extension=jpeg&width=100&target_dssim=0.01")
+            .multipart(multipart_form)
+            .await;
+
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let dssim: f64 = response
+            .header(DSSIM_HEADER)
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(dssim <= 0.01);
+        assert!(response.maybe_header(QUALITY_HEADER).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_auto() {
+        use super::*;
+        use crate::report::FORMAT_HEADER;
+        use axum_test::multipart::MultipartForm;
+        use axum_test::{multipart::Part, TestServer};
+
+        let server = TestServer::new(app(None)).unwrap();
+        let image_bytes = include_bytes!("../tests/files/issue-159.png");
+        let image_part = Part::bytes(image_bytes.as_slice()).file_name("issue-159.png");
+
+        let multipart_form = MultipartForm::new().add_part("file", image_part);
+        let response = server
+            .post("/?extension=auto&width=100&avif_speed=10")
+            .multipart(multipart_form)
+            .await;
+
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let format = response.header(FORMAT_HEADER);
+        let format = format.to_str().unwrap();
+        assert!(["webp", "avif", "png"].contains(&format));
+        assert_eq!(
+            response.header(header::CONTENT_TYPE),
+            extensions::media_type(format).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_metadata() {
+        use super::*;
+        use crate::utils::{
+            container::{jpeg, webp, Chunks},
+            exif::tests::{sample, tags},
+        };
+        use axum_test::multipart::MultipartForm;
+        use axum_test::{multipart::Part, TestServer};
+
+        let server = TestServer::new(app(None)).unwrap();
+        let image_bytes = jpeg::write(
+            include_bytes!("../tests/files/orientation_test.jpg"),
+            &Chunks {
+                exif: Some(sample()),
+                ..Chunks::default()
+            },
+        )
+        .unwrap();
+        let image_part = Part::bytes(image_bytes).file_name("orientation_test.jpg");
+
+        let multipart_form = MultipartForm::new().add_part("file", image_part);
+        let response = server
+            .post("/?extension=webp&width=100&metadata=keep_copyright")
+            .multipart(multipart_form)
+            .await;
+
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let exif = webp::read(response.as_bytes()).unwrap().exif.unwrap();
+        assert_eq!(
+            tags(&exif)
+                .into_iter()
+                .map(|(tag, _)| tag)
+                .collect::<Vec<_>>(),
+            [0x013B, 0x8298]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_color_profile() {
+        use super::*;
+        use crate::{utils::container::png, ColorProfile};
+        use axum_test::multipart::MultipartForm;
+        use axum_test::{multipart::Part, TestServer};
+
+        let server = TestServer::new(app(None)).unwrap();
+        let image_bytes = include_bytes!("../tests/files/issue-159.png");
+        let image_part = Part::bytes(image_bytes.as_slice()).file_name("issue-159.png");
+
+        let multipart_form = MultipartForm::new().add_part("file", image_part);
+        let response = server
+            .post("/?extension=png&width=100&color_profile=display-p3&embed_profile=true")
+            .multipart(multipart_form)
+            .await;
+
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(
+            png::read(response.as_bytes()).unwrap().icc,
+            Some(ColorProfile::DisplayP3.icc())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_without_enlargement() {
+        use super::*;
+        use axum_test::multipart::MultipartForm;
+        use axum_test::{multipart::Part, TestServer};
+
+        let app = Router::new()
+            .route("/", post(convert_method))
+            .with_state(Arc::new(Registry::default()));
+        let server = TestServer::new(app).unwrap();
+        let image_bytes = include_bytes!("../tests/files/issue-159.png");
+        let image = image::load_from_memory(image_bytes).unwrap();
+        let image_part = Part::bytes(image_bytes.as_slice()).file_name("issue-159.png");
+
+        let multipart_form = MultipartForm::new().add_part("file", image_part);
+        let response = server
+            .post(&format!(
+                "/?extension=webp&width={}&without_enlargement=true",
+                image.width() * 2
+            ))
+            .multipart(multipart_form)
+            .await;
+
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let output = image::load_from_memory(response.as_bytes()).unwrap();
+        assert_eq!(
+            (output.width(), output.height()),
+            (image.width(), image.height())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unknown_resize_mode() {
+        use super::*;
+        use axum_test::multipart::MultipartForm;
+        use axum_test::{multipart::Part, TestServer};
+
+        let app = Router::new()
+            .route("/", post(convert_method))
+            .with_state(Arc::new(Registry::default()));
+        let server = TestServer::new(app).unwrap();
+        let image_bytes = include_bytes!("../tests/files/issue-159.png");
+        let image_part = Part::bytes(image_bytes.as_slice()).file_name("issue-159.png");
+
+        let multipart_form = MultipartForm::new().add_part("file", image_part);
+        let response = server
+            .post("/?resize_mode=stretch")
+            .multipart(multipart_form)
+            .await;
+
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        assert!(response.text().ends_with(
+            "Unknown resize mode: stretch, expected one of fit, inside, cover, exact, pad"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_custom_converter() {
+        use super::*;
+        use crate::{extensions::PNG, Error, Options};
+        use axum_test::multipart::MultipartForm;
+        use axum_test::{multipart::Part, TestServer};
+
+        let mut registry = Registry::default();
+        registry.register(
+            PNG,
+            "bmp",
+            |_: &[u8], _: &Options| -> Result<Vec<u8>, Error> { Ok(b"BM".to_vec()) },
+        );
+        let server = TestServer::new(app_with_registry(None, Arc::new(registry))).unwrap();
+        let image_bytes = include_bytes!("../tests/files/issue-159.png");
+        let image_part = Part::bytes(image_bytes.as_slice()).file_name("issue-159.png");
+
         let multipart_form = MultipartForm::new().add_part("file", image_part);
         let response = server
             .post("/")
-            .add_query_params(Params {
-                extension: Some("jpeg".to_string()),
-                width: Some(100),
-                height: Some(100),
-                quality: Some(10),
-            })
+            .add_query_param("extension", "bmp")
             .multipart(multipart_form)
             .await;
 
         assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.as_bytes().as_ref(), b"BM");
+    }
+
+    #[tokio::test]
+    async fn test_custom_input_format() {
+        use super::*;
+        use crate::{extensions::WEBP, Error, Options};
+        use axum_test::multipart::MultipartForm;
+        use axum_test::{multipart::Part, TestServer};
+
+        let mut registry = Registry::default();
+        registry.register(
+            "bmp",
+            WEBP,
+            |_: &[u8], _: &Options| -> Result<Vec<u8>, Error> { Ok(b"RIFF\0\0\0\0WEBP".to_vec()) },
+        );
+        let server = TestServer::new(app_with_registry(None, Arc::new(registry))).unwrap();
+        let image_part = Part::bytes(b"BM".as_slice()).file_name("image.BMP");
+
+        let multipart_form = MultipartForm::new().add_part("file", image_part);
+        let response = server.post("/").multipart(multipart_form).await;
+
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.as_bytes().as_ref(), b"RIFF\0\0\0\0WEBP");
     }
 }
@@ -0,0 +1,154 @@
+//! How the external tools are run: the binary, the tuning arguments and the timeout.
+//! Configured with [`set_tools`], e.g. from the environment with [`Tools::from_env`].
+
+use std::{
+    env,
+    path::PathBuf,
+    sync::{PoisonError, RwLock},
+    time::Duration,
+};
+
+use crate::Error;
+
+/// An external tool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tool {
+    /// The binary, a name without a directory is looked up in `PATH`.
+    pub path: PathBuf,
+    /// The tuning arguments, passed after the arguments generated from the options,
+    /// so they can override them, e.g. `--no-dither` for gifsicle.
+    pub args: Vec<String>,
+    /// The tool is killed when it runs longer.
+    pub timeout: Option<Duration>,
+}
+
+impl Tool {
+    /// Overrides the settings given by the `RESPICTA_<NAME>_PATH`, `RESPICTA_<NAME>_ARGS`
+    /// (separated by whitespace) and `RESPICTA_<NAME>_TIMEOUT` (in seconds) environment variables.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the timeout is not a number of seconds.
+    ///
+    pub fn with_env(mut self, name: &str) -> Result<Self, Error> {
+        let prefix = format!("RESPICTA_{}", name.to_uppercase());
+        if let Some(path) = env::var_os(format!("{prefix}_PATH")) {
+            self.path = path.into();
+        }
+        if let Ok(args) = env::var(format!("{prefix}_ARGS")) {
+            self.args = split_args(&args);
+        }
+        let timeout_variable = format!("{prefix}_TIMEOUT");
+        if let Ok(timeout) = env::var(&timeout_variable) {
+            self.timeout = Some(
+                parse_timeout(&timeout)
+                    .map_err(|error| Error::InvalidEnvironmentVariable(timeout_variable, error))?,
+            );
+        }
+        Ok(self)
+    }
+}
+
+/// Splits the arguments of a tool given as a single string, e.g. `-O2 --no-dither`.
+#[must_use]
+pub fn split_args(args: &str) -> Vec<String> {
+    args.split_whitespace().map(str::to_string).collect()
+}
+
+/// Parses a timeout given in seconds, e.g. `30` or `0.5`.
+///
+/// # Errors
+///
+/// Returns an error if the timeout is not a non-negative number of seconds.
+///
+pub fn parse_timeout(seconds: &str) -> Result<Duration, String> {
+    seconds
+        .trim()
+        .parse::<f64>()
+        .map_err(|error| error.to_string())
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).map_err(|error| error.to_string()))
+}
+
+/// The external tools used by the built-in converters with the `native` feature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tools {
+    pub gifsicle: Tool,
+}
+
+impl Default for Tools {
+    fn default() -> Self {
+        Tools {
+            gifsicle: Tool {
+                path: PathBuf::from("gifsicle"),
+                args: vec![String::from("-O3")],
+                timeout: None,
+            },
+        }
+    }
+}
+
+impl Tools {
+    /// The defaults overridden by the environment, see [`Tool::with_env`], e.g. `RESPICTA_GIFSICLE_PATH`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a timeout is not a number of seconds.
+    ///
+    pub fn from_env() -> Result<Self, Error> {
+        let defaults = Tools::default();
+        Ok(Tools {
+            gifsicle: defaults.gifsicle.with_env("gifsicle")?,
+        })
+    }
+}
+
+static TOOLS: RwLock<Option<Tools>> = RwLock::new(None);
+
+/// The tools used by the conversions, [`Tools::default`] until [`set_tools`] is called.
+#[must_use]
+pub fn tools() -> Tools {
+    TOOLS
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+        .unwrap_or_default()
+}
+
+/// Replaces the tools used by the conversions.
+/// Call it before the first conversion, the [`capabilities`](crate::capabilities::capabilities) are probed only once.
+pub fn set_tools(tools: Tools) {
+    *TOOLS.write().unwrap_or_else(PoisonError::into_inner) = Some(tools);
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn tool_with_env() {
+        use super::*;
+
+        env::set_var("RESPICTA_TEST_TOOL_PATH", "/opt/vendor/bin/gifsicle");
+        env::set_var("RESPICTA_TEST_TOOL_ARGS", " -O2  --no-dither ");
+        env::set_var("RESPICTA_TEST_TOOL_TIMEOUT", "1.5");
+
+        let tool = Tools::default().gifsicle.with_env("test_tool").unwrap();
+
+        assert_eq!(
+            tool,
+            Tool {
+                path: PathBuf::from("/opt/vendor/bin/gifsicle"),
+                args: vec![String::from("-O2"), String::from("--no-dither")],
+                timeout: Some(Duration::from_millis(1500)),
+            }
+        );
+    }
+
+    #[test]
+    #[should_panic = "InvalidEnvironmentVariable(\"RESPICTA_INVALID_TOOL_TIMEOUT\""]
+    fn tool_with_env_invalid_timeout_panic() {
+        use super::*;
+
+        env::set_var("RESPICTA_INVALID_TOOL_TIMEOUT", "soon");
+
+        Tools::default().gifsicle.with_env("invalid_tool").unwrap();
+    }
+}
@@ -0,0 +1,89 @@
+use crate::Dimensions;
+
+use super::{rgba, smartcrop};
+
+/// A frame of an [`Animation`], the RGBA pixels of the whole canvas.
+pub struct Frame {
+    pub data: Vec<u8>,
+    /// How long the frame is shown, in milliseconds.
+    pub duration: u32,
+}
+
+/// The frames of an animation composited on the canvas,
+/// so the blending and disposal of the source frames are already applied.
+pub struct Animation {
+    pub width: u32,
+    pub height: u32,
+    /// How many times the animation is played, 0 plays it forever.
+    pub loop_count: u16,
+    pub frames: Vec<Frame>,
+}
+
+/// Crops, resizes and pads every frame like [`rgba::transform`].
+/// The geometry is computed once from the first frame, so all the frames are cropped alike.
+///
+/// # Errors
+///
+/// Returns an error if the resizing fails.
+///
+pub fn transform<T>(mut animation: Animation, config: &T) -> Result<Animation, resize::Error>
+where
+    T: Dimensions,
+{
+    let first = animation
+        .frames
+        .first()
+        .map_or(&[][..], |frame| &frame.data);
+    let geometry = smartcrop::geometry(first, animation.width, animation.height, config);
+
+    for frame in &mut animation.frames {
+        let (data, width, height) = rgba::apply(&frame.data, &geometry, config.background())?;
+        frame.data = data;
+        animation.width = width;
+        animation.height = height;
+    }
+
+    Ok(animation)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Options;
+
+    #[test]
+    fn transform_every_frame() {
+        use super::*;
+
+        let animation = Animation {
+            width: 4,
+            height: 2,
+            loop_count: 0,
+            frames: vec![
+                Frame {
+                    data: [255, 0, 0, 255].repeat(8),
+                    duration: 100,
+                },
+                Frame {
+                    data: [0, 0, 255, 255].repeat(8),
+                    duration: 200,
+                },
+            ],
+        };
+
+        let animation = transform(
+            animation,
+            &Options {
+                width: Some(2),
+                ..Options::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!((animation.width, animation.height), (2, 1));
+        assert!(animation
+            .frames
+            .iter()
+            .all(|frame| frame.data.len() == 2 * 4));
+        assert_eq!(animation.frames[1].duration, 200);
+    }
+}
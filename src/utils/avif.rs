@@ -0,0 +1,179 @@
+use imgref::ImgExt;
+use rgb::FromSlice;
+use thiserror::Error;
+
+use crate::{
+    encoder::{BitDepth, ColorModel},
+    Dimensions, PathAccessor, Quality,
+};
+
+use super::{icc, rgba};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Image(#[from] image::ImageError),
+    #[error("Resize({0:?})")]
+    Resize(resize::Error),
+    #[error(transparent)]
+    Encoding(#[from] ravif::Error),
+}
+
+/// Crops, resizes and pads the RGBA pixels and encodes them as AVIF.
+///
+/// # Errors
+///
+/// Returns an error if the resizing or the encoding fails.
+///
+pub fn rgba_to_avif<T>(data: &[u8], width: u32, height: u32, config: &T) -> Result<Vec<u8>, Error>
+where
+    T: Dimensions + Quality,
+{
+    let (pixels, width, height) =
+        rgba::transform(data, width, height, config).map_err(Error::Resize)?;
+    encode(&pixels, width, height, config)
+}
+
+/// Encodes the RGBA pixels as AVIF with the quality and the AVIF options of the config.
+#[allow(clippy::cast_precision_loss)]
+fn encode<T>(pixels: &[u8], width: u32, height: u32, config: &T) -> Result<Vec<u8>, Error>
+where
+    T: Quality,
+{
+    let img = ravif::Img::new(pixels.as_rgba(), width as usize, height as usize);
+    let options = config.encoder().avif;
+    let mut encoder = ravif::Encoder::new().with_speed(options.speed.unwrap_or(4).clamp(1, 10));
+
+    if let Some(quality) = config.quality() {
+        encoder = encoder.with_quality(quality as f32);
+    }
+    if let Some(alpha_quality) = options.alpha_quality {
+        encoder = encoder.with_alpha_quality(f32::from(alpha_quality.clamp(1, 100)));
+    }
+    if let Some(bit_depth) = options.bit_depth {
+        encoder = encoder.with_bit_depth(match bit_depth {
+            BitDepth::Eight => ravif::BitDepth::Eight,
+            BitDepth::Ten => ravif::BitDepth::Ten,
+        });
+    }
+    if let Some(color_model) = options.color_model {
+        encoder = encoder.with_internal_color_model(match color_model {
+            ColorModel::YCbCr => ravif::ColorModel::YCbCr,
+            ColorModel::Rgb => ravif::ColorModel::RGB,
+        });
+    }
+
+    let result = encoder.encode_rgba(img.as_ref()).map_err(Error::Encoding)?;
+
+    Ok(result.avif_file)
+}
+
+/// Decodes any supported input image, turned upright as described by its EXIF orientation
+/// and converted to the color profile of the config, and encodes it as AVIF.
+/// Only the first frame of animated images is used.
+///
+/// # Errors
+///
+/// Returns an error if the optimization fails.
+///
+pub fn optimize_bytes<T>(input: &[u8], config: &T) -> Result<Vec<u8>, Error>
+where
+    T: Dimensions + Quality,
+{
+    let (pixels, width, height) = transform(input, config)?;
+    encode(&pixels, width, height, config)
+}
+
+/// Decodes the input image and crops, resizes and pads it, the pixels encoded by [`optimize_bytes`].
+fn transform<T>(input: &[u8], config: &T) -> Result<(Vec<u8>, u32, u32), Error>
+where
+    T: Dimensions,
+{
+    let input_image = icc::decode(input, config.color_profile()).map_err(Error::Image)?;
+
+    let (width, height) = input_image.dimensions();
+    rgba::transform(input_image.as_raw(), width, height, config).map_err(Error::Resize)
+}
+
+/// # Errors
+///
+/// Returns an error if the optimization fails.
+///
+pub fn optimize<T>(config: &T) -> Result<(), Error>
+where
+    T: PathAccessor + Dimensions + Quality,
+{
+    super::optimize_file(config, |input| optimize_bytes(input, config))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Config, ConfigBuilder};
+
+    #[test]
+    fn avif_optimize_png_to_avif() {
+        use super::*;
+
+        optimize(&Config::new(
+            "tests/files/issue-159.png",
+            "target/issue-159.avif",
+            Some(100),
+            Some(100),
+        ))
+        .unwrap();
+    }
+
+    #[test]
+    fn low_quality() {
+        use super::*;
+
+        optimize(
+            &ConfigBuilder::default()
+                .input_path("tests/files/orientation_test.jpg")
+                .output_path("target/avif_low_quality.avif")
+                .width(Some(100))
+                .quality(Some(10))
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn avif_transform_alpha_golden() {
+        use super::*;
+        use crate::{utils::ssim, Options};
+
+        // The AVIF output cannot be decoded here, so the pixels handed to the encoder are compared.
+        let (pixels, width, height) = transform(
+            include_bytes!("../../tests/files/png2avif_test2.png"),
+            &Options {
+                width: Some(100),
+                ..Options::default()
+            },
+        )
+        .unwrap();
+        let resized = image::RgbaImage::from_raw(width, height, pixels).unwrap();
+        let reference = image::open("tests/files/png2avif_test2_100.png")
+            .unwrap()
+            .into_rgba8();
+
+        assert!(ssim::dssim(&reference, &resized).unwrap() < 0.0001);
+    }
+
+    #[test]
+    #[should_panic = "Io(Os { code: 2, kind: NotFound, message: \"No such file or directory\" })"]
+    fn avif_optimize_panic() {
+        use super::*;
+
+        optimize(&Config::new(
+            "tests/files/not_existing.png",
+            "target/not_existing.avif",
+            Some(100),
+            None,
+        ))
+        .unwrap();
+    }
+}
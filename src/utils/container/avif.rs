@@ -0,0 +1,813 @@
+//! The metadata of AVIF: EXIF and XMP in items describing the primary image, stored in an `mdat` box
+//! at the end of the file, and the ICC profile in a `colr` property of the primary image.
+
+use std::ops::Range;
+
+use super::{Chunks, Error};
+
+const MALFORMED: Error = Error::Malformed("avif");
+const TOO_LARGE: Error = Error::TooLarge("metadata", "avif");
+
+const EXIF: [u8; 4] = *b"Exif";
+const MIME: [u8; 4] = *b"mime";
+const XMP_CONTENT_TYPE: &[u8] = b"application/rdf+xml";
+/// The reference from a metadata item to the image it describes.
+const CDSC: [u8; 4] = *b"cdsc";
+
+struct BoxRange {
+    kind: [u8; 4],
+    /// The box with its header.
+    range: Range<usize>,
+    payload: Range<usize>,
+}
+
+/// The boxes in the range of the data.
+fn boxes(data: &[u8], range: Range<usize>) -> Result<Vec<BoxRange>, Error> {
+    let mut boxes = Vec::new();
+    let mut offset = range.start;
+    while offset < range.end {
+        let mut reader = Reader::new(data, offset..range.end);
+        let size = reader.uint(4)?;
+        let kind = reader.kind()?;
+        let size = match size {
+            0 => range.end - offset,
+            1 => usize::try_from(reader.uint(8)?).map_err(|_| MALFORMED)?,
+            size => usize::try_from(size).map_err(|_| MALFORMED)?,
+        };
+        let end = offset
+            .checked_add(size)
+            .filter(|end| *end <= range.end && *end >= reader.offset)
+            .ok_or(MALFORMED)?;
+        boxes.push(BoxRange {
+            kind,
+            range: offset..end,
+            payload: reader.offset..end,
+        });
+        offset = end;
+    }
+    Ok(boxes)
+}
+
+/// Reads the big endian fields of a box.
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], range: Range<usize>) -> Self {
+        Reader {
+            data: &data[..range.end],
+            offset: range.start,
+        }
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], Error> {
+        let end = self.offset.checked_add(length).ok_or(MALFORMED)?;
+        let bytes = self.data.get(self.offset..end).ok_or(MALFORMED)?;
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    /// An integer of 0 to 8 bytes.
+    fn uint(&mut self, size: u8) -> Result<u64, Error> {
+        if size > 8 {
+            return Err(MALFORMED);
+        }
+        Ok(self
+            .take(size.into())?
+            .iter()
+            .fold(0, |value, byte| value << 8 | u64::from(*byte)))
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        u16::try_from(self.uint(2)?).map_err(|_| MALFORMED)
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        u32::try_from(self.uint(4)?).map_err(|_| MALFORMED)
+    }
+
+    /// An item ID, 2 bytes in the boxes of version 0 and 4 bytes in the others.
+    fn id(&mut self, wide: bool) -> Result<u32, Error> {
+        if wide {
+            self.u32()
+        } else {
+            self.u16().map(u32::from)
+        }
+    }
+
+    fn kind(&mut self) -> Result<[u8; 4], Error> {
+        self.take(4)?.try_into().map_err(|_| MALFORMED)
+    }
+
+    /// The version and the flags of a full box.
+    fn header(&mut self) -> Result<(u8, [u8; 3]), Error> {
+        Ok((self.u8()?, self.take(3)?.try_into().map_err(|_| MALFORMED)?))
+    }
+
+    /// A string terminated with a zero.
+    fn string(&mut self) -> Result<&'a [u8], Error> {
+        let length = self.data[self.offset..]
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or(MALFORMED)?;
+        let string = self.take(length)?;
+        self.offset += 1;
+        Ok(string)
+    }
+}
+
+/// Appends the integer in the number of bytes.
+fn push_uint(output: &mut Vec<u8>, value: u64, size: u8) -> Result<(), Error> {
+    if size < 8 && value >> (8 * u32::from(size)) != 0 {
+        return Err(TOO_LARGE);
+    }
+    output.extend_from_slice(&value.to_be_bytes()[8 - usize::from(size)..]);
+    Ok(())
+}
+
+fn push_id(output: &mut Vec<u8>, id: u32, wide: bool) -> Result<(), Error> {
+    push_uint(output, id.into(), if wide { 4 } else { 2 })
+}
+
+/// Appends a box with the concatenated payload.
+fn push_box(output: &mut Vec<u8>, kind: [u8; 4], payload: &[&[u8]]) {
+    let length = payload.iter().map(|part| part.len()).sum::<usize>();
+    if let Ok(size) = u32::try_from(length + 8) {
+        output.extend_from_slice(&size.to_be_bytes());
+        output.extend_from_slice(&kind);
+    } else {
+        output.extend_from_slice(&1_u32.to_be_bytes());
+        output.extend_from_slice(&kind);
+        output.extend_from_slice(&(length as u64 + 16).to_be_bytes());
+    }
+    for part in payload {
+        output.extend_from_slice(part);
+    }
+}
+
+/// The size of the header of a box with the payload.
+fn box_header(length: usize) -> usize {
+    if u32::try_from(length + 8).is_ok() {
+        8
+    } else {
+        16
+    }
+}
+
+struct Item {
+    id: u32,
+    kind: [u8; 4],
+    content_type: Vec<u8>,
+}
+
+/// The `iinf` box with the `infe` boxes.
+fn items(data: &[u8], iinf: &BoxRange) -> Result<(u8, Vec<BoxRange>, Vec<Item>), Error> {
+    let mut reader = Reader::new(data, iinf.payload.clone());
+    let (version, _) = reader.header()?;
+    reader.uint(if version == 0 { 2 } else { 4 })?;
+    let entries = boxes(data, reader.offset..iinf.payload.end)?;
+    let mut items = Vec::new();
+    for entry in entries.iter().filter(|entry| entry.kind == *b"infe") {
+        let mut reader = Reader::new(data, entry.payload.clone());
+        let (version, _) = reader.header()?;
+        if version < 2 {
+            continue;
+        }
+        let id = reader.id(version > 2)?;
+        reader.u16()?;
+        let kind = reader.kind()?;
+        reader.string()?;
+        let content_type = if kind == MIME {
+            reader.string()?.to_vec()
+        } else {
+            Vec::new()
+        };
+        items.push(Item {
+            id,
+            kind,
+            content_type,
+        });
+    }
+    Ok((version, entries, items))
+}
+
+struct Extent {
+    index: u64,
+    offset: u64,
+    length: u64,
+}
+
+struct Location {
+    id: u32,
+    /// The construction method in its lowest 4 bits.
+    method: u16,
+    data_reference: u16,
+    base_offset: u64,
+    extents: Vec<Extent>,
+}
+
+impl Location {
+    fn construction_method(&self) -> u16 {
+        self.method & 0xF
+    }
+}
+
+/// The `iloc` box with the extents of the items.
+struct Iloc {
+    version: u8,
+    flags: [u8; 3],
+    offset_size: u8,
+    length_size: u8,
+    base_offset_size: u8,
+    index_size: u8,
+    locations: Vec<Location>,
+}
+
+impl Iloc {
+    fn parse(data: &[u8], iloc: &BoxRange) -> Result<Self, Error> {
+        let mut reader = Reader::new(data, iloc.payload.clone());
+        let (version, flags) = reader.header()?;
+        let sizes = reader.u8()?;
+        let (offset_size, length_size) = (sizes >> 4, sizes & 0xF);
+        let sizes = reader.u8()?;
+        let base_offset_size = sizes >> 4;
+        let index_size = if version > 0 { sizes & 0xF } else { 0 };
+        let count = if version < 2 {
+            u32::from(reader.u16()?)
+        } else {
+            reader.u32()?
+        };
+        let mut locations = Vec::new();
+        for _ in 0..count {
+            let id = reader.id(version >= 2)?;
+            let method = if version > 0 { reader.u16()? } else { 0 };
+            let data_reference = reader.u16()?;
+            let base_offset = reader.uint(base_offset_size)?;
+            let extents = (0..reader.u16()?)
+                .map(|_| {
+                    Ok(Extent {
+                        index: if version > 0 {
+                            reader.uint(index_size)?
+                        } else {
+                            0
+                        },
+                        offset: reader.uint(offset_size)?,
+                        length: reader.uint(length_size)?,
+                    })
+                })
+                .collect::<Result<_, Error>>()?;
+            locations.push(Location {
+                id,
+                method,
+                data_reference,
+                base_offset,
+                extents,
+            });
+        }
+        Ok(Iloc {
+            version,
+            flags,
+            offset_size,
+            length_size,
+            base_offset_size,
+            index_size,
+            locations,
+        })
+    }
+
+    fn serialize(&self) -> Result<Vec<u8>, Error> {
+        let mut payload = vec![self.version];
+        payload.extend_from_slice(&self.flags);
+        payload.push(self.offset_size << 4 | self.length_size);
+        payload.push(self.base_offset_size << 4 | self.index_size);
+        let count = u32::try_from(self.locations.len()).map_err(|_| TOO_LARGE)?;
+        push_uint(
+            &mut payload,
+            count.into(),
+            if self.version < 2 { 2 } else { 4 },
+        )?;
+        for location in &self.locations {
+            push_id(&mut payload, location.id, self.version >= 2)?;
+            if self.version > 0 {
+                push_uint(&mut payload, location.method.into(), 2)?;
+            }
+            push_uint(&mut payload, location.data_reference.into(), 2)?;
+            push_uint(&mut payload, location.base_offset, self.base_offset_size)?;
+            let count = u16::try_from(location.extents.len()).map_err(|_| TOO_LARGE)?;
+            push_uint(&mut payload, count.into(), 2)?;
+            for extent in &location.extents {
+                if self.version > 0 {
+                    push_uint(&mut payload, extent.index, self.index_size)?;
+                }
+                push_uint(&mut payload, extent.offset, self.offset_size)?;
+                push_uint(&mut payload, extent.length, self.length_size)?;
+            }
+        }
+        Ok(payload)
+    }
+
+    /// The data of the item, `None` if it has no location.
+    fn data(
+        &self,
+        data: &[u8],
+        idat: Option<Range<usize>>,
+        id: u32,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let Some(location) = self.locations.iter().find(|location| location.id == id) else {
+            return Ok(None);
+        };
+        let source = match location.construction_method() {
+            0 => 0..data.len(),
+            1 => idat.ok_or(MALFORMED)?,
+            _ => return Err(MALFORMED),
+        };
+        let mut item = Vec::new();
+        for extent in &location.extents {
+            let start = source.start as u64 + location.base_offset + extent.offset;
+            let start = usize::try_from(start).map_err(|_| MALFORMED)?;
+            // A length of zero is the rest of the data.
+            let end = if extent.length == 0 {
+                source.end
+            } else {
+                usize::try_from(extent.length)
+                    .ok()
+                    .and_then(|length| start.checked_add(length))
+                    .ok_or(MALFORMED)?
+            };
+            item.extend_from_slice(data.get(start..end.min(source.end)).ok_or(MALFORMED)?);
+        }
+        Ok(Some(item))
+    }
+}
+
+/// An association of a property with an item.
+struct Association {
+    essential: bool,
+    /// The index of the property in `ipco`, starting at 1.
+    index: u16,
+}
+
+/// The `ipma` box with the properties of the items.
+struct Ipma {
+    version: u8,
+    flags: [u8; 3],
+    entries: Vec<(u32, Vec<Association>)>,
+}
+
+impl Ipma {
+    fn parse(data: &[u8], ipma: &BoxRange) -> Result<Self, Error> {
+        let mut reader = Reader::new(data, ipma.payload.clone());
+        let (version, flags) = reader.header()?;
+        let large = flags[2] & 1 == 1;
+        let entries = (0..reader.u32()?)
+            .map(|_| {
+                let id = reader.id(version > 0)?;
+                let associations = (0..reader.u8()?)
+                    .map(|_| {
+                        Ok(if large {
+                            let value = reader.u16()?;
+                            Association {
+                                essential: value >> 15 == 1,
+                                index: value & 0x7FFF,
+                            }
+                        } else {
+                            let value = reader.u8()?;
+                            Association {
+                                essential: value >> 7 == 1,
+                                index: (value & 0x7F).into(),
+                            }
+                        })
+                    })
+                    .collect::<Result<_, Error>>()?;
+                Ok((id, associations))
+            })
+            .collect::<Result<_, Error>>()?;
+        Ok(Ipma {
+            version,
+            flags,
+            entries,
+        })
+    }
+
+    fn serialize(&self) -> Result<Vec<u8>, Error> {
+        let mut flags = self.flags;
+        let large = flags[2] & 1 == 1
+            || self
+                .entries
+                .iter()
+                .flat_map(|(_, associations)| associations)
+                .any(|association| association.index > 0x7F);
+        if large {
+            flags[2] |= 1;
+        }
+        let mut payload = vec![self.version];
+        payload.extend_from_slice(&flags);
+        let count = u32::try_from(self.entries.len()).map_err(|_| TOO_LARGE)?;
+        payload.extend_from_slice(&count.to_be_bytes());
+        for (id, associations) in &self.entries {
+            push_id(&mut payload, *id, self.version > 0)?;
+            payload.push(u8::try_from(associations.len()).map_err(|_| TOO_LARGE)?);
+            for association in associations {
+                let essential = u16::from(association.essential);
+                if large {
+                    push_uint(
+                        &mut payload,
+                        (essential << 15 | association.index).into(),
+                        2,
+                    )?;
+                } else {
+                    push_uint(&mut payload, (essential << 7 | association.index).into(), 1)?;
+                }
+            }
+        }
+        Ok(payload)
+    }
+}
+
+/// The boxes of `meta` and the ID of the primary item.
+fn meta(data: &[u8]) -> Result<(BoxRange, Vec<BoxRange>, u32), Error> {
+    let meta = boxes(data, 0..data.len())?
+        .into_iter()
+        .find(|top| top.kind == *b"meta")
+        .ok_or(MALFORMED)?;
+    let children = boxes(data, meta.payload.start + 4..meta.payload.end)?;
+    let pitm = children
+        .iter()
+        .find(|child| child.kind == *b"pitm")
+        .ok_or(MALFORMED)?;
+    let mut reader = Reader::new(data, pitm.payload.clone());
+    let (version, _) = reader.header()?;
+    let primary = reader.id(version > 0)?;
+    Ok((meta, children, primary))
+}
+
+fn child(children: &[BoxRange], kind: [u8; 4]) -> Result<&BoxRange, Error> {
+    children
+        .iter()
+        .find(|child| child.kind == kind)
+        .ok_or(MALFORMED)
+}
+
+/// Reads the metadata of the primary image of the AVIF image.
+///
+/// # Errors
+///
+/// Returns an error if the image is not an AVIF image.
+///
+pub fn read(data: &[u8]) -> Result<Chunks, Error> {
+    let (_, children, primary) = meta(data)?;
+    let (_, _, items) = items(data, child(&children, *b"iinf")?)?;
+    let iloc = Iloc::parse(data, child(&children, *b"iloc")?)?;
+    let idat = child(&children, *b"idat")
+        .ok()
+        .map(|idat| idat.payload.clone());
+    let item_data = |id| iloc.data(data, idat.clone(), id);
+
+    let mut chunks = Chunks::default();
+    if let Some(item) = items.iter().find(|item| item.kind == EXIF) {
+        // The offset of the TIFF header precedes the EXIF data.
+        chunks.exif = item_data(item.id)?.and_then(|exif| {
+            let offset = u32::from_be_bytes(exif.get(..4)?.try_into().ok()?);
+            exif.get(4 + usize::try_from(offset).ok()?..)
+                .map(<[u8]>::to_vec)
+        });
+    }
+    if let Some(item) = items
+        .iter()
+        .find(|item| item.kind == MIME && item.content_type == XMP_CONTENT_TYPE)
+    {
+        chunks.xmp = item_data(item.id)?;
+    }
+
+    let iprp = child(&children, *b"iprp")?;
+    let properties = boxes(data, iprp.payload.clone())?;
+    let ipco = boxes(data, child(&properties, *b"ipco")?.payload.clone())?;
+    let ipma = Ipma::parse(data, child(&properties, *b"ipma")?)?;
+    chunks.icc = ipma
+        .entries
+        .iter()
+        .filter(|(id, _)| *id == primary)
+        .flat_map(|(_, associations)| associations)
+        .filter_map(|association| ipco.get(usize::from(association.index).checked_sub(1)?))
+        .filter(|property| property.kind == *b"colr")
+        .map(|property| &data[property.payload.clone()])
+        .find(|colr| colr.starts_with(b"prof") || colr.starts_with(b"rICC"))
+        .map(|colr| colr[4..].to_vec());
+    Ok(chunks)
+}
+
+/// Adds the metadata to the AVIF image, which has none like the output of the encoders.
+///
+/// # Errors
+///
+/// Returns an error if the image is not an AVIF image or the metadata does not fit in its boxes.
+///
+pub fn write(data: &[u8], metadata: &Chunks) -> Result<Vec<u8>, Error> {
+    let (meta, children, primary) = meta(data)?;
+    let mut items = Vec::new();
+    if let Some(exif) = &metadata.exif {
+        items.push(NewItem {
+            kind: EXIF,
+            content_type: &[],
+            payload: [&[0, 0, 0, 0][..], exif].concat(),
+        });
+    }
+    if let Some(xmp) = &metadata.xmp {
+        items.push(NewItem {
+            kind: MIME,
+            content_type: XMP_CONTENT_TYPE,
+            payload: xmp.clone(),
+        });
+    }
+    let (_, _, existing) = self::items(data, child(&children, *b"iinf")?)?;
+    let first_id = existing
+        .iter()
+        .map(|item| item.id)
+        .chain([primary])
+        .max()
+        .unwrap_or(0)
+        + 1;
+    let mut edit = MetaEdit {
+        data,
+        meta: &meta,
+        primary,
+        icc: metadata.icc.as_deref(),
+        ids: (first_id..).take(items.len()).collect(),
+        items: &items,
+        grown: 0,
+    };
+
+    // The offsets in the file after `meta` move by its growth, unknown before it is written.
+    edit.grown = edit.meta_box(&children)?.len() - meta.range.len();
+    let meta_box = edit.meta_box(&children)?;
+
+    let mut output = Vec::with_capacity(data.len() + meta_box.len() + 1024);
+    output.extend_from_slice(&data[..meta.range.start]);
+    output.extend_from_slice(&meta_box);
+    let rest = output.len();
+    output.extend_from_slice(&data[meta.range.end..]);
+    // A last box extending to the end of the file gets its size, the metadata follows it.
+    if let Some(last) = boxes(data, meta.range.end..data.len())?.last() {
+        if data[last.range.start..last.range.start + 4] == [0; 4] {
+            let size = u32::try_from(last.range.len()).map_err(|_| TOO_LARGE)?;
+            let start = rest + last.range.start - meta.range.end;
+            output[start..start + 4].copy_from_slice(&size.to_be_bytes());
+        }
+    }
+    if !items.is_empty() {
+        let payloads: Vec<_> = items.iter().map(|item| &item.payload[..]).collect();
+        push_box(&mut output, *b"mdat", &payloads);
+    }
+    Ok(output)
+}
+
+/// An item added by [`write`], its payload is stored in the new `mdat` box.
+struct NewItem<'a> {
+    kind: [u8; 4],
+    content_type: &'a [u8],
+    payload: Vec<u8>,
+}
+
+/// Rebuilds the `meta` box with the new items and properties.
+struct MetaEdit<'a> {
+    data: &'a [u8],
+    meta: &'a BoxRange,
+    primary: u32,
+    icc: Option<&'a [u8]>,
+    items: &'a [NewItem<'a>],
+    ids: Vec<u32>,
+    /// The growth of the `meta` box.
+    grown: usize,
+}
+
+impl MetaEdit<'_> {
+    fn meta_box(&self, children: &[BoxRange]) -> Result<Vec<u8>, Error> {
+        let mut output = Vec::new();
+        for child in children {
+            match &child.kind {
+                b"iinf" => {
+                    push_box(&mut output, child.kind, &[&self.iinf(child)?]);
+                    if !self.items.is_empty()
+                        && !children.iter().any(|child| child.kind == *b"iref")
+                    {
+                        push_box(
+                            &mut output,
+                            *b"iref",
+                            &[&[0, 0, 0, 0], &self.references(false)?],
+                        );
+                    }
+                }
+                b"iloc" => push_box(&mut output, child.kind, &[&self.iloc(child)?]),
+                b"iref" => {
+                    let payload = &self.data[child.payload.clone()];
+                    let wide = payload.first().is_some_and(|version| *version > 0);
+                    push_box(&mut output, child.kind, &[payload, &self.references(wide)?]);
+                }
+                b"iprp" if self.icc.is_some() => {
+                    push_box(&mut output, child.kind, &[&self.iprp(child)?]);
+                }
+                _ => output.extend_from_slice(&self.data[child.range.clone()]),
+            }
+        }
+        let header = &self.data[self.meta.payload.start..self.meta.payload.start + 4];
+        let mut meta_box = Vec::with_capacity(output.len() + 16);
+        push_box(&mut meta_box, self.meta.kind, &[header, &output]);
+        Ok(meta_box)
+    }
+
+    /// The payload of `iinf` with an `infe` box for each new item.
+    fn iinf(&self, iinf: &BoxRange) -> Result<Vec<u8>, Error> {
+        let (version, entries, _) = items(self.data, iinf)?;
+        let flags = &self.data[iinf.payload.start + 1..iinf.payload.start + 4];
+        let count = entries.len() + self.items.len();
+        let version = if count > u16::MAX.into() {
+            version.max(1)
+        } else {
+            version
+        };
+        let mut payload = vec![version];
+        payload.extend_from_slice(flags);
+        push_uint(&mut payload, count as u64, if version == 0 { 2 } else { 4 })?;
+        for entry in &entries {
+            payload.extend_from_slice(&self.data[entry.range.clone()]);
+        }
+        for (item, id) in self.items.iter().zip(&self.ids) {
+            let wide = *id > u16::MAX.into();
+            let mut infe = vec![if wide { 3 } else { 2 }, 0, 0, 0];
+            push_id(&mut infe, *id, wide)?;
+            // No protection, the type and an empty name.
+            infe.extend_from_slice(&[0, 0]);
+            infe.extend_from_slice(&item.kind);
+            infe.push(0);
+            if item.kind == MIME {
+                infe.extend_from_slice(item.content_type);
+                infe.push(0);
+            }
+            push_box(&mut payload, *b"infe", &[&infe]);
+        }
+        Ok(payload)
+    }
+
+    /// The payload of `iloc` with the offsets after `meta` moved and the extents of the new items.
+    fn iloc(&self, iloc: &BoxRange) -> Result<Vec<u8>, Error> {
+        let meta_end = self.meta.range.end as u64;
+        let grown = self.grown as u64;
+        let total = self
+            .items
+            .iter()
+            .map(|item| item.payload.len())
+            .sum::<usize>();
+        let mut offset = (self.data.len() + self.grown + box_header(total)) as u64;
+
+        let mut iloc = Iloc::parse(self.data, iloc)?;
+        for location in iloc
+            .locations
+            .iter_mut()
+            .filter(|location| location.construction_method() == 0)
+        {
+            if location.base_offset >= meta_end {
+                location.base_offset += grown;
+            } else {
+                for extent in &mut location.extents {
+                    if location.base_offset + extent.offset >= meta_end {
+                        extent.offset += grown;
+                    }
+                }
+            }
+        }
+        if self.ids.last().is_some_and(|id| *id > u16::MAX.into()) {
+            iloc.version = 2;
+        }
+        let size = if offset + total as u64 > u32::MAX.into() {
+            8
+        } else {
+            4
+        };
+        iloc.offset_size = iloc.offset_size.max(size);
+        iloc.length_size = iloc.length_size.max(4);
+        if iloc.base_offset_size > 0 {
+            iloc.base_offset_size = iloc.base_offset_size.max(size);
+        }
+        for (item, id) in self.items.iter().zip(&self.ids) {
+            iloc.locations.push(Location {
+                id: *id,
+                method: 0,
+                data_reference: 0,
+                base_offset: 0,
+                extents: vec![Extent {
+                    index: 0,
+                    offset,
+                    length: item.payload.len() as u64,
+                }],
+            });
+            offset += item.payload.len() as u64;
+        }
+        iloc.serialize()
+    }
+
+    /// The `cdsc` references from the new items to the primary image.
+    fn references(&self, wide: bool) -> Result<Vec<u8>, Error> {
+        let mut references = Vec::new();
+        for id in &self.ids {
+            let mut reference = Vec::new();
+            push_id(&mut reference, *id, wide)?;
+            reference.extend_from_slice(&1_u16.to_be_bytes());
+            push_id(&mut reference, self.primary, wide)?;
+            push_box(&mut references, CDSC, &[&reference]);
+        }
+        Ok(references)
+    }
+
+    /// The payload of `iprp` with the ICC profile property associated with the primary image.
+    fn iprp(&self, iprp: &BoxRange) -> Result<Vec<u8>, Error> {
+        let properties = boxes(self.data, iprp.payload.clone())?;
+        let count = boxes(self.data, child(&properties, *b"ipco")?.payload.clone())?.len();
+        let mut payload = Vec::new();
+        for property in &properties {
+            match &property.kind {
+                b"ipco" => {
+                    let mut ipco = self.data[property.payload.clone()].to_vec();
+                    push_box(
+                        &mut ipco,
+                        *b"colr",
+                        &[b"prof", self.icc.unwrap_or_default()],
+                    );
+                    push_box(&mut payload, property.kind, &[&ipco]);
+                }
+                b"ipma" => {
+                    let mut ipma = Ipma::parse(self.data, property)?;
+                    let association = Association {
+                        essential: false,
+                        index: u16::try_from(count + 1).map_err(|_| TOO_LARGE)?,
+                    };
+                    match ipma.entries.iter_mut().find(|(id, _)| *id == self.primary) {
+                        Some((_, associations)) => associations.push(association),
+                        None => ipma.entries.push((self.primary, vec![association])),
+                    }
+                    push_box(&mut payload, property.kind, &[&ipma.serialize()?]);
+                }
+                _ => payload.extend_from_slice(&self.data[property.range.clone()]),
+            }
+        }
+        Ok(payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn write_read() {
+        use super::*;
+        use crate::utils::{avif::rgba_to_avif, exif::tests::sample};
+
+        let image = image::RgbaImage::from_fn(16, 8, |x, _| {
+            image::Rgba([255, 0, 0, u8::try_from(x * 16).unwrap()])
+        });
+        let options = crate::Options {
+            encoder: crate::EncoderOptions {
+                avif: crate::encoder::AvifOptions {
+                    speed: Some(10),
+                    ..crate::encoder::AvifOptions::default()
+                },
+                ..crate::EncoderOptions::default()
+            },
+            ..crate::Options::default()
+        };
+        let input = rgba_to_avif(image.as_raw(), 16, 8, &options).unwrap();
+        let metadata = Chunks {
+            icc: Some((0..=255).cycle().take(3000).collect()),
+            exif: Some(sample()),
+            xmp: Some(b"<x:xmpmeta/>".to_vec()),
+        };
+        let output = write(&input, &metadata).unwrap();
+
+        assert_eq!(read(&input).unwrap(), Chunks::default());
+        assert_eq!(read(&output).unwrap(), metadata);
+        // The images are where the moved offsets point.
+        let (_, children, primary) = meta(&input).unwrap();
+        let (_, _, images) = items(&input, child(&children, *b"iinf").unwrap()).unwrap();
+        let image_data = |data: &[u8], id| {
+            let (_, children, _) = meta(data).unwrap();
+            Iloc::parse(data, child(&children, *b"iloc").unwrap())
+                .unwrap()
+                .data(data, None, id)
+                .unwrap()
+        };
+        assert!(images.len() > 1);
+        for item in images {
+            assert!(image_data(&input, item.id).is_some());
+            assert_eq!(image_data(&output, item.id), image_data(&input, item.id));
+        }
+        assert_eq!(
+            crate::report::dimensions(&output, crate::extensions::AVIF),
+            Some((16, 8))
+        );
+        assert_eq!(meta(&output).unwrap().2, primary);
+    }
+}
@@ -0,0 +1,203 @@
+//! The metadata segments of JPEG: EXIF and XMP in APP1, the ICC profile in APP2 split into numbered parts.
+
+use std::ops::Range;
+
+use super::{Chunks, Error};
+
+const SOI: [u8; 2] = [0xFF, 0xD8];
+const APP0: u8 = 0xE0;
+const APP1: u8 = 0xE1;
+const APP2: u8 = 0xE2;
+/// The start of the scan, the compressed image follows it.
+const SOS: u8 = 0xDA;
+const EOI: u8 = 0xD9;
+
+const EXIF: &[u8] = b"Exif\0\0";
+const XMP: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const ICC: &[u8] = b"ICC_PROFILE\0";
+/// The largest payload of a segment, its length counts its own 2 bytes.
+const MAX_PAYLOAD: usize = u16::MAX as usize - 2;
+/// The largest part of the ICC profile in a segment, after the signature and the part numbers.
+const ICC_PART: usize = MAX_PAYLOAD - ICC.len() - 2;
+
+struct Segment {
+    marker: u8,
+    /// The segment with its marker.
+    range: Range<usize>,
+    payload: Range<usize>,
+}
+
+impl Segment {
+    fn is_metadata(&self, data: &[u8]) -> bool {
+        let payload = &data[self.payload.clone()];
+        match self.marker {
+            APP1 => payload.starts_with(EXIF) || payload.starts_with(XMP),
+            APP2 => payload.starts_with(ICC),
+            _ => false,
+        }
+    }
+}
+
+/// The segments before the start of the scan, and the offset of the scan.
+fn segments(data: &[u8]) -> Result<(Vec<Segment>, usize), Error> {
+    const MALFORMED: Error = Error::Malformed("jpeg");
+    if !data.starts_with(&SOI) {
+        return Err(MALFORMED);
+    }
+    let mut segments = Vec::new();
+    let mut offset = SOI.len();
+    loop {
+        let (0xFF, marker) = (
+            *data.get(offset).ok_or(MALFORMED)?,
+            *data.get(offset + 1).ok_or(MALFORMED)?,
+        ) else {
+            return Err(MALFORMED);
+        };
+        match marker {
+            // Fill bytes before a marker.
+            0xFF => offset += 1,
+            SOS | EOI => return Ok((segments, offset)),
+            // The markers without a payload.
+            0x01 | 0xD0..=0xD7 => offset += 2,
+            _ => {
+                let length = data
+                    .get(offset + 2..offset + 4)
+                    .map(|bytes| usize::from(u16::from_be_bytes([bytes[0], bytes[1]])))
+                    .filter(|length| *length >= 2 && offset + 2 + length <= data.len())
+                    .ok_or(MALFORMED)?;
+                let end = offset + 2 + length;
+                segments.push(Segment {
+                    marker,
+                    range: offset..end,
+                    payload: offset + 4..end,
+                });
+                offset = end;
+            }
+        }
+    }
+}
+
+/// Reads the metadata of the JPEG image.
+///
+/// # Errors
+///
+/// Returns an error if the image is not a JPEG image.
+///
+pub fn read(data: &[u8]) -> Result<Chunks, Error> {
+    let (segments, _) = segments(data)?;
+    let mut chunks = Chunks::default();
+    let mut icc_parts = Vec::new();
+    for segment in &segments {
+        let payload = &data[segment.payload.clone()];
+        match segment.marker {
+            APP1 if payload.starts_with(EXIF) => {
+                chunks
+                    .exif
+                    .get_or_insert_with(|| payload[EXIF.len()..].to_vec());
+            }
+            APP1 if payload.starts_with(XMP) => {
+                chunks
+                    .xmp
+                    .get_or_insert_with(|| payload[XMP.len()..].to_vec());
+            }
+            APP2 if payload.len() >= ICC.len() + 2 && payload.starts_with(ICC) => {
+                icc_parts.push((payload[ICC.len()], &payload[ICC.len() + 2..]));
+            }
+            _ => {}
+        }
+    }
+    if !icc_parts.is_empty() {
+        icc_parts.sort_by_key(|(number, _)| *number);
+        chunks.icc = Some(
+            icc_parts
+                .into_iter()
+                .flat_map(|(_, part)| part)
+                .copied()
+                .collect(),
+        );
+    }
+    Ok(chunks)
+}
+
+/// Replaces the metadata of the JPEG image with the chunks, after the JFIF segment.
+///
+/// # Errors
+///
+/// Returns an error if the image is not a JPEG image or a chunk does not fit in its segments.
+///
+pub fn write(data: &[u8], chunks: &Chunks) -> Result<Vec<u8>, Error> {
+    let (segments, scan) = segments(data)?;
+    let mut output = Vec::with_capacity(data.len());
+    output.extend_from_slice(&SOI);
+    let jfif = segments
+        .iter()
+        .take_while(|segment| segment.marker == APP0)
+        .count();
+    for segment in &segments[..jfif] {
+        output.extend_from_slice(&data[segment.range.clone()]);
+    }
+
+    if let Some(exif) = &chunks.exif {
+        push_segment(&mut output, APP1, &[EXIF, exif]).ok_or(Error::TooLarge("exif", "jpeg"))?;
+    }
+    if let Some(xmp) = &chunks.xmp {
+        push_segment(&mut output, APP1, &[XMP, xmp]).ok_or(Error::TooLarge("xmp", "jpeg"))?;
+    }
+    if let Some(icc) = &chunks.icc {
+        let parts: Vec<_> = icc.chunks(ICC_PART).collect();
+        let count = u8::try_from(parts.len()).map_err(|_| Error::TooLarge("icc", "jpeg"))?;
+        for (number, part) in (1..=count).zip(parts) {
+            push_segment(&mut output, APP2, &[ICC, &[number, count], part])
+                .ok_or(Error::TooLarge("icc", "jpeg"))?;
+        }
+    }
+
+    for segment in &segments[jfif..] {
+        if !segment.is_metadata(data) {
+            output.extend_from_slice(&data[segment.range.clone()]);
+        }
+    }
+    output.extend_from_slice(&data[scan..]);
+    Ok(output)
+}
+
+/// Appends a segment with the concatenated payload, `None` if it is too large.
+fn push_segment(output: &mut Vec<u8>, marker: u8, payload: &[&[u8]]) -> Option<()> {
+    let length = payload.iter().map(|part| part.len()).sum::<usize>();
+    if length > MAX_PAYLOAD {
+        return None;
+    }
+    output.extend_from_slice(&[0xFF, marker]);
+    output.extend_from_slice(&u16::try_from(length + 2).ok()?.to_be_bytes());
+    for part in payload {
+        output.extend_from_slice(part);
+    }
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn write_read() {
+        use super::*;
+        use crate::utils::exif::tests::sample;
+
+        let input = include_bytes!("../../../tests/files/orientation_test.jpg");
+        let chunks = Chunks {
+            icc: Some((0..=255).cycle().take(ICC_PART * 2 + 10).collect()),
+            exif: Some(sample()),
+            xmp: Some(b"<x:xmpmeta/>".to_vec()),
+        };
+        let output = write(input, &chunks).unwrap();
+
+        assert_eq!(read(&output).unwrap(), chunks);
+        assert_eq!(
+            read(&write(&output, &Chunks::default()).unwrap()).unwrap(),
+            Chunks::default()
+        );
+        assert_eq!(
+            image::load_from_memory(&output).unwrap(),
+            image::load_from_memory(input).unwrap()
+        );
+    }
+}
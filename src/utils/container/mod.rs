@@ -0,0 +1,46 @@
+//! Reads and writes the metadata chunks of the image containers, see [`Metadata`](crate::Metadata).
+//!
+//! The encoders strip every chunk, these modules put back the ones kept by the policy
+//! without decoding or encoding the image again.
+
+use thiserror::Error;
+
+pub mod avif;
+pub mod jpeg;
+pub mod png;
+pub mod webp;
+
+/// The metadata of an image, each chunk as stored in the container without its header.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Chunks {
+    /// The ICC color profile.
+    pub icc: Option<Vec<u8>>,
+    /// The EXIF data, starting with the TIFF header.
+    pub exif: Option<Vec<u8>>,
+    /// The XMP packet.
+    pub xmp: Option<Vec<u8>>,
+}
+
+impl Chunks {
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.icc.is_none() && self.exif.is_none() && self.xmp.is_none()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Malformed {0} container")]
+    Malformed(&'static str),
+    #[error("The {0} chunk does not fit in the {1} container")]
+    TooLarge(&'static str, &'static str),
+    #[error("Io({0})")]
+    Io(std::io::Error),
+}
+
+/// The value stored big endian in the bytes at the offset, `None` past the end of the data.
+fn be_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        data.get(offset..offset.checked_add(4)?)?.try_into().ok()?,
+    ))
+}
@@ -0,0 +1,190 @@
+//! The metadata chunks of PNG: the compressed ICC profile in `iCCP`, EXIF in `eXIf` and XMP in `iTXt`.
+
+use std::{
+    io::{Read, Write},
+    ops::Range,
+};
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+
+use super::{be_u32, Chunks, Error};
+
+const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const IHDR: &[u8] = b"IHDR";
+const ICCP: &[u8] = b"iCCP";
+/// The sRGB rendering intent, replaced by the ICC profile.
+const SRGB: &[u8] = b"sRGB";
+const EXIF: &[u8] = b"eXIf";
+const ITXT: &[u8] = b"iTXt";
+/// The keyword of the `iTXt` chunk with XMP.
+const XMP: &[u8] = b"XML:com.adobe.xmp";
+const ICC_NAME: &[u8] = b"ICC Profile";
+
+struct Chunk {
+    kind: [u8; 4],
+    /// The chunk with its length and checksum.
+    range: Range<usize>,
+    payload: Range<usize>,
+}
+
+impl Chunk {
+    fn is_metadata(&self, data: &[u8]) -> bool {
+        match &self.kind[..] {
+            ICCP | SRGB | EXIF => true,
+            ITXT => is_xmp(&data[self.payload.clone()]),
+            _ => false,
+        }
+    }
+}
+
+fn is_xmp(payload: &[u8]) -> bool {
+    payload.starts_with(XMP) && payload.get(XMP.len()) == Some(&0)
+}
+
+fn chunks(data: &[u8]) -> Result<Vec<Chunk>, Error> {
+    const MALFORMED: Error = Error::Malformed("png");
+    if !data.starts_with(SIGNATURE) {
+        return Err(MALFORMED);
+    }
+    let mut chunks = Vec::new();
+    let mut offset = SIGNATURE.len();
+    while offset < data.len() {
+        let length = be_u32(data, offset).ok_or(MALFORMED)? as usize;
+        let end = (offset + 12)
+            .checked_add(length)
+            .filter(|end| *end <= data.len())
+            .ok_or(MALFORMED)?;
+        chunks.push(Chunk {
+            kind: data[offset + 4..offset + 8]
+                .try_into()
+                .map_err(|_| MALFORMED)?,
+            range: offset..end,
+            payload: offset + 8..end - 4,
+        });
+        offset = end;
+    }
+    if chunks.first().map(|chunk| &chunk.kind[..]) != Some(IHDR) {
+        return Err(MALFORMED);
+    }
+    Ok(chunks)
+}
+
+/// Reads the metadata of the PNG image.
+///
+/// # Errors
+///
+/// Returns an error if the image is not a PNG image or its ICC profile cannot be decompressed.
+///
+pub fn read(data: &[u8]) -> Result<Chunks, Error> {
+    let mut metadata = Chunks::default();
+    for chunk in chunks(data)? {
+        let payload = &data[chunk.payload];
+        match &chunk.kind[..] {
+            ICCP if metadata.icc.is_none() => {
+                // The profile name, its terminator and the compression method precede the profile.
+                let start = payload
+                    .iter()
+                    .position(|byte| *byte == 0)
+                    .ok_or(Error::Malformed("png"))?
+                    + 2;
+                let mut icc = Vec::new();
+                ZlibDecoder::new(payload.get(start..).unwrap_or_default())
+                    .read_to_end(&mut icc)
+                    .map_err(Error::Io)?;
+                metadata.icc = Some(icc);
+            }
+            EXIF if metadata.exif.is_none() => metadata.exif = Some(payload.to_vec()),
+            // The keyword, the compression flag and method, the language and the translated keyword.
+            ITXT if metadata.xmp.is_none() && is_xmp(payload) => {
+                let header = &payload[XMP.len() + 1..];
+                if header.first() == Some(&0) {
+                    let text = header
+                        .iter()
+                        .enumerate()
+                        .skip(2)
+                        .filter(|(_, byte)| **byte == 0)
+                        .nth(1)
+                        .map(|(index, _)| &header[index + 1..]);
+                    metadata.xmp = text.map(<[u8]>::to_vec);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(metadata)
+}
+
+/// Replaces the metadata of the PNG image with the chunks, after the header.
+///
+/// # Errors
+///
+/// Returns an error if the image is not a PNG image or a chunk is too large.
+///
+pub fn write(data: &[u8], metadata: &Chunks) -> Result<Vec<u8>, Error> {
+    let chunks = chunks(data)?;
+    let mut output = Vec::with_capacity(data.len());
+    output.extend_from_slice(SIGNATURE);
+    output.extend_from_slice(&data[chunks[0].range.clone()]);
+
+    if let Some(icc) = &metadata.icc {
+        let mut encoder = ZlibEncoder::new([ICC_NAME, &[0, 0]].concat(), Compression::default());
+        encoder.write_all(icc).map_err(Error::Io)?;
+        push_chunk(&mut output, ICCP, &encoder.finish().map_err(Error::Io)?)?;
+    }
+    if let Some(exif) = &metadata.exif {
+        push_chunk(&mut output, EXIF, exif)?;
+    }
+    if let Some(xmp) = &metadata.xmp {
+        // Uncompressed, without a language or a translated keyword.
+        push_chunk(&mut output, ITXT, &[XMP, &[0, 0, 0, 0, 0], xmp].concat())?;
+    }
+
+    for chunk in &chunks[1..] {
+        if !chunk.is_metadata(data) {
+            output.extend_from_slice(&data[chunk.range.clone()]);
+        }
+    }
+    Ok(output)
+}
+
+fn push_chunk(output: &mut Vec<u8>, kind: &[u8], payload: &[u8]) -> Result<(), Error> {
+    let length = u32::try_from(payload.len())
+        .ok()
+        .filter(|length| i32::try_from(*length).is_ok())
+        .ok_or(Error::TooLarge("metadata", "png"))?;
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(kind);
+    hasher.update(payload);
+    output.extend_from_slice(&length.to_be_bytes());
+    output.extend_from_slice(kind);
+    output.extend_from_slice(payload);
+    output.extend_from_slice(&hasher.finalize().to_be_bytes());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn write_read() {
+        use super::*;
+        use crate::utils::exif::tests::sample;
+
+        let input = include_bytes!("../../../tests/files/convert_test1.png");
+        let chunks = Chunks {
+            icc: Some((0..=255).cycle().take(3000).collect()),
+            exif: Some(sample()),
+            xmp: Some(b"<x:xmpmeta/>".to_vec()),
+        };
+        let output = write(input, &chunks).unwrap();
+
+        assert_eq!(read(&output).unwrap(), chunks);
+        assert_eq!(
+            read(&write(&output, &Chunks::default()).unwrap()).unwrap(),
+            Chunks::default()
+        );
+        assert_eq!(
+            image::load_from_memory(&output).unwrap(),
+            image::load_from_memory(input).unwrap()
+        );
+    }
+}
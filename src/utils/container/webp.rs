@@ -0,0 +1,211 @@
+//! The metadata chunks of the extended WebP format, like `WebPMux` writes them:
+//! `ICCP` after the `VP8X` header, `EXIF` and `XMP ` after the image, with their flags in the header.
+
+use std::ops::Range;
+
+use super::{Chunks, Error};
+
+const VP8X: &[u8] = b"VP8X";
+const VP8: &[u8] = b"VP8 ";
+const VP8L: &[u8] = b"VP8L";
+const ICCP: &[u8] = b"ICCP";
+const EXIF: &[u8] = b"EXIF";
+const XMP: &[u8] = b"XMP ";
+
+/// The flags of the `VP8X` header.
+const ICC_FLAG: u8 = 0x20;
+const ALPHA_FLAG: u8 = 0x10;
+const EXIF_FLAG: u8 = 0x08;
+const XMP_FLAG: u8 = 0x04;
+
+/// The prefix of EXIF data written by some encoders, not part of the format.
+const EXIF_PREFIX: &[u8] = b"Exif\0\0";
+
+const MALFORMED: Error = Error::Malformed("webp");
+
+struct Chunk {
+    kind: [u8; 4],
+    /// The chunk with its header and padding.
+    range: Range<usize>,
+    payload: Range<usize>,
+}
+
+fn chunks(data: &[u8]) -> Result<Vec<Chunk>, Error> {
+    if data.len() < 12 || &data[..4] != b"RIFF" || &data[8..12] != b"WEBP" {
+        return Err(MALFORMED);
+    }
+    let mut chunks = Vec::new();
+    let mut offset = 12;
+    while offset + 8 <= data.len() {
+        let length = u32::from_le_bytes(
+            data[offset + 4..offset + 8]
+                .try_into()
+                .map_err(|_| MALFORMED)?,
+        ) as usize;
+        let payload = offset + 8..offset + 8 + length;
+        if payload.end > data.len() {
+            return Err(MALFORMED);
+        }
+        let end = (payload.end + length % 2).min(data.len());
+        chunks.push(Chunk {
+            kind: data[offset..offset + 4].try_into().map_err(|_| MALFORMED)?,
+            range: offset..end,
+            payload,
+        });
+        offset = end;
+    }
+    Ok(chunks)
+}
+
+/// Reads the metadata of the WebP image.
+///
+/// # Errors
+///
+/// Returns an error if the image is not a WebP image.
+///
+pub fn read(data: &[u8]) -> Result<Chunks, Error> {
+    let mut metadata = Chunks::default();
+    for chunk in chunks(data)? {
+        let payload = &data[chunk.payload];
+        match &chunk.kind[..] {
+            ICCP => metadata.icc = Some(payload.to_vec()),
+            EXIF => {
+                let exif = payload.strip_prefix(EXIF_PREFIX).unwrap_or(payload);
+                metadata.exif = Some(exif.to_vec());
+            }
+            XMP => metadata.xmp = Some(payload.to_vec()),
+            _ => {}
+        }
+    }
+    Ok(metadata)
+}
+
+/// Replaces the metadata of the WebP image with the chunks, turning a simple image into an extended one.
+///
+/// # Errors
+///
+/// Returns an error if the image is not a WebP image or a chunk is too large.
+///
+pub fn write(data: &[u8], metadata: &Chunks) -> Result<Vec<u8>, Error> {
+    let chunks = chunks(data)?;
+    let (mut flags, width, height) = canvas(data, &chunks)?;
+    flags &= !(ICC_FLAG | EXIF_FLAG | XMP_FLAG);
+    for (chunk, flag) in [
+        (&metadata.icc, ICC_FLAG),
+        (&metadata.exif, EXIF_FLAG),
+        (&metadata.xmp, XMP_FLAG),
+    ] {
+        if chunk.is_some() {
+            flags |= flag;
+        }
+    }
+
+    let mut header = [0; 10];
+    header[0] = flags;
+    header[4..7].copy_from_slice(&(width - 1).to_le_bytes()[..3]);
+    header[7..10].copy_from_slice(&(height - 1).to_le_bytes()[..3]);
+    let mut body = Vec::with_capacity(data.len());
+    push_chunk(&mut body, VP8X, &header)?;
+    if let Some(icc) = &metadata.icc {
+        push_chunk(&mut body, ICCP, icc)?;
+    }
+    for chunk in &chunks {
+        if ![VP8X, ICCP, EXIF, XMP].contains(&&chunk.kind[..]) {
+            body.extend_from_slice(&data[chunk.range.clone()]);
+            if chunk.range.len() % 2 == 1 {
+                body.push(0);
+            }
+        }
+    }
+    if let Some(exif) = &metadata.exif {
+        push_chunk(&mut body, EXIF, exif)?;
+    }
+    if let Some(xmp) = &metadata.xmp {
+        push_chunk(&mut body, XMP, xmp)?;
+    }
+
+    let size = u32::try_from(body.len() + 4).map_err(|_| Error::TooLarge("metadata", "webp"))?;
+    Ok([b"RIFF", &size.to_le_bytes()[..], b"WEBP", &body].concat())
+}
+
+/// The flags of the `VP8X` header and the dimensions of the canvas,
+/// read from the header or the bitstream of a simple image.
+fn canvas(data: &[u8], chunks: &[Chunk]) -> Result<(u8, u32, u32), Error> {
+    let first = chunks.first().ok_or(MALFORMED)?;
+    let payload = &data[first.payload.clone()];
+    let u24 = |bytes: &[u8]| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]);
+    match &first.kind[..] {
+        VP8X if payload.len() >= 10 => Ok((
+            payload[0],
+            u24(&payload[4..7]) + 1,
+            u24(&payload[7..10]) + 1,
+        )),
+        // The signature, then 14 bits of width and height minus one and the alpha bit.
+        VP8L if payload.len() >= 5 && payload[0] == 0x2F => {
+            let bits = u32::from_le_bytes([payload[1], payload[2], payload[3], payload[4]]);
+            let alpha = if bits >> 28 & 1 == 1 { ALPHA_FLAG } else { 0 };
+            Ok((alpha, (bits & 0x3FFF) + 1, (bits >> 14 & 0x3FFF) + 1))
+        }
+        // The frame tag, the start code, then 14 bits of width and height.
+        VP8 if payload.len() >= 10 && payload[3..6] == [0x9D, 0x01, 0x2A] => Ok((
+            0,
+            u32::from(u16::from_le_bytes([payload[6], payload[7]]) & 0x3FFF),
+            u32::from(u16::from_le_bytes([payload[8], payload[9]]) & 0x3FFF),
+        )),
+        _ => Err(MALFORMED),
+    }
+}
+
+fn push_chunk(output: &mut Vec<u8>, kind: &[u8], payload: &[u8]) -> Result<(), Error> {
+    let length = u32::try_from(payload.len()).map_err(|_| Error::TooLarge("metadata", "webp"))?;
+    output.extend_from_slice(kind);
+    output.extend_from_slice(&length.to_le_bytes());
+    output.extend_from_slice(payload);
+    if payload.len() % 2 == 1 {
+        output.push(0);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn write_read() {
+        use super::*;
+        use crate::utils::exif::tests::sample;
+
+        let metadata = Chunks {
+            icc: Some((0..=255).cycle().take(3001).collect()),
+            exif: Some(sample()),
+            xmp: Some(b"<x:xmpmeta/>".to_vec()),
+        };
+        let mut lossless = Vec::new();
+        image::RgbaImage::from_fn(8, 6, |x, y| {
+            image::Rgba([0, 0, 255, u8::try_from(x * y).unwrap()])
+        })
+        .write_with_encoder(image::codecs::webp::WebPEncoder::new_lossless(
+            &mut lossless,
+        ))
+        .unwrap();
+        for input in [
+            &lossless[..],
+            &include_bytes!("../../../tests/files/webp2webp_test1.webp")[..],
+        ] {
+            let output = write(input, &metadata).unwrap();
+
+            assert_eq!(read(&output).unwrap(), metadata);
+            assert_eq!(
+                read(&write(&output, &Chunks::default()).unwrap()).unwrap(),
+                Chunks::default()
+            );
+            assert_eq!(
+                canvas(&output, &chunks(&output).unwrap()).unwrap().0 & ALPHA_FLAG,
+                ALPHA_FLAG
+            );
+            assert_eq!(
+                image::load_from_memory(&output).unwrap().to_rgba8(),
+                image::load_from_memory(input).unwrap().to_rgba8()
+            );
+        }
+    }
+}
@@ -0,0 +1,302 @@
+//! Edits the EXIF data kept by the [`Metadata`](crate::Metadata) policies.
+//!
+//! The data is the TIFF structure stored in the metadata chunks of JPEG, PNG, WebP and AVIF,
+//! starting with the `II` or `MM` byte order mark. Only the first IFD is edited.
+
+/// The EXIF orientation, see [`utils::orientation`](super::orientation).
+const ORIENTATION: u16 = 0x0112;
+const ARTIST: u16 = 0x013B;
+const COPYRIGHT: u16 = 0x8298;
+/// The offset of the IFD with the GPS tags.
+const GPS_IFD: u16 = 0x8825;
+
+/// The TIFF type of ASCII strings.
+const ASCII: u16 = 2;
+/// The TIFF type of 16-bit integers.
+const SHORT: u16 = 3;
+/// The size of an IFD entry: the tag, the type, the count and the value or its offset.
+const ENTRY_SIZE: usize = 12;
+
+/// An entry of an IFD.
+struct Entry {
+    /// The offset of the entry.
+    position: usize,
+    tag: u16,
+    kind: u16,
+    /// The offset of the value, inside the entry when it fits in 4 bytes.
+    value: usize,
+    /// The size of the value in bytes.
+    size: usize,
+}
+
+struct Tiff<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a [u8]) -> Option<Self> {
+        match data.get(..4)? {
+            [b'I', b'I', 42, 0] => Some(Tiff {
+                data,
+                little_endian: true,
+            }),
+            [b'M', b'M', 0, 42] => Some(Tiff {
+                data,
+                little_endian: false,
+            }),
+            _ => None,
+        }
+    }
+
+    fn u16(&self, offset: usize) -> Option<u16> {
+        let bytes = self.data.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, offset: usize) -> Option<u32> {
+        let bytes = self.data.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    fn u16_bytes(&self, value: u16) -> [u8; 2] {
+        if self.little_endian {
+            value.to_le_bytes()
+        } else {
+            value.to_be_bytes()
+        }
+    }
+
+    /// The offset of the first IFD.
+    fn first_ifd(&self) -> Option<usize> {
+        self.u32(4).map(|offset| offset as usize)
+    }
+
+    /// The entries of the IFD at the offset, `None` if any of them points outside of the data.
+    fn entries(&self, ifd: usize) -> Option<Vec<Entry>> {
+        let count = usize::from(self.u16(ifd)?);
+        (0..count)
+            .map(|index| {
+                let position = ifd + 2 + index * ENTRY_SIZE;
+                let tag = self.u16(position)?;
+                let kind = self.u16(position + 2)?;
+                let unit = match kind {
+                    1 | 2 | 6 | 7 => 1,
+                    3 | 8 => 2,
+                    4 | 9 | 11 | 13 => 4,
+                    5 | 10 | 12 => 8,
+                    _ => return None,
+                };
+                let size = unit * self.u32(position + 4)? as usize;
+                let value = if size <= 4 {
+                    position + 8
+                } else {
+                    self.u32(position + 8)? as usize
+                };
+                self.data.get(value..value.checked_add(size)?)?;
+                Some(Entry {
+                    position,
+                    tag,
+                    kind,
+                    value,
+                    size,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Sets the orientation to upright, for images whose pixels were turned upright when they were decoded.
+/// Returns `None` if the data cannot be read.
+#[must_use]
+pub fn upright(mut exif: Vec<u8>) -> Option<Vec<u8>> {
+    let tiff = Tiff::new(&exif)?;
+    let entries = tiff.entries(tiff.first_ifd()?)?;
+    let value = entries
+        .iter()
+        .find(|entry| entry.tag == ORIENTATION && entry.kind == SHORT && entry.size == 2)
+        .map(|entry| (entry.value, tiff.u16_bytes(1)));
+    if let Some((position, bytes)) = value {
+        exif[position..position + 2].copy_from_slice(&bytes);
+    }
+    Some(exif)
+}
+
+/// Removes the GPS tags: their IFD is overwritten with zeros and unlinked from the first IFD.
+/// Returns `None` if the data cannot be read, so that no location is kept by mistake.
+#[must_use]
+pub fn without_gps(mut exif: Vec<u8>) -> Option<Vec<u8>> {
+    let tiff = Tiff::new(&exif)?;
+    let ifd = tiff.first_ifd()?;
+    let entries = tiff.entries(ifd)?;
+    let Some(index) = entries.iter().position(|entry| entry.tag == GPS_IFD) else {
+        return Some(exif);
+    };
+    let gps = tiff.u32(entries[index].value)? as usize;
+    let mut zeroed: Vec<_> = tiff
+        .entries(gps)?
+        .iter()
+        .filter(|entry| entry.size > 4)
+        .map(|entry| entry.value..entry.value + entry.size)
+        .collect();
+    let gps_end = gps + 2 + usize::from(tiff.u16(gps)?) * ENTRY_SIZE + 4;
+    zeroed.push(gps..gps_end.min(exif.len()));
+
+    // The entries after the GPS one and the offset of the next IFD move up by one entry.
+    let end = ifd + 2 + entries.len() * ENTRY_SIZE + 4;
+    exif.get(..end)?;
+    let count = tiff.u16_bytes(u16::try_from(entries.len() - 1).ok()?);
+    let position = entries[index].position;
+    for range in zeroed {
+        exif[range].fill(0);
+    }
+    exif.copy_within(position + ENTRY_SIZE..end, position);
+    exif[end - ENTRY_SIZE..end].fill(0);
+    exif[ifd..ifd + 2].copy_from_slice(&count);
+    Some(exif)
+}
+
+/// Builds EXIF data with only the artist and the copyright of the image, `None` if it has neither.
+#[must_use]
+pub fn copyright(exif: &[u8]) -> Option<Vec<u8>> {
+    let tiff = Tiff::new(exif)?;
+    let mut tags: Vec<_> = tiff
+        .entries(tiff.first_ifd()?)?
+        .into_iter()
+        .filter(|entry| matches!(entry.tag, ARTIST | COPYRIGHT) && entry.kind == ASCII)
+        .map(|entry| (entry.tag, &exif[entry.value..entry.value + entry.size]))
+        .collect();
+    if tags.is_empty() {
+        return None;
+    }
+    tags.sort_by_key(|(tag, _)| *tag);
+
+    // Big endian: the header, the IFD with its entries and the values that do not fit in them.
+    let mut output = b"MM\0\x2a\0\0\0\x08".to_vec();
+    output.extend_from_slice(&u16::try_from(tags.len()).ok()?.to_be_bytes());
+    let mut values_offset = 8 + 2 + tags.len() * ENTRY_SIZE + 4;
+    let mut values = Vec::new();
+    for (tag, value) in &tags {
+        output.extend_from_slice(&tag.to_be_bytes());
+        output.extend_from_slice(&ASCII.to_be_bytes());
+        output.extend_from_slice(&u32::try_from(value.len()).ok()?.to_be_bytes());
+        if value.len() <= 4 {
+            let mut inline = [0; 4];
+            inline[..value.len()].copy_from_slice(value);
+            output.extend_from_slice(&inline);
+        } else {
+            output.extend_from_slice(&u32::try_from(values_offset).ok()?.to_be_bytes());
+            values.extend_from_slice(value);
+            values_offset += value.len();
+        }
+    }
+    output.extend_from_slice(&[0; 4]);
+    output.extend_from_slice(&values);
+    Some(output)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    /// The latitude of [`sample`], 52° 13' 30".
+    pub(crate) const LATITUDE: [u8; 24] = [
+        52, 0, 0, 0, 1, 0, 0, 0, 13, 0, 0, 0, 1, 0, 0, 0, 184, 11, 0, 0, 100, 0, 0, 0,
+    ];
+
+    /// Little endian EXIF data turned by 90°, with an artist, a copyright and a location.
+    pub(crate) fn sample() -> Vec<u8> {
+        let entry = |tag: u16, kind: u16, count: u32, value: u32| {
+            [
+                tag.to_le_bytes().as_slice(),
+                &kind.to_le_bytes(),
+                &count.to_le_bytes(),
+                &value.to_le_bytes(),
+            ]
+            .concat()
+        };
+        [
+            b"II\x2a\0\x08\0\0\0".as_slice(),
+            &4_u16.to_le_bytes(),
+            &entry(0x0112, 3, 1, 6),
+            &entry(0x013B, 2, 9, 62),
+            &entry(0x8298, 2, 18, 71),
+            &entry(0x8825, 4, 1, 90),
+            &[0; 4],
+            b"Jane Doe\0",
+            b"(c) 2024 Jane Doe\0",
+            &[0],
+            &2_u16.to_le_bytes(),
+            &entry(0x0001, 2, 2, u32::from(b'N')),
+            &entry(0x0002, 5, 3, 120),
+            &[0; 4],
+            &LATITUDE,
+        ]
+        .concat()
+    }
+
+    /// The tags of the first IFD with their values.
+    pub(crate) fn tags(exif: &[u8]) -> Vec<(u16, Vec<u8>)> {
+        use super::*;
+
+        let tiff = Tiff::new(exif).unwrap();
+        tiff.entries(tiff.first_ifd().unwrap())
+            .unwrap()
+            .into_iter()
+            .map(|entry| {
+                (
+                    entry.tag,
+                    exif[entry.value..entry.value + entry.size].to_vec(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn upright_orientation() {
+        use super::*;
+
+        let exif = upright(sample()).unwrap();
+
+        assert_eq!(tags(&exif)[0], (ORIENTATION, vec![1, 0]));
+        assert_eq!(tags(&exif)[1..], tags(&sample())[1..]);
+        assert_eq!(upright(b"not exif".to_vec()), None);
+    }
+
+    #[test]
+    fn without_gps_tags() {
+        use super::*;
+
+        let exif = without_gps(sample()).unwrap();
+
+        assert_eq!(exif.len(), sample().len());
+        assert_eq!(
+            tags(&exif).iter().map(|(tag, _)| *tag).collect::<Vec<_>>(),
+            [ORIENTATION, ARTIST, COPYRIGHT]
+        );
+        assert!(!exif.windows(LATITUDE.len()).any(|bytes| bytes == LATITUDE));
+        assert_eq!(without_gps(exif.clone()), Some(exif));
+    }
+
+    #[test]
+    fn copyright_only() {
+        use super::*;
+
+        let exif = copyright(&sample()).unwrap();
+
+        assert_eq!(
+            tags(&exif),
+            [
+                (ARTIST, b"Jane Doe\0".to_vec()),
+                (COPYRIGHT, b"(c) 2024 Jane Doe\0".to_vec())
+            ]
+        );
+        assert_eq!(copyright(&upright(sample()).unwrap()[..8]), None);
+    }
+}
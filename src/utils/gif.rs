@@ -0,0 +1,328 @@
+use gif::{ColorOutput, DecodeOptions, DisposalMethod, Encoder, Frame, Repeat};
+use thiserror::Error;
+
+use crate::{geometry::Geometry, Color, Dimensions, PathAccessor};
+
+use super::animation::{self, Animation, Frame as AnimationFrame};
+
+/// The speed of the color quantization, from 1 (best colors) to 30 (fastest).
+const QUANTIZATION_SPEED: i32 = 10;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Decoding(#[from] gif::DecodingError),
+    #[error(transparent)]
+    Encoding(#[from] gif::EncodingError),
+    #[error(transparent)]
+    TryFromIntError(#[from] std::num::TryFromIntError),
+    #[error("Resize({0:?})")]
+    Resize(resize::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// Returns the index of the color in the palette, adding it if there is room, the closest color otherwise.
+fn color_index(palette: &mut Vec<u8>, color: Color) -> u8 {
+    let rgb = [color.r, color.g, color.b];
+    let colors = palette.len() / 3;
+    if let Some(index) = palette.chunks_exact(3).position(|entry| entry == rgb) {
+        return u8::try_from(index).unwrap_or(u8::MAX);
+    }
+    if colors < 256 {
+        palette.truncate(colors * 3);
+        palette.extend_from_slice(&rgb);
+        return u8::try_from(colors).unwrap_or(u8::MAX);
+    }
+    let distance = |entry: &[u8]| {
+        entry
+            .iter()
+            .zip(rgb)
+            .map(|(a, b)| (i32::from(*a) - i32::from(b)).pow(2))
+            .sum::<i32>()
+    };
+    palette
+        .chunks_exact(3)
+        .enumerate()
+        .min_by_key(|(_, entry)| distance(entry))
+        .and_then(|(index, _)| u8::try_from(index).ok())
+        .unwrap_or_default()
+}
+
+/// Places the frames of the GIF on a canvas of the geometry canvas size without re-encoding the colors.
+/// The first frame is extended to the whole canvas and filled with the background color,
+/// a fully transparent color leaves the canvas transparent.
+///
+/// # Errors
+///
+/// Returns an error if the GIF cannot be decoded or encoded.
+///
+pub fn pad(input: &[u8], geometry: &Geometry, background: Color) -> Result<Vec<u8>, Error> {
+    let mut options = DecodeOptions::new();
+    options.set_color_output(ColorOutput::Indexed);
+    let mut decoder = options.read_info(input)?;
+    let mut global_palette = decoder
+        .global_palette()
+        .map(<[u8]>::to_vec)
+        .unwrap_or_default();
+    let repeat = decoder.repeat();
+    let mut frames = Vec::new();
+    while let Some(frame) = decoder.read_next_frame()? {
+        frames.push(frame.clone());
+    }
+
+    let canvas_width = u16::try_from(geometry.canvas_width)?;
+    let canvas_height = u16::try_from(geometry.canvas_height)?;
+    let x = u16::try_from(geometry.x)?;
+    let y = u16::try_from(geometry.y)?;
+
+    for frame in &mut frames {
+        frame.left += x;
+        frame.top += y;
+    }
+
+    if let Some(first) = frames.first_mut().filter(|_| background.a > 0) {
+        let index = match first.palette.as_mut() {
+            Some(palette) => color_index(palette, background),
+            None => color_index(&mut global_palette, background),
+        };
+        let mut buffer = vec![index; usize::from(canvas_width) * usize::from(canvas_height)];
+        let width = usize::from(first.width);
+        for (row, line) in first.buffer.chunks_exact(width.max(1)).enumerate() {
+            let start = (usize::from(first.top) + row) * usize::from(canvas_width)
+                + usize::from(first.left);
+            buffer[start..start + width].copy_from_slice(line);
+        }
+        *first = Frame {
+            left: 0,
+            top: 0,
+            width: canvas_width,
+            height: canvas_height,
+            buffer: buffer.into(),
+            ..first.clone()
+        };
+    }
+
+    let mut output = Vec::new();
+    {
+        let mut encoder = Encoder::new(&mut output, canvas_width, canvas_height, &global_palette)?;
+        encoder.set_repeat(repeat)?;
+        for frame in &frames {
+            encoder.write_frame(frame)?;
+        }
+    }
+    Ok(output)
+}
+
+/// Decodes the GIF and composites every frame on the canvas, following the disposal methods of the frames.
+///
+/// # Errors
+///
+/// Returns an error if the GIF cannot be decoded.
+///
+pub fn animation(input: &[u8]) -> Result<Animation, Error> {
+    let mut options = DecodeOptions::new();
+    options.set_color_output(ColorOutput::RGBA);
+    let mut decoder = options.read_info(input)?;
+    let width = usize::from(decoder.width());
+    let height = usize::from(decoder.height());
+
+    let mut canvas = vec![0; width * height * 4];
+    let mut frames = Vec::new();
+    while let Some(frame) = decoder.read_next_frame()? {
+        let previous = (frame.dispose == DisposalMethod::Previous).then(|| canvas.clone());
+        let left = usize::from(frame.left);
+        let top = usize::from(frame.top);
+        let frame_width = usize::from(frame.width);
+        // Parts of the frame outside of the canvas are not shown.
+        let visible_width = frame_width.min(width.saturating_sub(left));
+        let visible_height = if visible_width > 0 {
+            usize::from(frame.height).min(height.saturating_sub(top))
+        } else {
+            0
+        };
+        let rows = frame
+            .buffer
+            .chunks_exact((frame_width * 4).max(1))
+            .take(visible_height);
+
+        for (row, line) in rows.enumerate() {
+            let start = ((top + row) * width + left) * 4;
+            for (pixel, source) in canvas[start..start + visible_width * 4]
+                .chunks_exact_mut(4)
+                .zip(line.chunks_exact(4))
+            {
+                if source[3] > 0 {
+                    pixel.copy_from_slice(source);
+                }
+            }
+        }
+
+        frames.push(AnimationFrame {
+            data: canvas.clone(),
+            duration: u32::from(frame.delay) * 10,
+        });
+
+        match (frame.dispose, previous) {
+            (DisposalMethod::Background, _) => {
+                for row in top..top + visible_height {
+                    let start = (row * width + left) * 4;
+                    canvas[start..start + visible_width * 4].fill(0);
+                }
+            }
+            (DisposalMethod::Previous, Some(previous)) => canvas = previous,
+            _ => {}
+        }
+    }
+
+    let loop_count = match decoder.repeat() {
+        Repeat::Infinite => 0,
+        // Without a loop count the animation is played once.
+        Repeat::Finite(0) => 1,
+        Repeat::Finite(count) => count,
+    };
+
+    Ok(Animation {
+        width: u32::try_from(width)?,
+        height: u32::try_from(height)?,
+        loop_count,
+        frames,
+    })
+}
+
+/// Encodes the frames, every frame covers the whole canvas with its own palette of up to 256 colors.
+///
+/// # Errors
+///
+/// Returns an error if the canvas is too large for a GIF or the encoding fails.
+///
+pub fn encode(animation: &Animation) -> Result<Vec<u8>, Error> {
+    let width = u16::try_from(animation.width)?;
+    let height = u16::try_from(animation.height)?;
+
+    let mut output = Vec::new();
+    {
+        let mut encoder = Encoder::new(&mut output, width, height, &[])?;
+        match animation.loop_count {
+            0 => encoder.set_repeat(Repeat::Infinite)?,
+            // Played once, without a loop count.
+            1 => {}
+            count => encoder.set_repeat(Repeat::Finite(count))?,
+        }
+        for frame in &animation.frames {
+            let mut data = frame.data.clone();
+            let mut gif_frame =
+                Frame::from_rgba_speed(width, height, &mut data, QUANTIZATION_SPEED);
+            gif_frame.delay = u16::try_from(frame.duration / 10).unwrap_or(u16::MAX);
+            // Transparent pixels must not show the previous frame.
+            gif_frame.dispose = DisposalMethod::Background;
+            encoder.write_frame(&gif_frame)?;
+        }
+    }
+    Ok(output)
+}
+
+/// Crops, resizes and pads every frame of the GIF without any external command.
+///
+/// # Errors
+///
+/// Returns an error if the GIF cannot be decoded or encoded.
+///
+pub fn optimize_bytes<T>(input: &[u8], config: &T) -> Result<Vec<u8>, Error>
+where
+    T: Dimensions,
+{
+    let animation = animation::transform(animation(input)?, config).map_err(Error::Resize)?;
+    encode(&animation)
+}
+
+/// # Errors
+///
+/// Returns an error if the GIF cannot be read, decoded or encoded.
+///
+pub fn optimize<T>(config: &T) -> Result<(), Error>
+where
+    T: PathAccessor + Dimensions,
+{
+    super::optimize_file(config, |input| optimize_bytes(input, config))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geometry::Rect;
+
+    #[test]
+    fn gif_pad() {
+        use super::*;
+
+        let input = include_bytes!("../../tests/files/gif2gif_test1.gif");
+        let decoder = DecodeOptions::new().read_info(&input[..]).unwrap();
+        let (width, height) = (u32::from(decoder.width()), u32::from(decoder.height()));
+
+        let output = pad(
+            input,
+            &Geometry {
+                source_width: width,
+                source_height: height,
+                crop: Rect {
+                    x: 0,
+                    y: 0,
+                    width,
+                    height,
+                },
+                width,
+                height,
+                canvas_width: width + 10,
+                canvas_height: height + 20,
+                x: 5,
+                y: 10,
+            },
+            Color::default(),
+        )
+        .unwrap();
+
+        let decoder = DecodeOptions::new().read_info(&output[..]).unwrap();
+        assert_eq!(
+            (u32::from(decoder.width()), u32::from(decoder.height())),
+            (width + 10, height + 20)
+        );
+    }
+
+    #[test]
+    fn gif_animation() {
+        use super::*;
+
+        let animation = animation(include_bytes!("../../tests/files/gif2webp_test1.gif")).unwrap();
+
+        assert!(animation.frames.len() > 1);
+        assert!(animation
+            .frames
+            .iter()
+            .all(|frame| frame.data.len()
+                == animation.width as usize * animation.height as usize * 4));
+    }
+
+    #[test]
+    fn gif_optimize_bytes() {
+        use super::*;
+        use crate::Options;
+
+        let input = include_bytes!("../../tests/files/gif2webp_test1.gif");
+
+        let contents = optimize_bytes(
+            input,
+            &Options {
+                width: Some(50),
+                height: Some(50),
+                ..Options::default()
+            },
+        )
+        .unwrap();
+
+        let source = animation(input).unwrap();
+        let resized = animation(&contents).unwrap();
+        assert_eq!(resized.width.max(resized.height), 50);
+        assert_eq!(resized.frames.len(), source.frames.len());
+        assert_eq!(resized.loop_count, source.loop_count);
+    }
+}
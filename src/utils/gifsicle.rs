@@ -1,43 +1,165 @@
+use std::{
+    io::{Read, Write},
+    process::{Child, Command, ExitStatus, Output, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
 use thiserror::Error;
 
-use crate::{Dimensions, PathAccessor, Quality};
+use crate::{
+    geometry::{geometry, Geometry},
+    tools::{tools, Tool},
+    Dimensions, Gravity, PathAccessor, Quality,
+};
 
-fn to_args<T>(config: &T) -> String
+use super::{gif, smartcrop};
+
+/// Reads the logical screen size from the GIF header.
+fn screen_size(header: &[u8]) -> Option<(u32, u32)> {
+    let size = header.get(6..10)?;
+    Some((
+        u32::from(u16::from_le_bytes([size[0], size[1]])),
+        u32::from(u16::from_le_bytes([size[2], size[3]])),
+    ))
+}
+
+/// The gifsicle arguments, the tuning arguments of the tool follow the generated ones
+/// and are overridden by the optimization level of the options.
+/// The crop applies to the input file given after them.
+/// Every argument is passed to the command directly, never through a shell.
+fn to_arg_vec<T>(config: &T, geometry: Option<&Geometry>, tool: &Tool) -> Vec<String>
 where
-    T: PathAccessor + Dimensions + Quality,
+    T: Quality,
 {
-    let output_path = config.output_path().display();
-    let input_path = config.input_path().display();
-    let mut result = String::from("-O3");
-    if let Some(width) = config.width() {
-        result = format!("{result} --resize-width {width}");
+    let mut result = Vec::new();
+    if let Some(geometry) = geometry.filter(|geometry| geometry.resizes()) {
+        result.extend([
+            String::from("--resize"),
+            format!("{}x{}", geometry.width, geometry.height),
+        ]);
+    }
+    let options = config.encoder().gif;
+    if let Some(colors) = options.colors {
+        result.extend([String::from("--colors"), colors.clamp(2, 256).to_string()]);
+    }
+    if let Some(lossy) = options
+        .lossy
+        .or_else(|| config.quality().map(|quality| 100 - quality))
+    {
+        result.extend([format!("--lossy={lossy}"), String::from("--dither")]);
     }
-    if let Some(height) = config.height() {
-        result = format!("{result} --resize-height {height}");
+    result.extend(tool.args.iter().cloned());
+    if let Some(level) = options.optimization_level {
+        result.push(format!("-O{}", level.clamp(1, 3)));
     }
-    if let Some(mut quality) = config.quality() {
-        quality = 100 - quality;
-        result = format!("{result} --lossy={quality} --dither");
+    if let Some(geometry) = geometry.filter(|geometry| geometry.crops()) {
+        let crop = geometry.crop;
+        result.extend([
+            String::from("--crop"),
+            format!("{},{}+{}x{}", crop.x, crop.y, crop.width, crop.height),
+        ]);
     }
-    format!("{result} {input_path} --output {output_path}")
+    result
 }
 
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("Io({0})")]
-    Io(std::io::Error),
-    #[error("Exit({0})")]
-    Exit(i32),
-    #[error("Signal")]
-    Signal,
+    Io(#[from] std::io::Error),
+    #[error("Exit({0}): {1}")]
+    Exit(i32, String),
+    #[error("Signal: {0}")]
+    Signal(String),
+    #[error("Gif({0})")]
+    Gif(gif::Error),
+    #[error("Timeout({0:?})")]
+    Timeout(Duration),
 }
 
-fn process_exit_code(code: Option<i32>) -> Result<(), Error> {
-    match code {
+/// Fails when the command did not exit successfully, with what it wrote to stderr.
+fn process_exit_code(output: &Output) -> Result<(), Error> {
+    let stderr = || {
+        String::from_utf8_lossy(&output.stderr)
+            .trim_end()
+            .to_string()
+    };
+    match output.status.code() {
         Some(0) => Ok(()),
-        Some(code) => Err(Error::Exit(code)),
-        None => Err(Error::Signal),
+        Some(code) => Err(Error::Exit(code, stderr())),
+        None => Err(Error::Signal(stderr())),
+    }
+}
+
+/// Waits for the child, killing it once the timeout passes.
+fn wait(child: &mut Child, timeout: Option<Duration>) -> Result<ExitStatus, Error> {
+    let Some(timeout) = timeout else {
+        return child.wait().map_err(Error::Io);
+    };
+    let start = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait().map_err(Error::Io)? {
+            return Ok(status);
+        }
+        if start.elapsed() >= timeout {
+            child.kill().map_err(Error::Io)?;
+            child.wait().map_err(Error::Io)?;
+            return Err(Error::Timeout(timeout));
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn read_all<R: Read>(pipe: Option<R>) -> Vec<u8> {
+    let mut contents = Vec::new();
+    if let Some(mut pipe) = pipe {
+        let _ = pipe.read_to_end(&mut contents);
     }
+    contents
+}
+
+/// Runs the command with the `input` on stdin, killing it after the timeout of the tool.
+fn run(mut command: Command, input: &[u8], tool: &Tool) -> Result<Output, Error> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(Error::Io)?;
+
+    thread::scope(|scope| {
+        let mut stdin = child.stdin.take();
+        // Feed stdin and drain stdout and stderr from other threads, so a full pipe cannot block the command.
+        scope.spawn(move || stdin.as_mut().map(|stdin| stdin.write_all(input)));
+        let (stdout, stderr) = (child.stdout.take(), child.stderr.take());
+        let stdout = scope.spawn(move || read_all(stdout));
+        let stderr = scope.spawn(move || read_all(stderr));
+        let status = wait(&mut child, tool.timeout)?;
+        Ok(Output {
+            status,
+            stdout: stdout.join().unwrap_or_default(),
+            stderr: stderr.join().unwrap_or_default(),
+        })
+    })
+}
+
+/// The version printed by `gifsicle --version`, e.g. `1.93`.
+///
+/// # Errors
+///
+/// Returns an error if the gifsicle command cannot be run or fails.
+///
+pub fn version() -> Result<String, Error> {
+    let tool = tools().gifsicle;
+    let mut command = Command::new(&tool.path);
+    command.arg("--version");
+    let output = run(command, &[], &tool)?;
+    process_exit_code(&output)?;
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().last())
+        .unwrap_or_default()
+        .to_string())
 }
 
 /// # Errors
@@ -48,38 +170,63 @@ pub fn optimize<T>(config: &T) -> Result<(), Error>
 where
     T: PathAccessor + Dimensions + Quality,
 {
-    let args = to_args(config);
-    let command = format!("gifsicle {args}");
-    let output = std::process::Command::new("sh")
-        .arg("-c")
-        .arg(command)
-        .output()
-        .map_err(Error::Io)?;
+    super::optimize_file(config, |input| optimize_bytes(input, config))
+}
 
-    println!("status: {}", output.status);
-    println!("stdout: {}", String::from_utf8_lossy(&output.stdout));
-    println!("stderr: {}", String::from_utf8_lossy(&output.stderr));
+/// Runs gifsicle on the GIF data, reading it from stdin and writing the result to stdout.
+///
+/// # Errors
+///
+/// Returns an error if the gifsicle command fails.
+///
+pub fn optimize_bytes<T>(input: &[u8], config: &T) -> Result<Vec<u8>, Error>
+where
+    T: Dimensions + Quality,
+{
+    let geometry = screen_size(input).map(|(width, height)| {
+        // The crop window of the attention gravity is chosen on the first frame.
+        let first_frame = (config.gravity() == Gravity::Attention)
+            .then(|| image::load_from_memory(input).ok())
+            .flatten();
+        match first_frame {
+            Some(image) => smartcrop::geometry(image.to_rgba8().as_raw(), width, height, config),
+            None => geometry(width, height, config),
+        }
+    });
+    let tool = tools().gifsicle;
+    let mut command = Command::new(&tool.path);
+    command
+        .args(to_arg_vec(config, geometry.as_ref(), &tool))
+        .arg("-");
+    let output = run(command, input, &tool)?;
 
-    let code = output.status.code();
+    process_exit_code(&output)?;
 
-    process_exit_code(code)
+    match geometry.filter(Geometry::pads) {
+        Some(geometry) => {
+            gif::pad(&output.stdout, &geometry, config.background()).map_err(Error::Gif)
+        }
+        None => Ok(output.stdout),
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{Config, ConfigBuilder};
+    use crate::{Config, ConfigBuilder, Options};
 
     #[test]
     fn gifsicle() {
         use super::*;
 
-        optimize(&ConfigBuilder::default()
-            .input_path("tests/files/gifsicle_test1.gif")
-            .output_path("target/gifsicle_test1.gif")
-            .width(Some(100))
-            .build()
-            .unwrap()
-        ).unwrap();
+        optimize(
+            &ConfigBuilder::default()
+                .input_path("tests/files/gifsicle_test1.gif")
+                .output_path("target/gifsicle_test1.gif")
+                .width(Some(100))
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
     }
 
     #[test]
@@ -109,7 +256,7 @@ mod tests {
     }
 
     #[test]
-    #[should_panic = "Exit(1)"]
+    #[should_panic = "NotFound"]
     fn gifsicle_panic() {
         use super::*;
 
@@ -123,24 +270,208 @@ mod tests {
     }
 
     #[test]
-    #[should_panic = "Signal"]
+    fn gifsicle_hostile_file_names() {
+        use super::*;
+        use crate::utils::HOSTILE_FILE_NAMES;
+        use std::path::Path;
+
+        let dir = Path::new("target/hostile/gifsicle");
+        std::fs::create_dir_all(dir).unwrap();
+        for name in HOSTILE_FILE_NAMES {
+            let input = dir.join(name);
+            let output = dir.join(format!("out {name}"));
+            std::fs::copy("tests/files/gifsicle_test1.gif", &input).unwrap();
+
+            optimize(&Config::new(
+                input.to_str().unwrap(),
+                output.to_str().unwrap(),
+                Some(100),
+                None,
+            ))
+            .unwrap();
+
+            assert!(output.exists(), "{name}");
+        }
+        assert!(!Path::new("pwned").exists());
+        assert!(!Path::new("pwned.gif").exists());
+    }
+
+    #[test]
+    fn gifsicle_cover_args() {
+        use super::*;
+        use crate::ResizeMode;
+
+        let options = Options {
+            width: Some(100),
+            height: Some(100),
+            resize_mode: ResizeMode::Cover,
+            ..Options::default()
+        };
+
+        assert_eq!(
+            to_arg_vec(
+                &options,
+                Some(&geometry(400, 300, &options)),
+                &tools().gifsicle
+            ),
+            ["--resize", "100x100", "-O3", "--crop", "50,0+300x300"]
+        );
+    }
+
+    #[test]
+    fn gifsicle_cover_attention() {
+        use super::*;
+        use crate::ResizeMode;
+        use image::{codecs::gif::GifEncoder, Frame, Rgba, RgbaImage};
+
+        // Flat gray with a saturated, high contrast square on the right, the center crop is only gray.
+        let image = RgbaImage::from_fn(200, 100, |x, y| {
+            if x >= 160 && (40..60).contains(&y) {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([128, 128, 128, 255])
+            }
+        });
+        let mut input = Vec::new();
+        GifEncoder::new(&mut input)
+            .encode_frame(Frame::new(image))
+            .unwrap();
+        std::fs::create_dir_all("target").unwrap();
+        std::fs::write("target/gifsicle_attention_input.gif", input).unwrap();
+
+        optimize(&Config {
+            input_path: "target/gifsicle_attention_input.gif".into(),
+            output_path: "target/gifsicle_attention.gif".into(),
+            options: Options {
+                width: Some(50),
+                height: Some(50),
+                resize_mode: ResizeMode::Cover,
+                gravity: Gravity::Attention,
+                ..Options::default()
+            },
+        })
+        .unwrap();
+
+        let output = image::open("target/gifsicle_attention.gif")
+            .unwrap()
+            .into_rgba8();
+        assert_eq!(output.dimensions(), (50, 50));
+        assert!(output.pixels().any(|pixel| pixel[0] > 200 && pixel[1] < 50));
+    }
+
+    #[test]
+    fn gifsicle_tool_args() {
+        use super::*;
+
+        let tool = Tool {
+            args: vec![String::from("-O2"), String::from("--no-dither")],
+            ..tools().gifsicle
+        };
+
+        assert_eq!(
+            to_arg_vec(
+                &Options {
+                    quality: Some(80),
+                    ..Options::default()
+                },
+                None,
+                &tool
+            ),
+            ["--lossy=20", "--dither", "-O2", "--no-dither"]
+        );
+    }
+
+    #[test]
+    fn gifsicle_encoder_args() {
+        use super::*;
+        use crate::encoder::{EncoderOptions, GifOptions};
+
+        let options = Options {
+            quality: Some(80),
+            encoder: EncoderOptions {
+                gif: GifOptions {
+                    colors: Some(64),
+                    lossy: Some(40),
+                    optimization_level: Some(2),
+                },
+                ..EncoderOptions::default()
+            },
+            ..Options::default()
+        };
+
+        assert_eq!(
+            to_arg_vec(&options, None, &tools().gifsicle),
+            ["--colors", "64", "--lossy=40", "--dither", "-O3", "-O2"]
+        );
+    }
+
+    #[test]
+    #[should_panic = "Timeout(100ms)"]
+    fn gifsicle_timeout_panic() {
+        use super::*;
+
+        let tool = Tool {
+            path: "sleep".into(),
+            args: Vec::new(),
+            timeout: Some(Duration::from_millis(100)),
+        };
+        let mut command = Command::new(&tool.path);
+        command.arg("10");
+
+        run(command, &[], &tool).unwrap();
+    }
+
+    #[test]
+    fn gifsicle_bytes() {
+        use super::*;
+
+        let contents = optimize_bytes(
+            include_bytes!("../../tests/files/gifsicle_test1.gif"),
+            &Options {
+                width: Some(100),
+                ..Options::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(&contents[..6], b"GIF89a");
+    }
+
+    #[test]
+    #[should_panic = "Exit(1, "]
+    fn gifsicle_bytes_panic() {
+        use super::*;
+
+        optimize_bytes(b"not a gif", &Options::default()).unwrap();
+    }
+
+    #[test]
+    #[should_panic = "Signal(\"killed\")"]
     fn process_exit_code_terminated_by_signal_panic() {
         use super::*;
+        use std::os::unix::process::ExitStatusExt;
 
-        process_exit_code(None).unwrap();
+        process_exit_code(&Output {
+            status: ExitStatus::from_raw(9),
+            stdout: Vec::new(),
+            stderr: b"killed\n".to_vec(),
+        })
+        .unwrap();
     }
 
     #[test]
     fn low_quality() {
         use super::*;
 
-        optimize(&ConfigBuilder::default()
-            .input_path("tests/files/gifsicle_test1.gif")
-            .output_path("target/gifsicle_low_quality.gif")
-            .width(Some(100))
-            .quality(Some(10))
-            .build()
-            .unwrap()
-        ).unwrap();
+        optimize(
+            &ConfigBuilder::default()
+                .input_path("tests/files/gifsicle_test1.gif")
+                .output_path("target/gifsicle_low_quality.gif")
+                .width(Some(100))
+                .quality(Some(10))
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
     }
 }
@@ -0,0 +1,94 @@
+//! Converts RGBA pixels to a [`ColorProfile`] with `moxcms`, like the `magick` backend does with its profiles.
+//!
+//! The pixels are converted from the embedded ICC profile of the input, or from sRGB when it has none
+//! or its profile is not an RGB profile, e.g. the CMYK profile of a JPEG decoded to RGB.
+
+use image::{ImageError, RgbaImage};
+use moxcms::{DataColorSpace, Layout, TransformOptions};
+
+use crate::ColorProfile;
+
+use super::orientation;
+
+/// Converts the RGBA pixels from the ICC profile to the color profile, the alpha channel is kept.
+/// The pixels are left as they are when the conversion is not possible.
+pub fn convert(data: &mut [u8], icc: Option<&[u8]>, profile: ColorProfile) {
+    let source = icc
+        .and_then(|icc| moxcms::ColorProfile::new_from_slice(icc).ok())
+        .filter(|source| source.color_space == DataColorSpace::Rgb);
+    let source = match source {
+        Some(source) => source,
+        None if profile == ColorProfile::Srgb => return,
+        None => ColorProfile::Srgb.profile(),
+    };
+    let Ok(transform) = source.create_transform_8bit(
+        Layout::Rgba,
+        &profile.profile(),
+        Layout::Rgba,
+        TransformOptions::default(),
+    ) else {
+        return;
+    };
+    let input = data.to_vec();
+    if transform.transform(&input, data).is_err() {
+        data.copy_from_slice(&input);
+    }
+}
+
+/// Decodes the image, turned upright as described by its EXIF orientation, with its pixels in the color profile.
+///
+/// # Errors
+///
+/// Returns an error if the image cannot be decoded.
+///
+pub fn decode(input: &[u8], profile: ColorProfile) -> Result<RgbaImage, ImageError> {
+    let (image, icc) = orientation::decode_with_icc(input)?;
+    let mut image = image.into_rgba8();
+    convert(&mut image, icc.as_deref(), profile);
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    /// Whether the pixels differ by at most one in each channel, the rounding of the SIMD paths of `moxcms` differs.
+    fn close(data: &[u8], expected: &[u8]) -> bool {
+        data.len() == expected.len()
+            && data
+                .iter()
+                .zip(expected)
+                .all(|(value, expected)| value.abs_diff(*expected) <= 1)
+    }
+
+    #[test]
+    fn convert_display_p3_to_srgb() {
+        use super::*;
+
+        let mut data = [200, 100, 50, 128, 0, 255, 0, 255];
+        convert(
+            &mut data,
+            Some(&ColorProfile::DisplayP3.icc()),
+            ColorProfile::Srgb,
+        );
+
+        // More saturated in sRGB, the green of Display P3 is out of its gamut.
+        assert!(
+            close(&data, &[215, 93, 31, 128, 0, 255, 0, 255]),
+            "{data:?}"
+        );
+    }
+
+    #[test]
+    fn convert_without_profile() {
+        use super::*;
+
+        let pixels = [200, 100, 50, 128];
+        let mut data = pixels;
+        convert(&mut data, None, ColorProfile::Srgb);
+        assert_eq!(data, pixels);
+        convert(&mut data, Some(b"not a profile"), ColorProfile::Srgb);
+        assert_eq!(data, pixels);
+
+        convert(&mut data, None, ColorProfile::DisplayP3);
+        assert!(close(&data, &[187, 105, 62, 128]), "{data:?}");
+    }
+}
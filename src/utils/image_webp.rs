@@ -0,0 +1,197 @@
+//! Encodes and decodes WebP with the pure-Rust codec of the `image` crate,
+//! used in place of libwebp when the `native` feature is disabled.
+//! The encoder is lossless only, so the quality of the config is not used.
+
+use std::io::Cursor;
+
+use image::{
+    codecs::webp::{WebPDecoder, WebPEncoder},
+    AnimationDecoder, ExtendedColorType, ImageDecoder, ImageError, Rgba,
+};
+
+use crate::Quality;
+
+use super::animation::{Animation, Frame};
+
+/// The longest duration of a frame of an animated WebP, in milliseconds.
+const MAX_DURATION: u32 = 0xFF_FFFF;
+
+/// Iterates over the four-character code and the payload of the chunks of a WebP file.
+fn chunks(data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    let mut rest = data.get(12..).unwrap_or_default();
+    std::iter::from_fn(move || {
+        let size = usize::try_from(u32::from_le_bytes(rest.get(4..8)?.try_into().ok()?)).ok()?;
+        let fourcc = &rest[..4];
+        let payload = rest.get(8..8 + size)?;
+        // Chunks are padded to an even size.
+        rest = rest.get(8 + size + size % 2..).unwrap_or_default();
+        Some((fourcc, payload))
+    })
+}
+
+fn write_chunk(output: &mut Vec<u8>, fourcc: &[u8], payload: &[u8]) {
+    output.extend_from_slice(fourcc);
+    output.extend_from_slice(
+        &u32::try_from(payload.len())
+            .unwrap_or(u32::MAX)
+            .to_le_bytes(),
+    );
+    output.extend_from_slice(payload);
+    if payload.len() % 2 == 1 {
+        output.push(0);
+    }
+}
+
+fn u24(value: u32) -> [u8; 3] {
+    let [a, b, c, _] = value.min(0xFF_FFFF).to_le_bytes();
+    [a, b, c]
+}
+
+/// Encodes the RGBA pixels without any loss.
+///
+/// # Errors
+///
+/// Returns an error if the encoding fails.
+///
+pub fn encode<T>(data: &[u8], width: u32, height: u32, _config: &T) -> Result<Vec<u8>, ImageError>
+where
+    T: Quality,
+{
+    let mut output = Vec::new();
+    WebPEncoder::new_lossless(&mut output).encode(data, width, height, ExtendedColorType::Rgba8)?;
+    Ok(output)
+}
+
+/// Encodes the frames into an animated WebP, keeping the frame timings and the loop count.
+/// Every frame covers the whole canvas and replaces the previous one.
+///
+/// # Errors
+///
+/// Returns an error if the encoding fails.
+///
+pub fn encode_animation<T>(animation: &Animation, config: &T) -> Result<Vec<u8>, ImageError>
+where
+    T: Quality,
+{
+    let width = u24(animation.width.saturating_sub(1));
+    let height = u24(animation.height.saturating_sub(1));
+
+    let mut body = b"WEBP".to_vec();
+    // Alpha and animation flags, followed by the canvas size.
+    let mut header = vec![0x10 | 0x02, 0, 0, 0];
+    header.extend_from_slice(&width);
+    header.extend_from_slice(&height);
+    write_chunk(&mut body, b"VP8X", &header);
+
+    // A transparent background color, followed by the loop count.
+    let mut anim = vec![0; 4];
+    anim.extend_from_slice(&animation.loop_count.to_le_bytes());
+    write_chunk(&mut body, b"ANIM", &anim);
+
+    for frame in &animation.frames {
+        let still = encode(&frame.data, animation.width, animation.height, config)?;
+        let mut anmf = vec![0; 6];
+        anmf.extend_from_slice(&width);
+        anmf.extend_from_slice(&height);
+        anmf.extend_from_slice(&u24(frame.duration.min(MAX_DURATION)));
+        // No blending, the frame is not disposed.
+        anmf.push(0b10);
+        for (fourcc, payload) in chunks(&still) {
+            if matches!(fourcc, b"ALPH" | b"VP8 " | b"VP8L") {
+                write_chunk(&mut anmf, fourcc, payload);
+            }
+        }
+        write_chunk(&mut body, b"ANMF", &anmf);
+    }
+
+    let mut output = Vec::new();
+    write_chunk(&mut output, b"RIFF", &body);
+    Ok(output)
+}
+
+/// Whether the data is an animated WebP.
+#[must_use]
+pub fn is_animation(input: &[u8]) -> bool {
+    WebPDecoder::new(Cursor::new(input)).is_ok_and(|decoder| decoder.has_animation())
+}
+
+/// Decodes the animated WebP into frames composited on a transparent canvas, like libwebp.
+///
+/// # Errors
+///
+/// Returns an error if the decoding fails.
+///
+pub fn decode_animation(input: &[u8]) -> Result<Animation, ImageError> {
+    let mut decoder = WebPDecoder::new(Cursor::new(input))?;
+    // Without a background color the frames disposed to the background are not cleared.
+    decoder.set_background_color(Rgba([0, 0, 0, 0]))?;
+    let (width, height) = decoder.dimensions();
+    let loop_count = chunks(input)
+        .find(|(fourcc, _)| *fourcc == b"ANIM")
+        .and_then(|(_, payload)| payload.get(4..6))
+        .map_or(0, |count| u16::from_le_bytes([count[0], count[1]]));
+
+    let frames = decoder
+        .into_frames()
+        .map(|frame| {
+            let frame = frame?;
+            let (numerator, denominator) = frame.delay().numer_denom_ms();
+            Ok(Frame {
+                duration: numerator / denominator.max(1),
+                data: frame.into_buffer().into_raw(),
+            })
+        })
+        .collect::<Result<Vec<_>, ImageError>>()?;
+
+    Ok(Animation {
+        width,
+        height,
+        loop_count,
+        frames,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Options;
+
+    #[test]
+    fn encode_and_decode_animation() {
+        use super::*;
+
+        let animation = Animation {
+            width: 3,
+            height: 2,
+            loop_count: 2,
+            frames: vec![
+                Frame {
+                    data: [255, 0, 0, 255].repeat(6),
+                    duration: 100,
+                },
+                Frame {
+                    data: [0, 0, 255, 128].repeat(6),
+                    duration: 250,
+                },
+            ],
+        };
+
+        let contents = encode_animation(&animation, &Options::default()).unwrap();
+
+        assert!(is_animation(&contents));
+        let decoded = decode_animation(&contents).unwrap();
+        assert_eq!((decoded.width, decoded.height), (3, 2));
+        assert_eq!(decoded.loop_count, 2);
+        assert_eq!(
+            decoded
+                .frames
+                .iter()
+                .map(|frame| (frame.duration, &frame.data))
+                .collect::<Vec<_>>(),
+            animation
+                .frames
+                .iter()
+                .map(|frame| (frame.duration, &frame.data))
+                .collect::<Vec<_>>()
+        );
+    }
+}
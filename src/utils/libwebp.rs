@@ -0,0 +1,316 @@
+use std::ffi::CStr;
+use thiserror::Error;
+
+use libwebp_sys::{
+    VP8StatusCode, WebPAnimDecoder, WebPAnimDecoderDelete, WebPAnimDecoderGetInfo,
+    WebPAnimDecoderGetNext, WebPAnimDecoderHasMoreFrames, WebPAnimDecoderNew,
+    WebPAnimDecoderOptions, WebPAnimDecoderOptionsInit, WebPAnimEncoder, WebPAnimEncoderAdd,
+    WebPAnimEncoderAssemble, WebPAnimEncoderDelete, WebPAnimEncoderGetError,
+    WebPAnimEncoderNewInternal, WebPAnimEncoderOptions, WebPAnimEncoderOptionsInitInternal,
+    WebPAnimInfo, WebPBitstreamFeatures, WebPConfig, WebPData, WebPDataClear, WebPEncode,
+    WebPEncodingError, WebPGetEncoderVersion, WebPGetFeatures, WebPMemoryWrite, WebPMemoryWriter,
+    WebPMemoryWriterClear, WebPMemoryWriterInit, WebPPicture, WebPPictureFree,
+    WebPPictureImportRGBA, WebPValidateConfig, WEBP_CSP_MODE, WEBP_MUX_ABI_VERSION,
+};
+
+use crate::Quality;
+
+use super::animation::{Animation, Frame};
+
+pub struct RGBAImage {
+    pub data: *const u8,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Error)]
+pub enum LibWebPError {
+    #[error("Failed to initialize WebP config")]
+    ConfigInit(()),
+    #[error("Failed to validate WebP config")]
+    ConfigValidate,
+    #[error("Failed to initialize WebP picture")]
+    Picture(()),
+    #[error("Failed to encode WebP image: {0:?}")]
+    Encoding(WebPEncodingError),
+    #[error("Failed to initialize WebP animation encoder")]
+    AnimEncoderInit,
+    #[error("Failed to encode WebP animation: {0}")]
+    AnimEncoding(String),
+    #[error("Failed to decode WebP animation")]
+    AnimDecoding,
+    #[error(transparent)]
+    TryFromIntError(#[from] std::num::TryFromIntError),
+}
+
+/// The version of the linked libwebp, e.g. `1.4.0`.
+#[must_use]
+pub fn version() -> String {
+    let version = unsafe { WebPGetEncoderVersion() };
+    format!(
+        "{}.{}.{}",
+        (version >> 16) & 0xFF,
+        (version >> 8) & 0xFF,
+        version & 0xFF
+    )
+}
+
+/// Lossy with the quality of the config, lossless without one, tuned by the WebP encoder options.
+fn webp_config<T>(config: &T) -> Result<WebPConfig, LibWebPError>
+where
+    T: Quality,
+{
+    let mut webp_config = WebPConfig::new().map_err(LibWebPError::ConfigInit)?;
+    let options = config.encoder().webp;
+
+    #[allow(clippy::cast_precision_loss)]
+    if let Some(quality) = config.quality() {
+        webp_config.lossless = 0;
+        webp_config.alpha_compression = 1;
+        webp_config.quality = quality as f32;
+    } else {
+        webp_config.lossless = 1;
+        webp_config.alpha_compression = 1;
+        webp_config.quality = 100.0;
+    }
+
+    if let Some(method) = options.method {
+        webp_config.method = i32::from(method);
+    }
+    if let Some(near_lossless) = options.near_lossless {
+        webp_config.near_lossless = i32::from(near_lossless);
+    }
+    if let Some(alpha_quality) = options.alpha_quality {
+        webp_config.alpha_quality = i32::from(alpha_quality);
+    }
+    webp_config.use_sharp_yuv = i32::from(options.sharp_yuv);
+    webp_config.exact = i32::from(options.exact);
+
+    if unsafe { WebPValidateConfig(&webp_config) } == 0 {
+        return Err(LibWebPError::ConfigValidate);
+    }
+
+    Ok(webp_config)
+}
+
+/// Encodes the RGBA pixels, lossy with the quality of the config and lossless without one.
+///
+/// # Errors
+///
+/// Returns an error if the encoding fails.
+///
+pub fn encode<T>(data: &[u8], width: u32, height: u32, config: &T) -> Result<Vec<u8>, LibWebPError>
+where
+    T: Quality,
+{
+    rgba_to_webp(
+        &RGBAImage {
+            data: data.as_ptr(),
+            width,
+            height,
+        },
+        config,
+    )
+}
+
+fn rgba_to_webp<T>(image: &RGBAImage, config: &T) -> Result<Vec<u8>, LibWebPError>
+where
+    T: Quality,
+{
+    let webp_config = webp_config(config)?;
+
+    let mut picture = WebPPicture::new().map_err(LibWebPError::Picture)?;
+    picture.use_argb = 1;
+    picture.width = i32::try_from(image.width).map_err(LibWebPError::TryFromIntError)?;
+    picture.height = i32::try_from(image.height).map_err(LibWebPError::TryFromIntError)?;
+
+    let mut ww: ::core::mem::MaybeUninit<WebPMemoryWriter> = ::core::mem::MaybeUninit::uninit();
+    picture.writer = Some(WebPMemoryWrite);
+    picture.custom_ptr = ww.as_mut_ptr().cast::<std::ffi::c_void>();
+
+    unsafe {
+        let memory_writer_ptr = ww.as_mut_ptr();
+
+        WebPMemoryWriterInit(memory_writer_ptr);
+
+        let rgba_stride = i32::try_from(image.width * 4).map_err(LibWebPError::TryFromIntError)?;
+
+        WebPPictureImportRGBA(&mut picture, image.data, rgba_stride);
+
+        let encode_result = WebPEncode(&webp_config, &mut picture);
+
+        // WebPEncode returns false, not a status code, when it fails.
+        if encode_result == 0 {
+            return Err(LibWebPError::Encoding(picture.error_code));
+        }
+
+        let memory_writer = ww.assume_init();
+        let contents = std::slice::from_raw_parts(memory_writer.mem, memory_writer.size).to_vec();
+
+        WebPPictureFree(&mut picture);
+        WebPMemoryWriterClear(memory_writer_ptr);
+
+        Ok(contents)
+    }
+}
+
+/// Owns the animation encoder, so it is deleted on every return.
+struct AnimEncoder(*mut WebPAnimEncoder);
+
+impl AnimEncoder {
+    fn error(&self) -> LibWebPError {
+        let message = unsafe { CStr::from_ptr(WebPAnimEncoderGetError(self.0)) };
+        LibWebPError::AnimEncoding(message.to_string_lossy().into_owned())
+    }
+}
+
+impl Drop for AnimEncoder {
+    fn drop(&mut self) {
+        unsafe { WebPAnimEncoderDelete(self.0) };
+    }
+}
+
+/// Encodes the frames into an animated WebP, keeping the frame timings and the loop count.
+///
+/// # Errors
+///
+/// Returns an error if the encoding fails.
+///
+pub fn encode_animation<T>(animation: &Animation, config: &T) -> Result<Vec<u8>, LibWebPError>
+where
+    T: Quality,
+{
+    let webp_config = webp_config(config)?;
+    let width = i32::try_from(animation.width)?;
+    let height = i32::try_from(animation.height)?;
+    let mux_abi_version = i32::try_from(WEBP_MUX_ABI_VERSION)?;
+
+    let mut options: ::core::mem::MaybeUninit<WebPAnimEncoderOptions> =
+        ::core::mem::MaybeUninit::uninit();
+
+    unsafe {
+        if WebPAnimEncoderOptionsInitInternal(options.as_mut_ptr(), mux_abi_version) == 0 {
+            return Err(LibWebPError::AnimEncoderInit);
+        }
+        let mut options = options.assume_init();
+        options.anim_params.loop_count = i32::from(animation.loop_count);
+
+        let encoder = WebPAnimEncoderNewInternal(width, height, &options, mux_abi_version);
+        if encoder.is_null() {
+            return Err(LibWebPError::AnimEncoderInit);
+        }
+        let encoder = AnimEncoder(encoder);
+
+        let mut timestamp = 0;
+        for frame in &animation.frames {
+            let mut picture = WebPPicture::new().map_err(LibWebPError::Picture)?;
+            picture.use_argb = 1;
+            picture.width = width;
+            picture.height = height;
+
+            let imported = WebPPictureImportRGBA(&mut picture, frame.data.as_ptr(), width * 4);
+            let added = imported != 0
+                && WebPAnimEncoderAdd(encoder.0, &mut picture, timestamp, &webp_config) != 0;
+            WebPPictureFree(&mut picture);
+            if !added {
+                return Err(encoder.error());
+            }
+
+            timestamp += i32::try_from(frame.duration)?;
+        }
+
+        // The last call without a frame sets the duration of the last frame.
+        if WebPAnimEncoderAdd(encoder.0, std::ptr::null_mut(), timestamp, std::ptr::null()) == 0 {
+            return Err(encoder.error());
+        }
+
+        let mut data = WebPData::default();
+        if WebPAnimEncoderAssemble(encoder.0, &mut data) == 0 {
+            return Err(encoder.error());
+        }
+        let contents = std::slice::from_raw_parts(data.bytes, data.size).to_vec();
+        WebPDataClear(&mut data);
+
+        Ok(contents)
+    }
+}
+
+/// Whether the data is an animated WebP.
+#[must_use]
+pub fn is_animation(input: &[u8]) -> bool {
+    let mut features: ::core::mem::MaybeUninit<WebPBitstreamFeatures> =
+        ::core::mem::MaybeUninit::uninit();
+    unsafe {
+        WebPGetFeatures(input.as_ptr(), input.len(), features.as_mut_ptr())
+            == VP8StatusCode::VP8_STATUS_OK
+            && features.assume_init().has_animation != 0
+    }
+}
+
+/// Owns the animation decoder, so it is deleted on every return.
+struct AnimDecoder(*mut WebPAnimDecoder);
+
+impl Drop for AnimDecoder {
+    fn drop(&mut self) {
+        unsafe { WebPAnimDecoderDelete(self.0) };
+    }
+}
+
+/// Decodes the animated WebP into frames composited on the canvas.
+///
+/// # Errors
+///
+/// Returns an error if the decoding fails.
+///
+pub fn decode_animation(input: &[u8]) -> Result<Animation, LibWebPError> {
+    let data = WebPData {
+        bytes: input.as_ptr(),
+        size: input.len(),
+    };
+    let mut options: ::core::mem::MaybeUninit<WebPAnimDecoderOptions> =
+        ::core::mem::MaybeUninit::uninit();
+
+    unsafe {
+        if WebPAnimDecoderOptionsInit(options.as_mut_ptr()) == 0 {
+            return Err(LibWebPError::AnimDecoding);
+        }
+        let mut options = options.assume_init();
+        options.color_mode = WEBP_CSP_MODE::MODE_RGBA;
+        options.use_threads = 1;
+
+        let decoder = WebPAnimDecoderNew(&data, &options);
+        if decoder.is_null() {
+            return Err(LibWebPError::AnimDecoding);
+        }
+        let decoder = AnimDecoder(decoder);
+
+        let mut info = WebPAnimInfo::default();
+        if WebPAnimDecoderGetInfo(decoder.0, &mut info) == 0 {
+            return Err(LibWebPError::AnimDecoding);
+        }
+        let size = info.canvas_width as usize * info.canvas_height as usize * 4;
+
+        let mut frames = Vec::new();
+        let mut previous = 0;
+        while WebPAnimDecoderHasMoreFrames(decoder.0) != 0 {
+            let mut buffer = std::ptr::null_mut();
+            // The timestamp is the time at which the frame ends.
+            let mut timestamp = 0;
+            if WebPAnimDecoderGetNext(decoder.0, &mut buffer, &mut timestamp) == 0 {
+                return Err(LibWebPError::AnimDecoding);
+            }
+            frames.push(Frame {
+                data: std::slice::from_raw_parts(buffer, size).to_vec(),
+                duration: u32::try_from(timestamp - previous)?,
+            });
+            previous = timestamp;
+        }
+
+        Ok(Animation {
+            width: info.canvas_width,
+            height: info.canvas_height,
+            loop_count: u16::try_from(info.loop_count)?,
+            frames,
+        })
+    }
+}
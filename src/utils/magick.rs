@@ -1,64 +1,170 @@
 #![allow(clippy::cast_precision_loss)]
 
-use magick_rust::{magick_wand_genesis, MagickWand};
-use std::{fs::create_dir_all, sync::Once};
+use magick_rust::{magick_wand_genesis, InterlaceType, MagickWand, PixelWand};
+use std::{
+    ffi::{c_char, CStr},
+    sync::Once,
+};
 use thiserror::Error;
 
-use crate::{Dimensions, PathAccessor, Quality};
+use crate::{
+    encoder::{ChromaSubsampling, JpegOptions},
+    extensions::{self, JPEG},
+    geometry::geometry,
+    ColorProfile, Dimensions, Gravity, PathAccessor, Quality,
+};
 
-use super::fit;
+use super::smartcrop;
 
 static START: Once = Once::new();
 
+extern "C" {
+    fn MagickGetVersion(version: *mut usize) -> *const c_char;
+    fn GetMagickDelegates() -> *const c_char;
+}
+
+/// Copies a string owned by the library, which is never freed.
+fn static_str(string: *const c_char) -> Option<String> {
+    (!string.is_null()).then(|| {
+        unsafe { CStr::from_ptr(string) }
+            .to_string_lossy()
+            .into_owned()
+    })
+}
+
+/// The version of the linked library, e.g. `7.1.1-38`.
+#[must_use]
+pub fn version() -> Option<String> {
+    let mut number = 0;
+    static_str(unsafe { MagickGetVersion(&mut number) })?
+        .split_whitespace()
+        .nth(1)
+        .map(str::to_string)
+}
+
+/// The libraries the linked `magick` library reads and writes formats with, e.g. `jpeg`, `png` and `webp`.
+#[must_use]
+pub fn delegates() -> Vec<String> {
+    static_str(unsafe { GetMagickDelegates() })
+        .map(|delegates| delegates.split_whitespace().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("Magick({0})")]
     Magick(magick_rust::MagickError),
     #[error("Io({0})")]
-    Io(std::io::Error),
+    Io(#[from] std::io::Error),
     #[error(transparent)]
     TryFromIntError(#[from] std::num::TryFromIntError),
 }
 
-/// # Errors
-///
-/// Returns an error if the optimization fails.
-///
-pub fn optimize<T>(config: &T, filter: Option<magick_rust::FilterType>) -> Result<(), Error>
-where
-    T: PathAccessor + Dimensions + Quality,
-{
-    START.call_once(|| {
-        magick_wand_genesis();
-    });
-    let mut wand = MagickWand::new();
-    wand.read_image(&config.input_path().display().to_string())
-        .map_err(Error::Magick)?;
+/// Sets the options of the JPEG encoder, used only when the image is written as JPEG.
+fn set_jpeg_options(wand: &mut MagickWand, options: JpegOptions) -> Result<(), Error> {
+    if options.progressive {
+        wand.set_interlace_scheme(InterlaceType::Line)
+            .map_err(Error::Magick)?;
+    }
+    if let Some(chroma_subsampling) = options.chroma_subsampling {
+        let sampling_factor = match chroma_subsampling {
+            ChromaSubsampling::Yuv444 => "1x1",
+            ChromaSubsampling::Yuv422 => "2x1",
+            ChromaSubsampling::Yuv420 => "2x2",
+        };
+        wand.set_option("jpeg:sampling-factor", sampling_factor)
+            .map_err(Error::Magick)?;
+    }
+    if options.optimize_coding {
+        wand.set_option("jpeg:optimize-coding", "true")
+            .map_err(Error::Magick)?;
+    }
+    Ok(())
+}
 
-    let width = config
-        .width()
-        .map_or(wand.get_image_width(), |s| s as usize);
-    let height = config
-        .height()
-        .map_or(wand.get_image_height(), |s| s as usize);
+/// Converts the pixels from the embedded ICC profile, or from sRGB without one, to the color profile.
+/// Like [`icc::convert`](super::icc::convert), the pixels are left as they are when the profile cannot be used.
+fn convert_profile(wand: &MagickWand, profile: ColorProfile) {
+    let embedded = wand
+        .get_image_profile("icc")
+        .is_ok_and(|icc| !icc.is_empty());
+    if !embedded {
+        if profile == ColorProfile::Srgb {
+            return;
+        }
+        // Assigns the sRGB profile, the next one is converted to.
+        if wand
+            .profile_image("icc", ColorProfile::Srgb.icc().as_slice())
+            .is_err()
+        {
+            return;
+        }
+    }
+    let _ = wand.profile_image("icc", profile.icc().as_slice());
+}
 
+fn process<T>(
+    wand: &MagickWand,
+    config: &T,
+    filter: Option<magick_rust::FilterType>,
+) -> Result<(), Error>
+where
+    T: Dimensions + Quality,
+{
     wand.auto_orient();
+    convert_profile(wand, config.color_profile());
     wand.strip_image().map_err(Error::Magick)?;
 
-    let (new_width, new_height) = fit(
-        u32::try_from(wand.get_image_width()).map_err(Error::TryFromIntError)?,
-        u32::try_from(wand.get_image_height()).map_err(Error::TryFromIntError)?,
-        u32::try_from(width).map_err(Error::TryFromIntError)?,
-        u32::try_from(height).map_err(Error::TryFromIntError)?,
-    );
-
-    if let Some(filter) = filter {
-        let _ = wand
-            .resize_image(new_width as usize, new_height as usize, filter)
-            .map_err(Error::Magick);
+    let width = u32::try_from(wand.get_image_width()).map_err(Error::TryFromIntError)?;
+    let height = u32::try_from(wand.get_image_height()).map_err(Error::TryFromIntError)?;
+    let geometry = if config.gravity() == Gravity::Attention {
+        let pixels = wand
+            .export_image_pixels(0, 0, width as usize, height as usize, "RGBA")
+            .unwrap_or_default();
+        if pixels.len() == width as usize * height as usize * 4 {
+            smartcrop::geometry(&pixels, width, height, config)
+        } else {
+            geometry(width, height, config)
+        }
     } else {
-        wand.adaptive_resize_image(new_width as usize, new_height as usize)
+        geometry(width, height, config)
+    };
+
+    if geometry.crops() {
+        wand.crop_image(
+            geometry.crop.width as usize,
+            geometry.crop.height as usize,
+            isize::try_from(geometry.crop.x).map_err(Error::TryFromIntError)?,
+            isize::try_from(geometry.crop.y).map_err(Error::TryFromIntError)?,
+        )
+        .map_err(Error::Magick)?;
+    }
+
+    if geometry.resizes() {
+        if let Some(filter) = filter {
+            let _ = wand
+                .resize_image(geometry.width as usize, geometry.height as usize, filter)
+                .map_err(Error::Magick);
+        } else {
+            wand.adaptive_resize_image(geometry.width as usize, geometry.height as usize)
+                .map_err(Error::Magick)?;
+        }
+    }
+
+    if geometry.pads() {
+        let mut background = PixelWand::new();
+        background
+            .set_color(&config.background().to_string())
+            .map_err(Error::Magick)?;
+        wand.set_image_background_color(&background)
             .map_err(Error::Magick)?;
+        wand.extend_image(
+            geometry.canvas_width as usize,
+            geometry.canvas_height as usize,
+            -isize::try_from(geometry.x).map_err(Error::TryFromIntError)?,
+            -isize::try_from(geometry.y).map_err(Error::TryFromIntError)?,
+        )
+        .map_err(Error::Magick)?;
     }
 
     if let Some(quality) = config.quality() {
@@ -66,19 +172,58 @@ where
             .map_err(Error::Magick)?;
     }
 
-    if let Some(parent) = config.output_path().parent() {
-        create_dir_all(parent).map_err(Error::Io)?;
+    Ok(())
+}
+
+/// # Errors
+///
+/// Returns an error if the optimization fails.
+///
+pub fn optimize<T>(config: &T, filter: Option<magick_rust::FilterType>) -> Result<(), Error>
+where
+    T: PathAccessor + Dimensions + Quality,
+{
+    let format = super::output_format(config);
+    super::optimize_file(config, |input| {
+        optimize_bytes(input, &format, config, filter)
+    })
+}
+
+/// Works like [`optimize`] on in-memory data, the output is encoded in the given `format`, e.g. `"jpeg"`.
+///
+/// # Errors
+///
+/// Returns an error if the optimization fails.
+///
+pub fn optimize_bytes<T>(
+    input: &[u8],
+    format: &str,
+    config: &T,
+    filter: Option<magick_rust::FilterType>,
+) -> Result<Vec<u8>, Error>
+where
+    T: Dimensions + Quality,
+{
+    START.call_once(|| {
+        magick_wand_genesis();
+    });
+    let mut wand = MagickWand::new();
+    wand.read_image_blob(input).map_err(Error::Magick)?;
+
+    process(&wand, config, filter)?;
+
+    if extensions::canonical(format) == JPEG {
+        set_jpeg_options(&mut wand, config.encoder().jpeg)?;
     }
 
-    wand.write_image(&config.output_path().display().to_string())
-        .map_err(Error::Magick)
+    wand.write_image_blob(format).map_err(Error::Magick)
 }
 
 #[cfg(test)]
 mod tests {
     use magick_rust::FilterType;
 
-    use crate::{Config, ConfigBuilder};
+    use crate::{Config, ConfigBuilder, Options};
 
     #[test]
     fn magic_resize_and_auto_orient() {
@@ -128,6 +273,25 @@ mod tests {
         .unwrap();
     }
 
+    #[test]
+    fn optimize_bytes_to_jpeg() {
+        use super::*;
+
+        let contents = optimize_bytes(
+            include_bytes!("../../tests/files/orientation_test.jpg"),
+            "jpeg",
+            &Options {
+                width: Some(240),
+                height: Some(100),
+                ..Options::default()
+            },
+            Some(FilterType::Lanczos),
+        )
+        .unwrap();
+
+        assert_eq!(&contents[..3], &[0xFF, 0xD8, 0xFF]);
+    }
+
     #[test]
     fn low_quality() {
         use super::*;
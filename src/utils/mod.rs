@@ -1,8 +1,70 @@
+use std::fs::{create_dir_all, read, write};
+
+use crate::PathAccessor;
+
+pub mod animation;
+pub mod avif;
+pub mod container;
+pub mod exif;
+pub mod gif;
+#[cfg(feature = "native")]
 pub mod gifsicle;
+pub mod icc;
+#[cfg(not(feature = "native"))]
+pub mod image_webp;
+#[cfg(feature = "native")]
+pub mod libwebp;
+#[cfg(feature = "native")]
 pub mod magick;
+pub mod orientation;
 pub mod oxipng;
+pub mod png;
+pub mod raster;
+pub mod resample;
+pub mod rgba;
+pub mod smartcrop;
+pub mod ssim;
 pub mod webp;
 
+/// Reads the input file, optimizes its contents with `optimize_bytes` and writes the result to the output file,
+/// creating its directory. Every function working on paths is a wrapper of the one working on bytes.
+///
+/// # Errors
+///
+/// Returns an error if a file cannot be read or written or if `optimize_bytes` fails.
+///
+pub(crate) fn optimize_file<T, E>(
+    config: &T,
+    optimize_bytes: impl FnOnce(&[u8]) -> Result<Vec<u8>, E>,
+) -> Result<(), E>
+where
+    T: PathAccessor,
+    E: From<std::io::Error>,
+{
+    let input = read(config.input_path())?;
+    let contents = optimize_bytes(&input)?;
+
+    if let Some(parent) = config.output_path().parent() {
+        create_dir_all(parent)?;
+    }
+
+    write(config.output_path(), contents)?;
+
+    Ok(())
+}
+
+/// The format of the output file given by its extension, e.g. `"jpg"`, empty without an extension.
+pub(crate) fn output_format<T>(config: &T) -> String
+where
+    T: PathAccessor,
+{
+    config
+        .output_path()
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
 #[must_use]
 pub fn fit(width: u32, height: u32, max_width: u32, max_height: u32) -> (u32, u32) {
     let width_ratio = f64::from(max_width) / f64::from(width);
@@ -18,3 +80,15 @@ pub fn fit(width: u32, height: u32, max_width: u32, max_height: u32) -> (u32, u3
         (new_width, max_height)
     }
 }
+
+/// File names that run `touch pwned` or pass an option when given to a shell, used by the tests of every entry point.
+#[cfg(test)]
+pub(crate) const HOSTILE_FILE_NAMES: [&str; 7] = [
+    "a;touch pwned;.gif",
+    "$(touch pwned).gif",
+    "`touch pwned`.gif",
+    "a|touch pwned.gif",
+    "a\ntouch pwned.gif",
+    "-o pwned.gif",
+    "it's \"quoted\" & spaced.gif",
+];
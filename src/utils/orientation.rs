@@ -0,0 +1,67 @@
+//! Decodes images upright, like `auto_orient` of the `magick` backend does.
+//!
+//! The EXIF orientation of JPEG, PNG and WebP images is applied to the pixels,
+//! the encoders never write it back, so the output of every conversion is upright without the tag,
+//! or with an upright one when the [`Metadata`](crate::Metadata) policy keeps EXIF.
+
+use std::io::Cursor;
+
+use image::{metadata::Orientation, DynamicImage, ImageDecoder, ImageError, ImageReader};
+
+/// Decodes the image, turned upright as described by its EXIF orientation.
+///
+/// # Errors
+///
+/// Returns an error if the image cannot be decoded.
+///
+pub fn decode(input: &[u8]) -> Result<DynamicImage, ImageError> {
+    decode_with_icc(input).map(|(image, _)| image)
+}
+
+/// Decodes the image like [`decode`], with its embedded ICC profile.
+///
+/// # Errors
+///
+/// Returns an error if the image cannot be decoded.
+///
+pub fn decode_with_icc(input: &[u8]) -> Result<(DynamicImage, Option<Vec<u8>>), ImageError> {
+    let mut decoder = ImageReader::new(Cursor::new(input))
+        .with_guessed_format()?
+        .into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let icc = decoder.icc_profile()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+    Ok((image, icc))
+}
+
+/// Reads the EXIF orientation of the image, [`Orientation::NoTransforms`] without one.
+///
+/// # Errors
+///
+/// Returns an error if the image cannot be read.
+///
+pub fn orientation(input: &[u8]) -> Result<Orientation, ImageError> {
+    ImageReader::new(Cursor::new(input))
+        .with_guessed_format()?
+        .into_decoder()?
+        .orientation()
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn decode_upright() {
+        use super::*;
+
+        let input = include_bytes!("../../tests/files/orientation_test.jpg");
+        let stored = image::load_from_memory(input).unwrap();
+        let image = decode(input).unwrap();
+
+        assert_eq!(orientation(input).unwrap(), Orientation::Rotate90);
+        assert_eq!(
+            (image.width(), image.height()),
+            (stored.height(), stored.width())
+        );
+    }
+}
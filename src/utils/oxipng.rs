@@ -1,23 +1,48 @@
-use oxipng::{Options, OutFile};
+use std::num::NonZeroU8;
 
-use crate::PathAccessor;
+use oxipng::{Deflaters, Interlacing, Options, OutFile};
+
+use crate::{encoder::PngOptions, PathAccessor};
+
+/// The number of Zopfli iterations, the default of oxipng.
+const ZOPFLI_ITERATIONS: u8 = 15;
+
+fn options(png: PngOptions) -> Options {
+    let mut options = png
+        .level
+        .map_or_else(Options::default, |level| Options::from_preset(level.min(6)));
+    options.strip = oxipng::StripChunks::Safe; // Optionally, strip metadata
+    if png.interlace {
+        options.interlace = Some(Interlacing::Adam7);
+        // Interlacing makes the file bigger, keep it even when the input is smaller.
+        options.force = true;
+    }
+    if let Some(iterations) = NonZeroU8::new(ZOPFLI_ITERATIONS).filter(|_| png.zopfli) {
+        options.deflate = Deflaters::Zopfli { iterations };
+    }
+    options
+}
 
 /// # Errors
 ///
 /// Returns an error if the optimization fails.
 ///
-pub fn optimize<T>(config: &T) -> Result<(), oxipng::PngError>
+pub fn optimize<T>(config: &T, png: PngOptions) -> Result<(), oxipng::PngError>
 where
     T: PathAccessor,
 {
     let input = &config.input_path().into();
     let output = &OutFile::from_path(config.output_path().into());
-    let options = &Options {
-        strip: oxipng::StripChunks::Safe, // Optionally, strip metadata
-        ..Options::default()
-    };
 
-    oxipng::optimize(input, output, options)
+    oxipng::optimize(input, output, &options(png))
+}
+
+/// # Errors
+///
+/// Returns an error if the optimization fails.
+///
+pub fn optimize_bytes(input: &[u8], png: PngOptions) -> Result<Vec<u8>, oxipng::PngError> {
+    oxipng::optimize_from_memory(input, &options(png))
 }
 
 #[cfg(test)]
@@ -28,12 +53,46 @@ mod tests {
     fn oxipng_optimize() {
         use super::*;
 
-        optimize(&Config::new(
-            "tests/files/issue-159.png",
-            "target/issue-159.png",
-            None,
-            None,
-        ))
+        optimize(
+            &Config::new(
+                "tests/files/issue-159.png",
+                "target/issue-159.png",
+                None,
+                None,
+            ),
+            PngOptions::default(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn oxipng_optimize_bytes() {
+        use super::*;
+
+        let contents = optimize_bytes(
+            include_bytes!("../../tests/files/issue-159.png"),
+            PngOptions::default(),
+        )
         .unwrap();
+
+        assert_eq!(&contents[..4], b"\x89PNG");
+    }
+
+    #[test]
+    fn oxipng_interlace() {
+        use super::*;
+
+        let contents = optimize_bytes(
+            include_bytes!("../../tests/files/issue-159.png"),
+            PngOptions {
+                level: Some(1),
+                interlace: true,
+                zopfli: false,
+            },
+        )
+        .unwrap();
+
+        // The interlace method is the last byte of the IHDR chunk.
+        assert_eq!(contents[28], 1);
     }
 }
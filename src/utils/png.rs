@@ -0,0 +1,73 @@
+use image::{ImageError, ImageFormat};
+use std::io::Cursor;
+
+use super::{
+    container::{self, Chunks},
+    orientation,
+};
+
+/// Decodes any image supported by the `image` crate, turned upright as described by its EXIF orientation,
+/// and encodes it as PNG without any loss, keeping its ICC profile for the conversion of the next step.
+/// Only the first frame of animated images is used.
+///
+/// # Errors
+///
+/// Returns an error if the decoding or the encoding fails.
+///
+pub fn decode_to_png(input: &[u8]) -> Result<Vec<u8>, ImageError> {
+    let (image, icc) = orientation::decode_with_icc(input)?;
+    let mut output = Cursor::new(Vec::new());
+    image.write_to(&mut output, ImageFormat::Png)?;
+    if icc.is_none() {
+        return Ok(output.into_inner());
+    }
+    container::png::write(
+        &output.into_inner(),
+        &Chunks {
+            icc,
+            ..Chunks::default()
+        },
+    )
+    .map_err(|error| ImageError::IoError(std::io::Error::other(error)))
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn decode_webp_to_png() {
+        use super::*;
+
+        let contents =
+            decode_to_png(include_bytes!("../../tests/files/webp2webp_test1.webp")).unwrap();
+
+        assert_eq!(&contents[..4], b"\x89PNG");
+    }
+
+    #[test]
+    fn decode_to_png_keeps_icc() {
+        use super::*;
+        use crate::ColorProfile;
+
+        let icc = ColorProfile::DisplayP3.icc();
+        let input = container::jpeg::write(
+            include_bytes!("../../tests/files/orientation_test.jpg"),
+            &Chunks {
+                icc: Some(icc.clone()),
+                ..Chunks::default()
+            },
+        )
+        .unwrap();
+
+        let contents = decode_to_png(&input).unwrap();
+
+        assert_eq!(container::png::read(&contents).unwrap().icc, Some(icc));
+    }
+
+    #[test]
+    #[should_panic = "Unsupported"]
+    fn decode_to_png_panic() {
+        use super::*;
+
+        decode_to_png(b"not an image").unwrap();
+    }
+}
@@ -0,0 +1,235 @@
+//! Resizes still images and encodes them as JPEG, PNG or GIF,
+//! with the `magick` module when the `native` feature is enabled and with the `image` crate otherwise.
+
+use crate::{Dimensions, PathAccessor, Quality};
+
+#[cfg(feature = "native")]
+pub use super::magick::Error;
+
+/// The name of the backend, as reported in the conversion steps.
+#[cfg(feature = "native")]
+pub const BACKEND: &str = "magick";
+#[cfg(not(feature = "native"))]
+pub const BACKEND: &str = "image";
+
+/// How the image is resized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    Lanczos,
+    /// Resizes with a filter suited to the scale, used for images with few colors.
+    Adaptive,
+}
+
+impl Filter {
+    /// The name of the filter used by the backend, as reported in the conversion steps.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        if cfg!(feature = "native") {
+            match self {
+                Self::Lanczos => "lanczos",
+                Self::Adaptive => "adaptive",
+            }
+        } else {
+            "lanczos3"
+        }
+    }
+
+    #[cfg(feature = "native")]
+    fn magick(self) -> Option<magick_rust::FilterType> {
+        match self {
+            Self::Lanczos => Some(magick_rust::FilterType::Lanczos),
+            Self::Adaptive => None,
+        }
+    }
+}
+
+/// Resizes the image at the input path and writes it to the output path,
+/// in the format given by the extension of the output path.
+///
+/// # Errors
+///
+/// Returns an error if the optimization fails.
+///
+pub fn optimize<T>(config: &T, filter: Filter) -> Result<(), Error>
+where
+    T: PathAccessor + Dimensions + Quality,
+{
+    let format = super::output_format(config);
+    super::optimize_file(config, |input| {
+        optimize_bytes(input, &format, config, filter)
+    })
+}
+
+/// Works like [`optimize`] on in-memory data, the output is encoded in the given `format`, e.g. `"jpeg"`.
+///
+/// # Errors
+///
+/// Returns an error if the optimization fails.
+///
+#[cfg(feature = "native")]
+pub fn optimize_bytes<T>(
+    input: &[u8],
+    format: &str,
+    config: &T,
+    filter: Filter,
+) -> Result<Vec<u8>, Error>
+where
+    T: Dimensions + Quality,
+{
+    super::magick::optimize_bytes(input, format, config, filter.magick())
+}
+
+#[cfg(not(feature = "native"))]
+pub use image_backend::{optimize_bytes, Error};
+
+#[cfg(not(feature = "native"))]
+mod image_backend {
+    use std::io::Cursor;
+
+    use image::{
+        codecs::{gif::GifEncoder, jpeg::JpegEncoder},
+        DynamicImage, ImageFormat, RgbaImage,
+    };
+    use thiserror::Error;
+
+    use super::Filter;
+    use crate::{
+        utils::{icc, rgba},
+        Dimensions, Quality,
+    };
+
+    /// The quality of JPEG images without a configured quality, the same default as the `magick` backend.
+    const JPEG_QUALITY: u8 = 92;
+
+    /// Higher is faster with fewer colors, 10 is the default of the `gif` crate.
+    const GIF_SPEED: i32 = 10;
+
+    #[derive(Debug, Error)]
+    pub enum Error {
+        #[error("Image({0})")]
+        Image(#[from] image::ImageError),
+        #[error("Resize({0:?})")]
+        Resize(resize::Error),
+        #[error("Io({0})")]
+        Io(#[from] std::io::Error),
+        #[error("Unsupported format: {0}")]
+        UnsupportedFormat(String),
+    }
+
+    fn encode<T>(image: RgbaImage, format: ImageFormat, config: &T) -> Result<Vec<u8>, Error>
+    where
+        T: Quality,
+    {
+        let mut output = Vec::new();
+        match format {
+            ImageFormat::Jpeg => {
+                let quality = config
+                    .quality()
+                    .and_then(|quality| u8::try_from(quality.clamp(1, 100)).ok())
+                    .unwrap_or(JPEG_QUALITY);
+                // JPEG has no alpha channel, it is dropped like the `magick` backend does.
+                DynamicImage::ImageRgba8(image)
+                    .to_rgb8()
+                    .write_with_encoder(JpegEncoder::new_with_quality(&mut output, quality))?;
+            }
+            ImageFormat::Gif => {
+                let mut encoder = GifEncoder::new_with_speed(&mut output, GIF_SPEED);
+                encoder.encode_frame(image::Frame::new(image))?;
+            }
+            format => {
+                image.write_to(&mut Cursor::new(&mut output), format)?;
+            }
+        }
+        Ok(output)
+    }
+
+    fn optimize_image<T>(input: &[u8], format: ImageFormat, config: &T) -> Result<Vec<u8>, Error>
+    where
+        T: Dimensions + Quality,
+    {
+        let image = icc::decode(input, config.color_profile())?;
+        let (width, height) = image.dimensions();
+        let (pixels, width, height) =
+            rgba::transform(image.as_raw(), width, height, config).map_err(Error::Resize)?;
+        let image = RgbaImage::from_raw(width, height, pixels)
+            .ok_or(Error::Resize(resize::Error::InvalidParameters))?;
+        encode(image, format, config)
+    }
+
+    /// Works like [`optimize`](super::optimize) on in-memory data, the output is encoded in the given `format`, e.g. `"jpeg"`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the optimization fails.
+    ///
+    pub fn optimize_bytes<T>(
+        input: &[u8],
+        format: &str,
+        config: &T,
+        _filter: Filter,
+    ) -> Result<Vec<u8>, Error>
+    where
+        T: Dimensions + Quality,
+    {
+        let format = ImageFormat::from_extension(format)
+            .ok_or_else(|| Error::UnsupportedFormat(format.to_string()))?;
+        optimize_image(input, format, config)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use crate::{Config, Options};
+
+        #[test]
+        fn raster_resize_and_auto_orient() {
+            use super::*;
+
+            let contents = optimize_bytes(
+                include_bytes!("../../tests/files/orientation_test.jpg"),
+                "jpeg",
+                &Options {
+                    width: Some(240),
+                    height: Some(100),
+                    ..Options::default()
+                },
+                Filter::Lanczos,
+            )
+            .unwrap();
+
+            let image = image::load_from_memory(&contents).unwrap();
+            assert_eq!(&contents[..3], &[0xFF, 0xD8, 0xFF]);
+            assert!(image.height() > image.width());
+        }
+
+        #[test]
+        fn raster_gif() {
+            use super::*;
+            use crate::utils::raster::optimize;
+
+            optimize(
+                &Config::new(
+                    "tests/files/test1.gif",
+                    "target/raster_out.gif",
+                    Some(100),
+                    Some(100),
+                ),
+                Filter::Adaptive,
+            )
+            .unwrap();
+        }
+
+        #[test]
+        #[should_panic = "UnsupportedFormat(\"bmp2\")"]
+        fn raster_unsupported_format() {
+            use super::*;
+
+            optimize_bytes(
+                include_bytes!("../../tests/files/orientation_test.jpg"),
+                "bmp2",
+                &Options::default(),
+                Filter::Lanczos,
+            )
+            .unwrap();
+        }
+    }
+}
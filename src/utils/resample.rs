@@ -0,0 +1,204 @@
+//! Resizes RGBA pixels with premultiplied alpha in linear light, the resizing of every path without the `magick` backend.
+//!
+//! The 8-bit channels are sRGB encoded, averaging them as they are darkens fine detail and the edges between colors,
+//! and averaging the colors with straight alpha lets the color of transparent pixels, usually black,
+//! bleed into the edges of the opaque ones as dark halos. So the pixels are decoded to linear light and multiplied by
+//! their alpha, resized with Lanczos3, then divided by their alpha and encoded again. The sRGB transfer curve is
+//! used for every [`ColorProfile`](crate::ColorProfile), Display P3 has the same one.
+
+use std::sync::OnceLock;
+
+use resize::Type::Lanczos3;
+use rgb::FromSlice;
+
+fn to_linear(value: f32) -> f32 {
+    if value <= 0.040_45 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn to_srgb(value: f32) -> f32 {
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// The linear light of every 8-bit sRGB value.
+fn linear_table() -> &'static [f32; 256] {
+    static TABLE: OnceLock<[f32; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = [0.0; 256];
+        for value in 0..=u8::MAX {
+            table[usize::from(value)] = to_linear(f32::from(value) / 255.0);
+        }
+        table
+    })
+}
+
+/// The 8-bit value of a channel from 0 to 1.
+fn quantize(value: f32) -> u8 {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let value = (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    value
+}
+
+/// The linear light of the RGBA pixels multiplied by their alpha, from 0 to 1.
+fn premultiply(data: &[u8]) -> Vec<f32> {
+    let table = linear_table();
+    data.chunks_exact(4)
+        .flat_map(|pixel| {
+            let alpha = f32::from(pixel[3]) / 255.0;
+            let channel = |value: u8| table[usize::from(value)] * alpha;
+            [
+                channel(pixel[0]),
+                channel(pixel[1]),
+                channel(pixel[2]),
+                alpha,
+            ]
+        })
+        .collect()
+}
+
+/// The sRGB pixels with straight alpha of the premultiplied linear light,
+/// fully transparent pixels are black.
+fn unpremultiply(data: &[f32]) -> Vec<u8> {
+    data.chunks_exact(4)
+        .flat_map(|pixel| {
+            let alpha = pixel[3].clamp(0.0, 1.0);
+            let channel = |value: f32| {
+                if alpha > 0.0 {
+                    quantize(to_srgb((value / alpha).clamp(0.0, 1.0)))
+                } else {
+                    0
+                }
+            };
+            [
+                channel(pixel[0]),
+                channel(pixel[1]),
+                channel(pixel[2]),
+                quantize(alpha),
+            ]
+        })
+        .collect()
+}
+
+/// Resizes the RGBA pixels from their width and height to the new ones.
+///
+/// # Errors
+///
+/// Returns an error if a dimension is zero or the pixels do not match the dimensions.
+///
+pub fn resize(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    new_width: u32,
+    new_height: u32,
+) -> Result<Vec<u8>, resize::Error> {
+    let source = premultiply(data);
+    let mut dest = vec![0.0; new_width as usize * new_height as usize * 4];
+    resize::new(
+        width as usize,
+        height as usize,
+        new_width as usize,
+        new_height as usize,
+        resize::Pixel::RGBAF32,
+        Lanczos3,
+    )?
+    .resize(source.as_rgba(), dest.as_rgba_mut())?;
+    Ok(unpremultiply(&dest))
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn resize_without_dark_halo() {
+        use super::*;
+
+        // Opaque white next to transparent black, straight alpha gives gray.
+        let data = [255, 255, 255, 255, 0, 0, 0, 0];
+
+        assert_eq!(resize(&data, 2, 1, 1, 1).unwrap(), [255, 255, 255, 128]);
+    }
+
+    #[test]
+    fn resize_in_linear_light() {
+        use super::*;
+
+        // Half of the light of white, not the sRGB value halfway between black and white.
+        let data = [0, 0, 0, 255, 255, 255, 255, 255];
+
+        assert_eq!(resize(&data, 2, 1, 1, 1).unwrap(), [188, 188, 188, 255]);
+    }
+
+    #[test]
+    fn resize_round_trip() {
+        use super::*;
+
+        let data = (0..=u8::MAX)
+            .flat_map(|value| [value, 255 - value, value / 2, 255])
+            .collect::<Vec<_>>();
+
+        assert_eq!(unpremultiply(&premultiply(&data)), data);
+    }
+
+    /// Halves the image with a box filter, with straight alpha in sRGB or premultiplied in linear light.
+    fn halve(image: &image::RgbaImage, linear: bool) -> image::RgbaImage {
+        let (width, height) = (image.width() / 2, image.height() / 2);
+        image::RgbaImage::from_fn(width, height, |x, y| {
+            let pixels = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                .map(|(dx, dy)| image.get_pixel(x * 2 + dx, y * 2 + dy).0);
+            let pixels = pixels.concat();
+            let sum = |data: &[f32]| {
+                data.chunks_exact(4).fold([0.0; 4], |sum, pixel| {
+                    std::array::from_fn(|index| sum[index] + pixel[index] / 4.0)
+                })
+            };
+            if linear {
+                let average = sum(&super::premultiply(&pixels));
+                image::Rgba(super::unpremultiply(&average).try_into().unwrap())
+            } else {
+                let average = sum(&pixels
+                    .iter()
+                    .map(|&value| f32::from(value) / 255.0)
+                    .collect::<Vec<_>>());
+                image::Rgba(average.map(super::quantize))
+            }
+        })
+    }
+
+    #[test]
+    fn resize_alpha_image() {
+        use super::*;
+        use crate::utils::ssim::dssim;
+
+        let mut image = image::open("tests/files/png2avif_test2.png")
+            .unwrap()
+            .into_rgba8();
+        // Transparent black stripes, whose color bleeds into the edges of the straight average.
+        for (x, _, pixel) in image.enumerate_pixels_mut() {
+            if x / 7 % 2 == 1 {
+                *pixel = image::Rgba([0, 0, 0, 0]);
+            }
+        }
+        let (width, height) = (image.width() / 2, image.height() / 2);
+        let image = image::imageops::crop_imm(&image, 0, 0, width * 2, height * 2).to_image();
+
+        let resized = image::RgbaImage::from_raw(
+            width,
+            height,
+            resize(image.as_raw(), width * 2, height * 2, width, height).unwrap(),
+        )
+        .unwrap();
+
+        let reference = halve(&image, true);
+        let straight = halve(&image, false);
+        // The edges of the straight sRGB average are dark halos, Lanczos3 only adds some ringing.
+        assert!(dssim(&reference, &resized).unwrap() < 0.01);
+        assert!(dssim(&reference, &straight).unwrap() > 0.1);
+    }
+}
@@ -0,0 +1,187 @@
+use std::borrow::Cow;
+
+use crate::{
+    geometry::{Geometry, Rect},
+    Color, Dimensions,
+};
+
+use super::{resample, smartcrop};
+
+fn crop(data: &[u8], width: u32, rect: Rect) -> Vec<u8> {
+    let stride = width as usize * 4;
+    let row = rect.width as usize * 4;
+    let left = rect.x as usize * 4;
+    (rect.y as usize..(rect.y + rect.height) as usize)
+        .flat_map(|y| &data[y * stride + left..y * stride + left + row])
+        .copied()
+        .collect()
+}
+
+fn pad(
+    data: &[u8],
+    width: u32,
+    canvas_width: u32,
+    canvas_height: u32,
+    x: u32,
+    y: u32,
+    color: Color,
+) -> Vec<u8> {
+    let mut canvas =
+        [color.r, color.g, color.b, color.a].repeat(canvas_width as usize * canvas_height as usize);
+    let row = width as usize * 4;
+    let stride = canvas_width as usize * 4;
+    for (index, line) in data.chunks_exact(row).enumerate() {
+        let start = (y as usize + index) * stride + x as usize * 4;
+        canvas[start..start + row].copy_from_slice(line);
+    }
+    canvas
+}
+
+/// Crops, resizes and pads the RGBA pixels as described by [`smartcrop::geometry`].
+/// Returns the pixels with their width and height.
+///
+/// # Errors
+///
+/// Returns an error if the resizing fails.
+///
+pub fn transform<T>(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    config: &T,
+) -> Result<(Vec<u8>, u32, u32), resize::Error>
+where
+    T: Dimensions,
+{
+    let geometry = smartcrop::geometry(data, width, height, config);
+    apply(data, &geometry, config.background())
+}
+
+/// Crops, resizes and pads the RGBA pixels of the geometry source size as described by the geometry.
+/// The resizing is done with premultiplied alpha in linear light by [`resample::resize`].
+/// Returns the pixels with their width and height.
+///
+/// # Errors
+///
+/// Returns an error if the resizing fails.
+///
+pub fn apply(
+    data: &[u8],
+    geometry: &Geometry,
+    background: Color,
+) -> Result<(Vec<u8>, u32, u32), resize::Error> {
+    let cropped = if geometry.crops() {
+        Cow::Owned(crop(data, geometry.source_width, geometry.crop))
+    } else {
+        Cow::Borrowed(data)
+    };
+
+    let resized = if geometry.resizes() {
+        resample::resize(
+            &cropped,
+            geometry.crop.width,
+            geometry.crop.height,
+            geometry.width,
+            geometry.height,
+        )?
+    } else {
+        cropped.into_owned()
+    };
+
+    if geometry.pads() {
+        let canvas = pad(
+            &resized,
+            geometry.width,
+            geometry.canvas_width,
+            geometry.canvas_height,
+            geometry.x,
+            geometry.y,
+            background,
+        );
+        return Ok((canvas, geometry.canvas_width, geometry.canvas_height));
+    }
+
+    Ok((resized, geometry.width, geometry.height))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Options, ResizeMode};
+
+    #[test]
+    fn transform_cover() {
+        use super::*;
+
+        let data = [255, 0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255].repeat(2);
+
+        let (pixels, width, height) = transform(
+            &data,
+            3,
+            2,
+            &Options {
+                width: Some(1),
+                height: Some(2),
+                resize_mode: ResizeMode::Cover,
+                ..Options::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!((width, height), (1, 2));
+        assert_eq!(pixels, [0, 255, 0, 255].repeat(2));
+    }
+
+    #[test]
+    fn transform_cover_attention() {
+        use super::*;
+        use crate::Gravity;
+
+        let mut data = [128, 128, 128, 255].repeat(8 * 2);
+        // A saturated, high contrast column on the right.
+        for y in 0..2 {
+            let index = (y * 8 + 7) * 4;
+            data[index..index + 4].copy_from_slice(&[255, 0, 0, 255]);
+        }
+
+        let (pixels, width, height) = transform(
+            &data,
+            8,
+            2,
+            &Options {
+                width: Some(2),
+                height: Some(2),
+                resize_mode: ResizeMode::Cover,
+                gravity: Gravity::Attention,
+                ..Options::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!((width, height), (2, 2));
+        assert_eq!(&pixels[4..8], &[255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn transform_pad() {
+        use super::*;
+
+        let data = [255, 255, 255, 255].repeat(4);
+
+        let (pixels, width, height) = transform(
+            &data,
+            2,
+            2,
+            &Options {
+                width: Some(4),
+                height: Some(2),
+                resize_mode: ResizeMode::Pad,
+                background: "#ff0000".parse().unwrap(),
+                ..Options::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!((width, height), (4, 2));
+        assert_eq!(&pixels[..8], &[255, 0, 0, 255, 255, 255, 255, 255]);
+    }
+}
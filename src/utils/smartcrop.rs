@@ -0,0 +1,254 @@
+use crate::{
+    geometry::{self, Geometry, Rect},
+    Dimensions, Gravity,
+};
+
+/// The longer side of the downscaled image that is scored, large enough to find the subject
+/// and small enough to keep the analysis cheap.
+const ANALYSIS_SIZE: u32 = 256;
+
+const EDGE_WEIGHT: f64 = 1.0;
+const SATURATION_WEIGHT: f64 = 0.6;
+const SKIN_WEIGHT: f64 = 1.8;
+/// The normalized RGB direction of skin tones.
+const SKIN_COLOR: [f64; 3] = [0.78, 0.57, 0.44];
+
+struct Analysis {
+    width: usize,
+    height: usize,
+    /// Luminance of every pixel, between 0 and 1.
+    luminance: Vec<f64>,
+    /// Saturation and skin scores of every pixel, between 0 and 1.
+    saturation: Vec<f64>,
+    skin: Vec<f64>,
+    alpha: Vec<f64>,
+}
+
+/// Downscales the RGBA pixels by averaging blocks and computes the per-pixel features.
+#[allow(clippy::cast_precision_loss)]
+fn analyze(data: &[u8], width: u32, height: u32) -> Analysis {
+    let scale = (width.max(height) as usize)
+        .div_ceil(ANALYSIS_SIZE as usize)
+        .max(1);
+    let analysis_width = (width as usize).div_ceil(scale);
+    let analysis_height = (height as usize).div_ceil(scale);
+    let size = analysis_width * analysis_height;
+    let mut analysis = Analysis {
+        width: analysis_width,
+        height: analysis_height,
+        luminance: vec![0.0; size],
+        saturation: vec![0.0; size],
+        skin: vec![0.0; size],
+        alpha: vec![0.0; size],
+    };
+
+    for y in 0..analysis_height {
+        for x in 0..analysis_width {
+            let mut sum = [0.0; 4];
+            let mut count = 0.0;
+            for source_y in y * scale..((y + 1) * scale).min(height as usize) {
+                for source_x in x * scale..((x + 1) * scale).min(width as usize) {
+                    let index = (source_y * width as usize + source_x) * 4;
+                    for (channel, value) in sum.iter_mut().zip(&data[index..index + 4]) {
+                        *channel += f64::from(*value) / 255.0;
+                    }
+                    count += 1.0;
+                }
+            }
+            let [r, g, b, a] = sum.map(|channel| channel / count);
+            let index = y * analysis_width + x;
+            let luminance = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+            let max = r.max(g).max(b);
+            let min = r.min(g).min(b);
+            let saturation = if max > 0.0 { (max - min) / max } else { 0.0 };
+            // Very dark or very bright pixels carry no useful color.
+            let brightness = if (0.05..=0.9).contains(&luminance) {
+                1.0
+            } else {
+                0.0
+            };
+            let length = (r * r + g * g + b * b).sqrt();
+            let skin = if length > 0.0 {
+                let distance = [r, g, b]
+                    .iter()
+                    .zip(SKIN_COLOR)
+                    .map(|(channel, skin)| (channel / length - skin).powi(2))
+                    .sum::<f64>()
+                    .sqrt();
+                (1.0 - distance * 4.0).max(0.0)
+            } else {
+                0.0
+            };
+            analysis.luminance[index] = luminance;
+            analysis.saturation[index] = saturation * brightness;
+            analysis.skin[index] = skin * brightness;
+            analysis.alpha[index] = a;
+        }
+    }
+
+    analysis
+}
+
+/// Scores every analysis pixel by edge density, saturation and skin tone.
+fn scores(analysis: &Analysis) -> Vec<f64> {
+    let (width, height) = (analysis.width, analysis.height);
+    let luminance = |x: usize, y: usize| analysis.luminance[y * width + x];
+    let mut scores = vec![0.0; width * height];
+    for y in 0..height {
+        for x in 0..width {
+            let center = luminance(x, y);
+            let neighbors = [
+                luminance(x.saturating_sub(1), y),
+                luminance((x + 1).min(width - 1), y),
+                luminance(x, y.saturating_sub(1)),
+                luminance(x, (y + 1).min(height - 1)),
+            ];
+            let edge = neighbors
+                .iter()
+                .map(|neighbor| (center - neighbor).abs())
+                .sum::<f64>();
+            let index = y * width + x;
+            scores[index] = analysis.alpha[index]
+                * (edge * EDGE_WEIGHT
+                    + analysis.saturation[index] * SATURATION_WEIGHT
+                    + analysis.skin[index] * SKIN_WEIGHT);
+        }
+    }
+    scores
+}
+
+/// Finds the `crop_width` × `crop_height` window of the RGBA image that keeps the most interesting content.
+/// Ties are resolved towards the center of the image.
+#[must_use]
+#[allow(
+    clippy::cast_precision_loss,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss
+)]
+pub fn find(data: &[u8], width: u32, height: u32, crop_width: u32, crop_height: u32) -> Rect {
+    let analysis = analyze(data, width, height);
+    let scores = scores(&analysis);
+    let (analysis_width, analysis_height) = (analysis.width, analysis.height);
+
+    // Summed-area table, so every window is scored in constant time.
+    let mut integral = vec![0.0; (analysis_width + 1) * (analysis_height + 1)];
+    for y in 0..analysis_height {
+        let mut row = 0.0;
+        for x in 0..analysis_width {
+            row += scores[y * analysis_width + x];
+            integral[(y + 1) * (analysis_width + 1) + x + 1] =
+                integral[y * (analysis_width + 1) + x + 1] + row;
+        }
+    }
+    let sum = |x: usize, y: usize, w: usize, h: usize| {
+        let stride = analysis_width + 1;
+        integral[(y + h) * stride + x + w]
+            - integral[y * stride + x + w]
+            - integral[(y + h) * stride + x]
+            + integral[y * stride + x]
+    };
+
+    let ratio_x = analysis_width as f64 / f64::from(width);
+    let ratio_y = analysis_height as f64 / f64::from(height);
+    let window_width =
+        ((f64::from(crop_width) * ratio_x).round() as usize).clamp(1, analysis_width);
+    let window_height =
+        ((f64::from(crop_height) * ratio_y).round() as usize).clamp(1, analysis_height);
+    let free_x = analysis_width - window_width;
+    let free_y = analysis_height - window_height;
+
+    let mut best = (f64::MIN, free_x / 2, free_y / 2);
+    for y in 0..=free_y {
+        for x in 0..=free_x {
+            let distance = x.abs_diff(free_x / 2) + y.abs_diff(free_y / 2);
+            // A tiny penalty for leaving the center makes flat images crop like the center gravity.
+            let score = sum(x, y, window_width, window_height) - distance as f64 * 1e-9;
+            if score > best.0 {
+                best = (score, x, y);
+            }
+        }
+    }
+
+    let (_, x, y) = best;
+    Rect {
+        x: ((x as f64 / ratio_x).round() as u32).min(width - crop_width),
+        y: ((y as f64 / ratio_y).round() as u32).min(height - crop_height),
+        width: crop_width,
+        height: crop_height,
+    }
+}
+
+/// Computes the geometry like [`geometry::geometry`],
+/// with [`Gravity::Attention`] the crop window is chosen by the content of the RGBA pixels.
+#[must_use]
+pub fn geometry<T>(data: &[u8], width: u32, height: u32, config: &T) -> Geometry
+where
+    T: Dimensions,
+{
+    let mut geometry = geometry::geometry(width, height, config);
+    if config.gravity() == Gravity::Attention && config.focal_point().is_none() && geometry.crops()
+    {
+        geometry.crop = find(
+            data,
+            width,
+            height,
+            geometry.crop.width,
+            geometry.crop.height,
+        );
+    }
+    geometry
+}
+
+#[cfg(test)]
+mod tests {
+    /// A gray image with a colorful checkered square at the given position.
+    fn image_with_subject(width: u32, height: u32, subject_x: u32, subject_y: u32) -> Vec<u8> {
+        let mut data = [128, 128, 128, 255].repeat(width as usize * height as usize);
+        for y in subject_y..subject_y + 20 {
+            for x in subject_x..subject_x + 20 {
+                let index = (y * width + x) as usize * 4;
+                let color = if (x + y) % 2 == 0 {
+                    [220, 40, 40, 255]
+                } else {
+                    [230, 180, 140, 255]
+                };
+                data[index..index + 4].copy_from_slice(&color);
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn find_subject_on_the_right() {
+        use super::*;
+
+        let data = image_with_subject(200, 50, 170, 15);
+
+        let rect = find(&data, 200, 50, 50, 50);
+
+        assert!(rect.x >= 140, "{rect:?}");
+        assert_eq!(rect.y, 0);
+    }
+
+    #[test]
+    fn find_subject_at_the_top() {
+        use super::*;
+
+        let data = image_with_subject(60, 300, 20, 10);
+
+        let rect = find(&data, 60, 300, 60, 60);
+
+        assert!(rect.y <= 10, "{rect:?}");
+    }
+
+    #[test]
+    fn find_flat_image_center() {
+        use super::*;
+
+        let data = [128, 128, 128, 255].repeat(100 * 40);
+
+        let rect = find(&data, 100, 40, 40, 40);
+
+        assert_eq!(rect.x, 30);
+    }
+}
@@ -0,0 +1,283 @@
+//! Measures how much an encoded image differs from its source, used to pick the encoder quality
+//! for a perceptual target.
+//!
+//! The score is the SSIM of the luma with an 11x11 gaussian window, averaged over up to 5 scales halved each time
+//! with the weights of MS-SSIM (Wang et al., 2003), and reported as DSSIM (`1 / SSIM - 1`) like the `dssim` tool:
+//! 0 for identical images and growing with the difference.
+//! Transparent images are compared over black and over white and the worse score is kept,
+//! the alpha of the source is ignored when the output has none.
+
+use std::borrow::Cow;
+
+use image::RgbaImage;
+use thiserror::Error;
+
+/// The standard deviation of the gaussian window.
+const SIGMA: f32 = 1.5;
+/// The window covers the pixels up to this distance from its center.
+const RADIUS: usize = 5;
+/// The weights of the scales, from the full resolution to the coarsest.
+const SCALE_WEIGHTS: [f64; 5] = [0.0448, 0.2856, 0.3001, 0.2363, 0.1333];
+/// Stabilize the division for flat areas, `(0.01 * L)^2` and `(0.03 * L)^2` for the dynamic range `L = 1`.
+const C1: f32 = 0.0001;
+const C2: f32 = 0.0009;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Image(#[from] image::ImageError),
+    #[error("Dimensions differ: {0:?} and {1:?}")]
+    DimensionsMismatch((u32, u32), (u32, u32)),
+}
+
+/// Decodes the image data of one of the formats read by the `image` crate, turned upright like by the conversions.
+///
+/// # Errors
+///
+/// Returns an error if the data cannot be decoded.
+///
+pub fn decode(data: &[u8]) -> Result<RgbaImage, Error> {
+    Ok(super::orientation::decode(data)?.to_rgba8())
+}
+
+/// The DSSIM of the `image` compared with the `reference`, see the module documentation.
+///
+/// # Errors
+///
+/// Returns an error if the images have different dimensions.
+///
+pub fn dssim(reference: &RgbaImage, image: &RgbaImage) -> Result<f64, Error> {
+    ssim(reference, image).map(|ssim| 1.0 / ssim.max(f64::EPSILON) - 1.0)
+}
+
+/// The SSIM of the `image` compared with the `reference`, 1 for identical images.
+///
+/// # Errors
+///
+/// Returns an error if the images have different dimensions.
+///
+pub fn ssim(reference: &RgbaImage, image: &RgbaImage) -> Result<f64, Error> {
+    if reference.dimensions() != image.dimensions() {
+        return Err(Error::DimensionsMismatch(
+            reference.dimensions(),
+            image.dimensions(),
+        ));
+    }
+    let reference = comparable(reference, image);
+    Ok(backgrounds(&reference, image)
+        .iter()
+        .map(|&background| {
+            multi_scale_ssim(
+                luma(&reference, background),
+                luma(image, background),
+                reference.width() as usize,
+            )
+        })
+        .fold(f64::INFINITY, f64::min))
+}
+
+pub(crate) fn is_opaque(image: &RgbaImage) -> bool {
+    image.pixels().all(|pixel| pixel[3] == u8::MAX)
+}
+
+/// The reference without its alpha when the image has none,
+/// formats without an alpha channel keep the color of transparent pixels.
+pub(crate) fn comparable<'a>(reference: &'a RgbaImage, image: &RgbaImage) -> Cow<'a, RgbaImage> {
+    if !is_opaque(reference) && is_opaque(image) {
+        let mut opaque = reference.clone();
+        opaque.pixels_mut().for_each(|pixel| pixel[3] = u8::MAX);
+        Cow::Owned(opaque)
+    } else {
+        Cow::Borrowed(reference)
+    }
+}
+
+/// The gray levels transparent images are blended over before they are compared.
+pub(crate) fn backgrounds(reference: &RgbaImage, image: &RgbaImage) -> &'static [f32] {
+    if is_opaque(reference) && is_opaque(image) {
+        &[0.0]
+    } else {
+        &[0.0, 1.0]
+    }
+}
+
+/// The Rec. 709 luma of the pixels blended over the gray `background`, from 0 to 1.
+fn luma(image: &RgbaImage, background: f32) -> Vec<f32> {
+    image
+        .pixels()
+        .map(|pixel| {
+            let [red, green, blue, alpha] = pixel.0.map(|channel| f32::from(channel) / 255.0);
+            let luma = 0.2126 * red + 0.7152 * green + 0.0722 * blue;
+            luma * alpha + background * (1.0 - alpha)
+        })
+        .collect()
+}
+
+/// The weighted mean of the SSIM at every scale at which the planes are at least twice as big as the window.
+fn multi_scale_ssim(mut x: Vec<f32>, mut y: Vec<f32>, mut width: usize) -> f64 {
+    let mut sum = 0.0;
+    let mut weights = 0.0;
+    for weight in SCALE_WEIGHTS {
+        sum += weight * plane_ssim(&x, &y, width);
+        weights += weight;
+        let height = x.len() / width.max(1);
+        if width < 4 * RADIUS + 2 || height < 4 * RADIUS + 2 {
+            break;
+        }
+        x = downsample(&x, width);
+        y = downsample(&y, width);
+        width /= 2;
+    }
+    sum / weights
+}
+
+/// Halves the plane by averaging blocks of 2x2 pixels, dropping the last odd row and column.
+fn downsample(plane: &[f32], width: usize) -> Vec<f32> {
+    let height = plane.len() / width;
+    let mut output = Vec::with_capacity((width / 2) * (height / 2));
+    for row in (0..height - height % 2).step_by(2) {
+        for column in (0..width - width % 2).step_by(2) {
+            let index = row * width + column;
+            let sum =
+                plane[index] + plane[index + 1] + plane[index + width] + plane[index + width + 1];
+            output.push(sum / 4.0);
+        }
+    }
+    output
+}
+
+/// The mean SSIM of two planes of the same dimensions.
+#[allow(clippy::similar_names)]
+fn plane_ssim(x: &[f32], y: &[f32], width: usize) -> f64 {
+    if x.is_empty() {
+        return 1.0;
+    }
+    let local_mean = |plane: &[f32]| gaussian_blur(plane, width);
+    let mean_x = local_mean(x);
+    let mean_y = local_mean(y);
+    let mean_x_squared = local_mean(&x.iter().map(|x| x * x).collect::<Vec<_>>());
+    let mean_y_squared = local_mean(&y.iter().map(|y| y * y).collect::<Vec<_>>());
+    let mean_product = local_mean(&x.iter().zip(y).map(|(x, y)| x * y).collect::<Vec<_>>());
+
+    let sum: f64 = (0..x.len())
+        .map(|index| {
+            let (mx, my) = (mean_x[index], mean_y[index]);
+            let variance_x = mean_x_squared[index] - mx * mx;
+            let variance_y = mean_y_squared[index] - my * my;
+            let covariance = mean_product[index] - mx * my;
+            f64::from(
+                ((2.0 * mx * my + C1) * (2.0 * covariance + C2))
+                    / ((mx * mx + my * my + C1) * (variance_x + variance_y + C2)),
+            )
+        })
+        .sum();
+    #[allow(clippy::cast_precision_loss)]
+    let len = x.len() as f64;
+    sum / len
+}
+
+/// The normalized weights of the gaussian window, from the center outwards.
+fn kernel() -> [f32; RADIUS + 1] {
+    let mut kernel = [0.0; RADIUS + 1];
+    for (distance, weight) in kernel.iter_mut().enumerate() {
+        #[allow(clippy::cast_precision_loss)]
+        let distance = distance as f32;
+        *weight = (-distance * distance / (2.0 * SIGMA * SIGMA)).exp();
+    }
+    let sum = kernel[0] + 2.0 * kernel[1..].iter().sum::<f32>();
+    kernel.map(|weight| weight / sum)
+}
+
+/// The local means of the plane, weighted by the gaussian window.
+pub(crate) fn gaussian_blur(plane: &[f32], width: usize) -> Vec<f32> {
+    blur(plane, width, &kernel())
+}
+
+/// Blurs the plane horizontally and then vertically, repeating the pixels at the edges.
+fn blur(plane: &[f32], width: usize, kernel: &[f32; RADIUS + 1]) -> Vec<f32> {
+    let height = plane.len() / width;
+    let pass = |plane: &[f32], step: usize, len: usize, position: &dyn Fn(usize) -> usize| {
+        (0..plane.len())
+            .map(|index| {
+                let center = position(index);
+                let origin = index - center * step;
+                kernel
+                    .iter()
+                    .enumerate()
+                    .map(|(distance, weight)| {
+                        let before = center.saturating_sub(distance);
+                        let after = (center + distance).min(len - 1);
+                        let pixels = if distance == 0 {
+                            plane[index]
+                        } else {
+                            plane[origin + before * step] + plane[origin + after * step]
+                        };
+                        weight * pixels
+                    })
+                    .sum()
+            })
+            .collect::<Vec<f32>>()
+    };
+    let horizontal = pass(plane, 1, width, &|index| index % width);
+    pass(&horizontal, width, height, &|index| index / width)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+
+    fn gradient() -> RgbaImage {
+        #[allow(clippy::cast_possible_truncation)]
+        RgbaImage::from_fn(64, 48, |x, y| {
+            Rgba([(x * 4) as u8, (y * 5) as u8, ((x + y) * 2) as u8, 255])
+        })
+    }
+
+    #[test]
+    fn dssim_identical() {
+        use super::*;
+
+        let image = gradient();
+
+        assert!(dssim(&image, &image).unwrap().abs() < 1e-6);
+    }
+
+    #[test]
+    fn dssim_grows_with_noise() {
+        use super::*;
+
+        let reference = gradient();
+        let noisy = |amplitude: u8| {
+            let mut image = reference.clone();
+            for (index, pixel) in image.pixels_mut().enumerate() {
+                let offset = if index % 2 == 0 { amplitude } else { 0 };
+                pixel.0[1] = pixel.0[1].saturating_add(offset);
+            }
+            image
+        };
+
+        let low = dssim(&reference, &noisy(4)).unwrap();
+        let high = dssim(&reference, &noisy(32)).unwrap();
+
+        assert!(low > 0.0);
+        assert!(high > low);
+    }
+
+    #[test]
+    fn dssim_transparent_pixels() {
+        use super::*;
+
+        let reference = RgbaImage::from_pixel(16, 16, Rgba([255, 0, 0, 0]));
+        let image = RgbaImage::from_pixel(16, 16, Rgba([0, 0, 255, 0]));
+
+        assert!(dssim(&reference, &image).unwrap().abs() < 1e-6);
+    }
+
+    #[test]
+    #[should_panic = "DimensionsMismatch((64, 48), (48, 64))"]
+    fn dssim_dimensions_mismatch_panic() {
+        use super::*;
+
+        dssim(&gradient(), &RgbaImage::new(48, 64)).unwrap();
+    }
+}
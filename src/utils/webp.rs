@@ -1,114 +1,71 @@
-use std::{
-    fs::{create_dir_all, write},
-    process::Command,
-};
 use thiserror::Error;
 
 use crate::{Dimensions, PathAccessor, Quality};
-use image::{io::Reader, GenericImageView};
-use libwebp_sys::{
-    VP8StatusCode, WebPConfig, WebPEncode, WebPEncodingError, WebPMemoryWrite, WebPMemoryWriter,
-    WebPMemoryWriterClear, WebPMemoryWriterInit, WebPPicture, WebPPictureFree,
-    WebPPictureImportRGBA, WebPPictureRescale, WebPValidateConfig,
-};
 
-use super::fit;
+#[cfg(not(feature = "native"))]
+use super::image_webp as codec;
+#[cfg(feature = "native")]
+use super::libwebp::{self as codec, LibWebPError};
+use super::{
+    animation::{self, Animation},
+    gif, icc, rgba,
+};
 
-pub struct RGBAImage {
-    pub data: *const u8,
-    pub width: u32,
-    pub height: u32,
-}
+/// The name of the backend, as reported in the conversion steps.
+#[cfg(feature = "native")]
+pub const BACKEND: &str = "libwebp";
+#[cfg(not(feature = "native"))]
+pub const BACKEND: &str = "image-webp";
 
 #[derive(Debug, Error)]
-pub enum LibWebPError {
-    #[error("Failed to initialize WebP config")]
-    ConfigInit(()),
-    #[error("Failed to validate WebP config")]
-    ConfigValidate,
-    #[error("Failed to initialize WebP picture")]
-    Picture(()),
-    #[error("Failed to encode WebP image: {0:?}")]
-    Encoding(WebPEncodingError),
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Image(#[from] image::ImageError),
+    #[cfg(feature = "native")]
     #[error(transparent)]
-    TryFromIntError(#[from] std::num::TryFromIntError),
+    LibWebPError(#[from] LibWebPError),
+    #[error("Resize({0:?})")]
+    Resize(resize::Error),
+    #[error(transparent)]
+    Gif(#[from] gif::Error),
 }
 
-fn rgba_to_webp<T>(image: &RGBAImage, config: &T) -> Result<Vec<u8>, LibWebPError>
+/// Animated WebP inputs keep all their frames, their durations and the loop count, every other input
+/// is encoded as a still image converted to the color profile of the config.
+/// The frames are composited on the canvas before they are resized, so the blend and dispose methods
+/// of the input are applied rather than copied, the encoder chooses its own for the output frames.
+/// Without the `native` feature the output is always lossless.
+///
+/// # Errors
+///
+/// Returns an error if the optimization fails.
+///
+pub fn optimize_bytes<T>(input: &[u8], config: &T) -> Result<Vec<u8>, Error>
 where
     T: Dimensions + Quality,
 {
-    let (target_width, target_height) = fit(
-        image.width,
-        image.height,
-        config.width().unwrap_or(image.width),
-        config.height().unwrap_or(image.height),
-    );
-
-    let mut webp_config = WebPConfig::new().map_err(LibWebPError::ConfigInit)?;
-
-    #[allow(clippy::cast_precision_loss)]
-    if let Some(quality) = config.quality() {
-        webp_config.lossless = 0;
-        webp_config.alpha_compression = 1;
-        webp_config.quality = quality as f32;
-    } else {
-        webp_config.lossless = 1;
-        webp_config.alpha_compression = 1;
-        webp_config.quality = 100.0;
+    if codec::is_animation(input) {
+        let animation = codec::decode_animation(input)?;
+        return encode_animation(animation, config);
     }
 
-    let mut picture = WebPPicture::new().map_err(LibWebPError::Picture)?;
-    picture.use_argb = 1;
-    picture.width = i32::try_from(image.width).map_err(LibWebPError::TryFromIntError)?;
-    picture.height = i32::try_from(image.height).map_err(LibWebPError::TryFromIntError)?;
-
-    let mut ww: ::core::mem::MaybeUninit<WebPMemoryWriter> = ::core::mem::MaybeUninit::uninit();
-    picture.writer = Some(WebPMemoryWrite);
-    picture.custom_ptr = ww.as_mut_ptr().cast::<std::ffi::c_void>();
-
-    unsafe {
-        if WebPValidateConfig(&webp_config) == 0 {
-            return Err(LibWebPError::ConfigValidate);
-        }
-
-        let memory_writer_ptr = ww.as_mut_ptr();
-
-        WebPMemoryWriterInit(memory_writer_ptr);
-
-        let rgba_stride = i32::try_from(image.width * 4).map_err(LibWebPError::TryFromIntError)?;
-
-        WebPPictureImportRGBA(&mut picture, image.data, rgba_stride);
+    let input_image = icc::decode(input, config.color_profile()).map_err(Error::Image)?;
 
-        let target_width = i32::try_from(target_width).map_err(LibWebPError::TryFromIntError)?;
-        let target_height = i32::try_from(target_height).map_err(LibWebPError::TryFromIntError)?;
+    let (width, height) = input_image.dimensions();
+    let (pixels, width, height) =
+        rgba::transform(input_image.as_raw(), width, height, config).map_err(Error::Resize)?;
 
-        WebPPictureRescale(&mut picture, target_width, target_height);
-
-        let encode_result = WebPEncode(&webp_config, &mut picture);
-
-        if encode_result == VP8StatusCode::VP8_STATUS_OK as i32 {
-            return Err(LibWebPError::Encoding(picture.error_code));
-        }
-
-        let memory_writer = ww.assume_init();
-        let contents = std::slice::from_raw_parts(memory_writer.mem, memory_writer.size).to_vec();
-
-        WebPPictureFree(&mut picture);
-        WebPMemoryWriterClear(memory_writer_ptr);
-
-        Ok(contents)
-    }
+    Ok(codec::encode(&pixels, width, height, config)?)
 }
 
-#[derive(Debug, Error)]
-pub enum Error {
-    #[error(transparent)]
-    Io(#[from] std::io::Error),
-    #[error(transparent)]
-    Image(#[from] image::ImageError),
-    #[error(transparent)]
-    LibWebPError(#[from] LibWebPError),
+fn encode_animation<T>(animation: Animation, config: &T) -> Result<Vec<u8>, Error>
+where
+    T: Dimensions + Quality,
+{
+    let animation = animation::transform(animation, config).map_err(Error::Resize)?;
+    Ok(codec::encode_animation(&animation, config)?)
 }
 
 /// # Errors
@@ -119,69 +76,37 @@ pub fn optimize<T>(config: &T) -> Result<(), Error>
 where
     T: PathAccessor + Dimensions + Quality,
 {
-    let input_image = Reader::open(config.input_path())
-        .map_err(Error::Io)?
-        .with_guessed_format()
-        .map_err(Error::Io)?
-        .decode()
-        .map_err(Error::Image)?;
-
-    let dimensions = input_image.dimensions();
-    let dimension_width = dimensions.0;
-    let dimension_height = dimensions.1;
-    let rgba_image = input_image.into_rgba8();
-
-    let rgba_image = RGBAImage {
-        data: rgba_image.as_ptr(),
-        width: dimension_width,
-        height: dimension_height,
-    };
-
-    let contents = rgba_to_webp(&rgba_image, config).map_err(Error::LibWebPError)?;
-
-    if let Some(parent) = config.output_path().parent() {
-        create_dir_all(parent).map_err(Error::Io)?;
-    }
-
-    write(config.output_path(), contents).map_err(Error::Io)?;
-
-    Ok(())
+    super::optimize_file(config, |input| optimize_bytes(input, config))
 }
 
-fn process_exit_code(code: Option<i32>) -> std::result::Result<(), std::io::Error> {
-    match code {
-        Some(0) => Ok(()),
-        Some(_) | None => Err(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "gif2webp failed",
-        )),
-    }
+/// Encodes every frame of the GIF into an animated WebP, keeping the frame timings and the loop count.
+///
+/// # Errors
+///
+/// Returns an error if the GIF cannot be decoded or the encoding fails.
+///
+pub fn optimize_animation_bytes<T>(input: &[u8], config: &T) -> Result<Vec<u8>, Error>
+where
+    T: Dimensions + Quality,
+{
+    let animation = gif::animation(input).map_err(Error::Gif)?;
+    encode_animation(animation, config)
 }
 
 /// # Errors
 ///
-/// Returns an error if the command gif2webp fails.
+/// Returns an error if the GIF cannot be read or the encoding fails.
 ///
-pub fn optimize_gif<T>(config: &T) -> Result<(), std::io::Error>
+pub fn optimize_animation<T>(config: &T) -> Result<(), Error>
 where
-    T: PathAccessor,
+    T: PathAccessor + Dimensions + Quality,
 {
-    let input_path = config.input_path().display();
-    let output_path = config.output_path().display();
-    let command = format!("gif2webp -o {output_path} -q 75 -m 6 -mt -v {input_path}");
-    let output = Command::new("sh").arg("-c").arg(command).output()?;
-    println!("status: {}", output.status);
-    println!("stdout: {}", String::from_utf8_lossy(&output.stdout));
-    println!("stderr: {}", String::from_utf8_lossy(&output.stderr));
-
-    let code = output.status.code();
-
-    process_exit_code(code)
+    super::optimize_file(config, |input| optimize_animation_bytes(input, config))
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{core::PathIO, Config, ConfigBuilder};
+    use crate::{Config, ConfigBuilder, Options};
 
     #[test]
     fn webp_optimize_png_to_webp() {
@@ -210,47 +135,229 @@ mod tests {
     }
 
     #[test]
-    fn webp_optimize_gif_to_webp_2() {
+    fn webp_optimize_animation() {
         use super::*;
 
-        optimize_gif(&PathIO::new(
-            &"tests/files/test1.gif".into(),
-            &"target/webp_gif_test1.webp".into(),
+        optimize_animation(&Config::new(
+            "tests/files/test1.gif",
+            "target/webp_animation_test1.webp",
+            Some(100),
+            Some(100),
         ))
         .unwrap();
     }
 
     #[test]
-    #[should_panic = "Custom { kind: Other, error: \"gif2webp failed\" }"]
-    fn webp_optimize_gif_to_webp_panic() {
+    fn webp_optimize_bytes() {
         use super::*;
 
-        optimize_gif(&PathIO::new(
-            &"tests/files/not_existing.gif".into(),
-            &"target/webp_gif_test1.webp".into(),
-        ))
+        let contents = optimize_bytes(
+            include_bytes!("../../tests/files/issue-159.png"),
+            &Options {
+                width: Some(100),
+                height: Some(100),
+                ..Options::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(&contents[8..12], b"WEBP");
+    }
+
+    #[test]
+    fn webp_optimize_animation_bytes() {
+        use super::*;
+        use image::{codecs::webp::WebPDecoder, AnimationDecoder, ImageDecoder};
+        use std::{io::Cursor, time::Duration};
+
+        let input = include_bytes!("../../tests/files/gif2webp_test1.gif");
+        let animation = gif::animation(input).unwrap();
+
+        let contents = optimize_animation_bytes(
+            input,
+            &Options {
+                width: Some(100),
+                height: Some(100),
+                ..Options::default()
+            },
+        )
         .unwrap();
+
+        let decoder = WebPDecoder::new(Cursor::new(&contents)).unwrap();
+        assert!(decoder.has_animation());
+        assert_eq!(decoder.dimensions().0.max(decoder.dimensions().1), 100);
+        let durations = decoder
+            .into_frames()
+            .map(|frame| Duration::from(frame.unwrap().delay()).as_millis())
+            .collect::<Vec<_>>();
+        let expected = animation
+            .frames
+            .iter()
+            .map(|frame| u128::from(frame.duration))
+            .collect::<Vec<_>>();
+        assert_eq!(durations, expected);
+
+        // The loop count follows the background color in the ANIM chunk.
+        let anim = contents
+            .windows(4)
+            .position(|chunk| chunk == b"ANIM")
+            .unwrap();
+        let loop_count = u16::from_le_bytes([contents[anim + 12], contents[anim + 13]]);
+        assert_eq!(loop_count, animation.loop_count);
+    }
+
+    #[test]
+    fn webp_optimize_bytes_animated() {
+        use super::*;
+
+        let durations = |animation: &Animation| {
+            animation
+                .frames
+                .iter()
+                .map(|frame| frame.duration)
+                .collect::<Vec<_>>()
+        };
+        let animated = optimize_animation_bytes(
+            include_bytes!("../../tests/files/gif2webp_test1.gif"),
+            &Options::default(),
+        )
+        .unwrap();
+        let source = codec::decode_animation(&animated).unwrap();
+
+        let contents = optimize_bytes(
+            &animated,
+            &Options {
+                width: Some(50),
+                quality: Some(80),
+                ..Options::default()
+            },
+        )
+        .unwrap();
+
+        assert!(codec::is_animation(&contents));
+        let animation = codec::decode_animation(&contents).unwrap();
+        assert_eq!(animation.width, 50);
+        assert_eq!(durations(&animation), durations(&source));
+        assert_eq!(animation.loop_count, source.loop_count);
+    }
+
+    /// A 4x4 animation of a red frame, a half transparent blue square in the bottom right corner
+    /// replacing the red without blending and disposed to the background,
+    /// and a green square in the top left corner.
+    fn blend_dispose_animation() -> Vec<u8> {
+        use image::{codecs::webp::WebPEncoder, ExtendedColorType};
+
+        let chunk = |fourcc: &[u8], payload: &[u8]| {
+            let mut chunk = fourcc.to_vec();
+            chunk.extend_from_slice(&u32::try_from(payload.len()).unwrap().to_le_bytes());
+            chunk.extend_from_slice(payload);
+            if payload.len() % 2 == 1 {
+                chunk.push(0);
+            }
+            chunk
+        };
+        let u24 = |value: u32| value.to_le_bytes()[..3].to_vec();
+        let frame = |color: [u8; 4], x: u32, y: u32, size: u32, flags: u8| {
+            let mut still = Vec::new();
+            WebPEncoder::new_lossless(&mut still)
+                .encode(
+                    &color.repeat((size * size) as usize),
+                    size,
+                    size,
+                    ExtendedColorType::Rgba8,
+                )
+                .unwrap();
+            let mut anmf = [
+                u24(x / 2),
+                u24(y / 2),
+                u24(size - 1),
+                u24(size - 1),
+                u24(100),
+            ]
+            .concat();
+            anmf.push(flags);
+            // The VP8L chunk follows the RIFF header of the still image.
+            anmf.extend_from_slice(&still[12..]);
+            chunk(b"ANMF", &anmf)
+        };
+
+        let body = [
+            b"WEBP".to_vec(),
+            chunk(
+                b"VP8X",
+                &[vec![0x10 | 0x02, 0, 0, 0], u24(3), u24(3)].concat(),
+            ),
+            // A transparent background, played forever.
+            chunk(b"ANIM", &[0; 6]),
+            frame([255, 0, 0, 255], 0, 0, 4, 0),
+            // Without blending and disposed to the background.
+            frame([0, 0, 255, 128], 2, 2, 2, 0b11),
+            frame([0, 255, 0, 255], 0, 0, 2, 0),
+        ]
+        .concat();
+        chunk(b"RIFF", &body)
     }
 
     #[test]
-    #[should_panic = "Custom { kind: Other, error: \"gif2webp failed\" }"]
-    fn process_exit_code_terminated_by_signal_panic() {
+    fn webp_optimize_bytes_blend_dispose() {
         use super::*;
 
-        process_exit_code(None).unwrap();
+        let input = blend_dispose_animation();
+        let source = codec::decode_animation(&input).unwrap();
+        let pixel = |animation: &Animation, frame: usize, x: usize, y: usize| {
+            let index = (y * animation.width as usize + x) * 4;
+            <[u8; 4]>::try_from(&animation.frames[frame].data[index..index + 4]).unwrap()
+        };
+        assert_eq!(pixel(&source, 1, 3, 3), [0, 0, 255, 128]);
+        assert_eq!(pixel(&source, 2, 3, 3)[3], 0);
+
+        let contents = optimize_bytes(&input, &Options::default()).unwrap();
+
+        let animation = codec::decode_animation(&contents).unwrap();
+        assert_eq!(animation.frames.len(), 3);
+        for (frame, source_frame) in animation.frames.iter().zip(&source.frames) {
+            assert_eq!(frame.duration, source_frame.duration);
+            for (pixel, source_pixel) in frame
+                .data
+                .chunks_exact(4)
+                .zip(source_frame.data.chunks_exact(4))
+            {
+                // The colors of transparent pixels are not kept.
+                if source_pixel[3] == 0 {
+                    assert_eq!(pixel[3], 0);
+                } else {
+                    assert_eq!(pixel, source_pixel);
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic = "Io(Os { code: 2, kind: NotFound"]
+    fn webp_optimize_animation_panic() {
+        use super::*;
+
+        optimize_animation(&Config::new(
+            "tests/files/not_existing.gif",
+            "target/webp_animation_test1.webp",
+            Some(100),
+            None,
+        ))
+        .unwrap();
     }
 
     #[test]
     fn low_quality() {
         use super::*;
 
-        optimize(&ConfigBuilder::default()
-            .input_path("tests/files/issue-159.png")
-            .output_path("target/webp_low_quality.webp")
-            .width(Some(100))
-            .quality(Some(10))
-            .build()
-            .unwrap()
+        optimize(
+            &ConfigBuilder::default()
+                .input_path("tests/files/issue-159.png")
+                .output_path("target/webp_low_quality.webp")
+                .width(Some(100))
+                .quality(Some(10))
+                .build()
+                .unwrap(),
         )
         .unwrap();
     }